@@ -41,11 +41,11 @@ pub async fn get_cur_pod_images_info() -> Result<String, Error> {
 
         // Concat all pod quote data into one String.
         let pod_image_id_data = pod_data_array.join(SEPARATOR);
-        return Ok(pod_image_id_data);
+        Ok(pod_image_id_data)
     } else {
         println!("Pod {pod_name} in {namespace} not found.");
         let error_message = format!("Pod '{}' in '{}' not found.", pod_name, namespace);
-        return Err(anyhow!(error_message));
+        Err(anyhow!(error_message))
     }
 }
 
@@ -54,7 +54,5 @@ pub fn sha256_hash(input: &str) -> String {
     let input_bytes = input.as_bytes();
 
     // Calculate the SHA-256 hash
-    let hash = crypto_hash::hex_digest(crypto_hash::Algorithm::SHA256, input_bytes);
-
-    hash
+    crypto_hash::hex_digest(crypto_hash::Algorithm::SHA256, input_bytes)
 }
@@ -7,34 +7,35 @@ use anyhow::*;
 use clap::Parser;
 use core::result::Result::Ok;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Request as HyperRequest, Response as HyperResponse, Body, Server as HyperServer};
+use hyper::{Body, Request as HyperRequest, Response as HyperResponse, Server as HyperServer};
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 pub mod kube;
 pub mod tee;
 use tee::*;
 
 // A http server for provide the current pod quote data
-#[derive(Copy, Clone)]
 pub struct PerPodQuoteServer {
     sock_address: SocketAddr,
-    local_tee: tee::TeeType,
+    local_tee: Arc<dyn TeeBackend>,
 }
 
 impl PerPodQuoteServer {
-    pub fn new(sock_address: SocketAddr, local_tee: tee::TeeType) -> Self {
+    pub fn new(sock_address: SocketAddr, local_tee: Box<dyn TeeBackend>) -> Self {
         PerPodQuoteServer {
             sock_address,
-            local_tee,
+            local_tee: Arc::from(local_tee),
         }
     }
 
     pub async fn start(&self) -> Result<(), hyper::Error> {
-        let local_tee = self.local_tee;
-        let make_svc = make_service_fn(|_conn| {
+        let local_tee = self.local_tee.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let local_tee = local_tee.clone();
             let service = service_fn(move |req| {
                 // Route request to the appropriate handler
-                Self::handle_request(local_tee, req)
+                Self::handle_request(local_tee.clone(), req)
             });
             async move { Ok::<_, hyper::Error>(service) }
         });
@@ -47,7 +48,9 @@ impl PerPodQuoteServer {
     }
 
     // generate current pod quote based on its all containers' imageIDs
-    async fn get_current_pod_quote(local_tee: tee::TeeType) -> Result<String, anyhow::Error> {
+    async fn get_current_pod_quote(
+        local_tee: Arc<dyn TeeBackend>,
+    ) -> Result<String, anyhow::Error> {
         // Handle the "/quote" route
         // Create an instance of your custom kube client
         let pod_data = kube::get_cur_pod_images_info();
@@ -55,13 +58,11 @@ impl PerPodQuoteServer {
             Ok(report_data) => {
                 let report_data_clone = report_data.clone();
                 let hash_report_data = kube::sha256_hash(&report_data_clone);
-                let quote_data = get_quote(
-                    local_tee,
-                    hash_report_data.clone(),
+                get_quote(
+                    local_tee.as_ref(),
+                    Some(hash_report_data.clone()),
                     hash_report_data.clone(),
                 )
-                .unwrap();
-                Ok(quote_data)
             }
             Err(error) => Err(anyhow!(
                 "There was a problem when get current pod images information: {:?}",
@@ -71,8 +72,8 @@ impl PerPodQuoteServer {
     }
 
     async fn handle_request(
-        local_tee: tee::TeeType,
-        req: HyperRequest<Body>
+        local_tee: Arc<dyn TeeBackend>,
+        req: HyperRequest<Body>,
     ) -> Result<HyperResponse<Body>, hyper::Error> {
         match req.uri().path() {
             "/quote" => {
@@ -113,17 +114,20 @@ struct Cli {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let http_addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    // Create the http server tokio task for fetching quote with current pod image IDs
-    let _ = tokio::spawn(async move {
+    // Create the http server tokio task for fetching quote with current pod image IDs,
+    // and serve until it stops
+    tokio::spawn(async move {
         let http_server = PerPodQuoteServer::new(http_addr, {
-            match tee::get_tee_type() {
-                tee::TeeType::PLAIN => panic!("Not found any TEE device!"),
-                t => t,
+            match TeeRegistry::default().detect() {
+                Err(e) => panic!("Fail to detect TEE device: {}", e),
+                Ok(None) => panic!("Not found any TEE device!"),
+                Ok(Some(t)) => t,
             }
         });
         if let Err(err) = http_server.start().await {
             eprintln!("HTTP server error: {}", err);
         }
-    });
+    })
+    .await?;
     Ok(())
 }
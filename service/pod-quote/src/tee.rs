@@ -7,37 +7,203 @@ use anyhow::*;
 use sha2::{Digest, Sha512};
 use std::path::Path;
 use std::result::Result::Ok;
+use tdx_attest::eventlog::TPM_ALG_SHA384;
+use tdx_attest::TdReport;
 
-#[derive(Debug, Copy, Clone)]
+const CCEL_DATA_PATH: &str = "/sys/firmware/acpi/tables/data/CCEL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TeeType {
     TDX,
     SEV,
     TPM,
-    PLAIN,
 }
 
-pub fn get_tee_type() -> TeeType {
-    if Path::new("/dev/tpm0").exists() {
-        TeeType::TPM
-    } else if Path::new("/dev/tdx-guest").exists()
-        || Path::new("/dev/tdx-attest").exists()
-        || Path::new("/dev/tdx_guest").exists()
-    {
-        if Path::new("/dev/tdx-attest").exists() {
-            panic!("[get_tee_type]: Deprecated device node /dev/tdx-attest, please upgrade to use /dev/tdx-guest or /dev/tdx_guest");
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeasurementRegister {
+    pub name: String,      // MRTD, RTMR0-3, PCR0-23, ...
+    pub algorithm_id: u16, // TPM_ALG_ID of the digest
+    pub digest: Vec<u8>,
+}
+
+//a TEE the pod quote server can serve evidence from, registered in a TeeRegistry.
+//it mirrors the backends of the quote server, which is a binary crate and cannot be depended
+//on, device access and TD report parsing are shared through tdx_attest
+pub trait TeeBackend: Send + Sync {
+    fn tee_type(&self) -> TeeType;
+
+    //whether the TEE is present on this platform
+    fn detect(&self) -> Result<bool>;
+
+    //evidence for a remote verifier bound to the report data, e.g. the TDX quote
+    fn get_evidence(&self, report_data: &[u8; 64]) -> Result<Vec<u8>>;
+
+    //report of the TEE only verifiable on this platform, e.g. the TD report
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>>;
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>>;
+
+    //raw TCG event log of the boot chain
+    fn get_event_log(&self) -> Result<Vec<u8>>;
+}
+
+//the TEE backends known to the pod quote server, in detection priority
+pub struct TeeRegistry {
+    backends: Vec<Box<dyn TeeBackend>>,
+}
+
+impl Default for TeeRegistry {
+    fn default() -> Self {
+        //a vTPM can coexist with the confidential VM, it does not provide evidence yet
+        TeeRegistry::new()
+            .with_backend(Box::new(TdxBackend {}))
+            .with_backend(Box::new(SevBackend {}))
+            .with_backend(Box::new(TpmBackend {}))
+    }
+}
+
+impl TeeRegistry {
+    pub fn new() -> Self {
+        TeeRegistry {
+            backends: Vec::new(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: Box<dyn TeeBackend>) -> Self {
+        self.backends.push(backend);
+        self
+    }
+
+    //the first registered backend whose TEE is present, None on plain platforms
+    pub fn detect(self) -> Result<Option<Box<dyn TeeBackend>>> {
+        for backend in self.backends {
+            if backend.detect()? {
+                return Ok(Some(backend));
+            }
         }
+        Ok(None)
+    }
+}
+
+pub struct TdxBackend {}
+
+impl TeeBackend for TdxBackend {
+    fn tee_type(&self) -> TeeType {
         TeeType::TDX
-    } else if Path::new("/dev/sev-guest").exists() || Path::new("/dev/sev").exists() {
+    }
+
+    fn detect(&self) -> Result<bool> {
+        if Path::new("/dev/tdx-attest").exists() {
+            return Err(anyhow!("[detect]: Deprecated device node /dev/tdx-attest, please upgrade to use /dev/tdx-guest or /dev/tdx_guest"));
+        }
+        Ok(Path::new("/dev/tdx-guest").exists() || Path::new("/dev/tdx_guest").exists())
+    }
+
+    fn get_evidence(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        tdx_attest::get_tdx_quote(base64::encode(report_data))
+            .map_err(|e| anyhow!("[get_evidence] Fail to get TDX quote: {:?}", e))
+    }
+
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        tdx_attest::get_td_report(base64::encode(report_data))
+            .map_err(|e| anyhow!("[get_report] Fail to get TD report: {:?}", e))
+    }
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+        let report = match TdReport::from_bytes(&self.get_report(&[0; 64])?) {
+            Err(e) => {
+                return Err(anyhow!(
+                    "[get_measurements] Fail to parse TD report: {:?}",
+                    e
+                ))
+            }
+            Ok(r) => r,
+        };
+        let td_info = report.td_info;
+
+        let mut registers = vec![("MRTD".to_string(), td_info.mrtd)];
+        for (i, rtmr) in td_info.rtmrs.iter().enumerate() {
+            registers.push((format!("RTMR{}", i), rtmr.0));
+        }
+        registers.push(("MRCONFIGID".to_string(), td_info.mrconfigid));
+        registers.push(("MROWNER".to_string(), td_info.mrowner));
+        registers.push(("MROWNERCONFIG".to_string(), td_info.mrownerconfig));
+        Ok(registers
+            .into_iter()
+            .map(|(name, digest)| MeasurementRegister {
+                name,
+                algorithm_id: TPM_ALG_SHA384,
+                digest: digest.to_vec(),
+            })
+            .collect())
+    }
+
+    fn get_event_log(&self) -> Result<Vec<u8>> {
+        std::fs::read(CCEL_DATA_PATH)
+            .map_err(|e| anyhow!("[get_event_log] Fail to read {}: {:?}", CCEL_DATA_PATH, e))
+    }
+}
+
+pub struct TpmBackend {}
+
+impl TeeBackend for TpmBackend {
+    fn tee_type(&self) -> TeeType {
+        TeeType::TPM
+    }
+
+    fn detect(&self) -> Result<bool> {
+        Ok(Path::new("/dev/tpm0").exists())
+    }
+
+    fn get_evidence(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        Err(anyhow!("TPM to be supported!"))
+    }
+
+    fn get_report(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        Err(anyhow!("TPM to be supported!"))
+    }
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+        Err(anyhow!("TPM to be supported!"))
+    }
+
+    fn get_event_log(&self) -> Result<Vec<u8>> {
+        Err(anyhow!("TPM to be supported!"))
+    }
+}
+
+pub struct SevBackend {}
+
+impl TeeBackend for SevBackend {
+    fn tee_type(&self) -> TeeType {
         TeeType::SEV
-    } else {
-        TeeType::PLAIN
+    }
+
+    fn detect(&self) -> Result<bool> {
+        Ok(Path::new("/dev/sev-guest").exists() || Path::new("/dev/sev").exists())
+    }
+
+    fn get_evidence(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        Err(anyhow!("SEV to be supported!"))
+    }
+
+    fn get_report(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        Err(anyhow!("SEV to be supported!"))
+    }
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+        Err(anyhow!("SEV to be supported!"))
+    }
+
+    fn get_event_log(&self) -> Result<Vec<u8>> {
+        Err(anyhow!("SEV to be supported!"))
     }
 }
 
 fn generate_tdx_report_data(
     report_data: Option<String>,
     nonce: String,
-) -> Result<String, anyhow::Error> {
+) -> Result<[u8; 64], anyhow::Error> {
     let nonce_decoded = match base64::decode(nonce) {
         Ok(v) => v,
         Err(e) => {
@@ -49,7 +215,7 @@ fn generate_tdx_report_data(
     };
     let mut hasher = Sha512::new();
     hasher.update(nonce_decoded);
-    let _ret = match report_data {
+    match report_data {
         Some(_encoded_report_data) => {
             if _encoded_report_data.is_empty() {
                 hasher.update("")
@@ -67,46 +233,19 @@ fn generate_tdx_report_data(
             }
         }
         None => hasher.update(""),
-    };
+    }
     let hash_array: [u8; 64] = hasher
         .finalize()
         .as_slice()
         .try_into()
         .expect("[generate_tdx_report_data] Wrong length of report data");
-    Ok(base64::encode(hash_array))
-}
-
-fn get_tdx_quote(report_data: Option<String>, nonce: String) -> Result<String> {
-    let tdx_report_data = match generate_tdx_report_data(report_data, nonce) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(anyhow!("[get_tdx_quote]: {:?}", e));
-        }
-    };
-
-    let quote = match tdx_attest::get_tdx_quote(tdx_report_data) {
-        Err(e) => panic!("[get_tdx_quote] Fail to get TDX quote: {:?}", e),
-        Ok(q) => base64::encode(q),
-    };
-
-    serde_json::to_string(&quote).map_err(|e| anyhow!("[get_tdx_quote]: {:?}", e))
-}
-
-fn get_tpm_quote() -> Result<String> {
-    Err(anyhow!("TPM to be supported!"))
-}
-
-fn get_sev_quote() -> Result<String> {
-    Err(anyhow!("SEV to be supported!"))
+    Ok(hash_array)
 }
 
-pub fn get_quote(local_tee: TeeType, user_data: String, nonce: String) -> Result<String> {
-    match local_tee {
-        TeeType::TDX => get_tdx_quote(Some(user_data), nonce),
-        TeeType::TPM => get_tpm_quote(),
-        TeeType::SEV => get_sev_quote(),
-        _ => Err(anyhow!("Unexpected case!")),
-    }
+pub fn get_quote(tee: &dyn TeeBackend, user_data: Option<String>, nonce: String) -> Result<String> {
+    let report_data = generate_tdx_report_data(user_data, nonce)?;
+    let quote = base64::encode(tee.get_evidence(&report_data)?);
+    serde_json::to_string(&quote).map_err(|e| anyhow!("[get_quote]: {:?}", e))
 }
 
 #[cfg(test)]
@@ -211,8 +350,7 @@ mod tests {
             32, 162, 89, 243, 191, 177, 131, 4, 159, 156, 104, 11, 193, 18, 217, 92, 215, 194, 98,
             145, 191, 211, 85, 187, 118, 39, 80,
         ];
-        let generated_hash = base64::decode(result).unwrap();
-        assert_eq!(generated_hash, expected_hash);
+        assert_eq!(result, expected_hash);
     }
 
     #[test]
@@ -261,21 +399,29 @@ mod tests {
             Ok(r) => r,
             Err(_) => todo!(),
         };
-        let generated_hash_len = base64::decode(report_data_hashed).unwrap().len();
+        let generated_hash_len = report_data_hashed.len();
         assert_eq!(generated_hash_len, 64);
     }
 
     #[test]
     //TDX ENV required: tdx_get_quote allow empty nonce
     fn tdx_get_quote_empty_nonce() {
-        let result = get_tdx_quote(Some("YWJjZGVmZw==".to_string()), "".to_string());
+        let result = get_quote(
+            &TdxBackend {},
+            Some("YWJjZGVmZw==".to_string()),
+            "".to_string(),
+        );
         assert!(result.is_ok());
     }
 
     #[test]
     //TDX ENV required: tdx_get_quote allow 0 bytes report data string
     fn tdx_get_quote_report_data_size_0() {
-        let result = get_tdx_quote(Some("".to_string()), "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = get_quote(
+            &TdxBackend {},
+            Some("".to_string()),
+            "IXUKoBO1XEFBPwopN4sY".to_string(),
+        );
         assert!(result.is_ok());
     }
 
@@ -283,7 +429,8 @@ mod tests {
     //TDX ENV required: tdx_get_quote allow 8 bytes report data string
     fn tdx_get_quote_report_data_size_8() {
         // "YWJjZGVmZw==" is base64 of "abcdefg", 8 bytes
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &TdxBackend {},
             Some("YWJjZGVmZw==".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
         );
@@ -293,7 +440,8 @@ mod tests {
     #[test]
     //TDX ENV required: tdx_get_quote allow 48 bytes report data string
     fn tdx_get_quote_report_data_size_48() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &TdxBackend {},
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
         );
@@ -303,14 +451,15 @@ mod tests {
     #[test]
     //TDX ENV required: tdx_get_quote allow optional report data
     fn tdx_get_quote_report_data_null() {
-        let result = get_tdx_quote(None, "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = get_quote(&TdxBackend {}, None, "IXUKoBO1XEFBPwopN4sY".to_string());
         assert!(result.is_ok());
     }
 
     #[test]
     //TDX ENV required: tdx_get_quote require report data string is base64 encoded
     fn tdx_get_quote_report_data_not_base64_encoded() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &TdxBackend {},
             Some("XD^%*!x".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
         );
@@ -320,7 +469,8 @@ mod tests {
     #[test]
     //TDX ENV required: tdx_get_quote require nonce string is base64 encoded
     fn tdx_get_quote_nonce_not_base64_encoded() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &TdxBackend {},
             Some("IXUKoBO1XEFBPwopN4sY".to_string()),
             "XD^%*!x".to_string(),
         );
@@ -330,7 +480,8 @@ mod tests {
     #[test]
     //TDX ENV required: tdx_get_quote allow long report data string
     fn tdx_get_quote_long_tdx_report_data() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &TdxBackend {},
             Some(
                 "MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2Nzgx\
                 MjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEy\
@@ -349,7 +500,8 @@ mod tests {
     #[test]
     //TDX ENV required: tdx_get_quote allow long nonce string
     fn tdx_get_quote_long_nonce() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &TdxBackend {},
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2Nzgx\
             MjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEy\
@@ -366,7 +518,8 @@ mod tests {
     #[test]
     //TDX ENV required: get_tdx_quote return non-empty encoded quote string
     fn tdx_get_quote_report_data_encoded_quote_is_not_0_bytes() {
-        let quote = match get_tdx_quote(
+        let quote = match get_quote(
+            &TdxBackend {},
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
         ) {
@@ -377,49 +530,33 @@ mod tests {
     }
 
     #[test]
-    //get_quote does not allow tee type beyond TDX/SEV/TPM
-    fn get_quote_wrong_tee_type() {
-        let result = get_quote(
-            TeeType::PLAIN,
-            "".to_string(),
-            "IXUKoBO1XEFBPwopN4sY".to_string(),
-        );
-        assert!(result.is_err());
-    }
-
-    #[test]
-    //get_quote does not support SEV for now
-    fn get_quote_sev_tee_type() {
-        //does not allow tee type beyond TDX/SEV/TPM
-        let result = get_quote(
-            TeeType::SEV,
-            "".to_string(),
-            "IXUKoBO1XEFBPwopN4sY".to_string(),
-        );
-        assert!(result.is_err());
-    }
-
-    #[test]
-    //get_quote does not support TPM for now
-    fn get_quote_tpm_tee_type() {
-        //does not allow tee type beyond TDX/SEV/TPM
-        let result = get_quote(
-            TeeType::TPM,
-            "".to_string(),
-            "IXUKoBO1XEFBPwopN4sY".to_string(),
-        );
-        assert!(result.is_err());
+    //SEV and TPM backends do not provide evidence for now
+    fn get_quote_unsupported_tee_type() {
+        let tees: [&dyn TeeBackend; 2] = [&SevBackend {}, &TpmBackend {}];
+        for tee in tees {
+            let result = get_quote(
+                tee,
+                Some("".to_string()),
+                "IXUKoBO1XEFBPwopN4sY".to_string(),
+            );
+            assert!(result.is_err());
+        }
     }
 
     #[test]
     //get_quote support TDX now
     fn get_quote_tdx_tee_type() {
-        //does not allow tee type beyond TDX/SEV/TPM
         let result = get_quote(
-            TeeType::TDX,
-            "".to_string(),
+            &TdxBackend {},
+            Some("".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
         );
         assert!(result.is_ok());
     }
+
+    #[test]
+    //an empty registry detects no TEE
+    fn registry_detect_plain() {
+        assert!(TeeRegistry::new().detect().unwrap().is_none());
+    }
 }
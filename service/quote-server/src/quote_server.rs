@@ -4,14 +4,31 @@
 */
 
 use clap::Parser;
+use nix::errno::Errno;
 use quote_server::get_quote_server::{GetQuote, GetQuoteServer};
-use quote_server::{GetQuoteRequest, GetQuoteResponse};
+use quote_server::{
+    GetEventLogRequest, GetEventLogResponse, GetMeasurementsRequest, GetMeasurementsResponse,
+    GetQuoteRequest, GetQuoteResponse, GetQuoteV2Response, GetTdReportRequest, GetTdReportResponse,
+};
+use std::sync::Arc;
+use std::time::Instant;
+use std::vec::IntoIter;
+use tdx_attest::eventlog::{EventLog, EventLogEntry};
+use tdx_attest::{RetryPolicy, TdReport, TdxError};
 use tokio::net::UnixListener;
+use tokio::time;
 use tokio_stream::wrappers::UnixListenerStream;
+use tokio_stream::Iter;
 use tonic::{transport::Server, Request, Response, Status};
 
+pub mod sev;
 pub mod tee;
+pub mod tpm;
+pub mod worker;
+use quote_server::get_event_log_response::Entry;
 use tee::*;
+use tpm::hash_alg_name;
+use worker::{WorkerError, WorkerPool};
 
 pub mod quote_server {
     tonic::include_proto!("quoteserver");
@@ -20,42 +37,449 @@ pub mod quote_server {
         tonic::include_file_descriptor_set!("quote_server_descriptor");
 }
 
+//device requests are served by few blocking workers, requests beyond the queue are rejected.
+//a quote waits out its retry backoff off the workers, so they only ever hold running attempts
+const QUOTE_WORKERS: usize = 2;
+const QUOTE_QUEUE_LEN: usize = 32;
+//TD reports, measurements and event logs have their own lane, a quote generation service
+//that is slow to answer must not hold up reads that never reach it
+const REPORT_WORKERS: usize = 1;
+const REPORT_QUEUE_LEN: usize = 32;
+
 pub struct CCNPGetQuote {
-    local_tee: tee::TeeType,
+    local_tees: Vec<Arc<dyn TeeBackend>>, // Detected TEEs, the preferred one first
+    quote_workers: WorkerPool,            // Quotes, which may wait for the quote generation service
+    report_workers: WorkerPool,           // Local reads: reports, measurements and event logs
+    retry_policy: RetryPolicy, // Retries of quotes the quote generation service cannot answer yet
 }
 
 impl CCNPGetQuote {
-    fn new(_local_tee: TeeType) -> Self {
+    fn new(
+        _local_tees: Vec<Box<dyn TeeBackend>>,
+        _quote_workers: WorkerPool,
+        _report_workers: WorkerPool,
+    ) -> Self {
         CCNPGetQuote {
-            local_tee: _local_tee,
+            local_tees: _local_tees.into_iter().map(Arc::from).collect(),
+            quote_workers: _quote_workers,
+            report_workers: _report_workers,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    //retry and deadline of quote requests, attempts of the device itself should not retry
+    fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    //the TEE of the requested type, the preferred one when no type is requested
+    fn select_tee(&self, tee_type: Option<TeeType>) -> Option<Arc<dyn TeeBackend>> {
+        match tee_type {
+            None => self.local_tees.first().cloned(),
+            Some(t) => self.local_tees.iter().find(|b| b.tee_type() == t).cloned(),
+        }
+    }
+
+    //the device calls block, run them on the given workers instead of the async runtime
+    async fn run_on_tee<T, F>(
+        &self,
+        workers: &WorkerPool,
+        tee: Arc<dyn TeeBackend>,
+        operation: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(&dyn TeeBackend) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = match workers.run(move || operation(tee.as_ref())).await {
+            Err(e) => return Err(worker_status(&e)),
+            Ok(r) => r,
+        };
+        match result {
+            Err(e) => Err(to_status(&e)),
+            Ok(r) => Ok(r),
+        }
+    }
+
+    //a quote may have to wait for the quote generation service. every attempt runs on a quote
+    //worker, a transient failure gives the worker back and the request waits out the backoff
+    //on the runtime before it is queued again
+    async fn run_with_retries<T, F>(
+        &self,
+        tee: Arc<dyn TeeBackend>,
+        operation: Arc<F>,
+    ) -> Result<T, Status>
+    where
+        F: Fn(&dyn TeeBackend) -> anyhow::Result<T> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let deadline = Instant::now() + self.retry_policy.timeout;
+        let mut retries = 0;
+        loop {
+            let (attempt, tee) = (operation.clone(), tee.clone());
+            let result = match time::timeout_at(
+                deadline.into(),
+                self.quote_workers.run(move || attempt(tee.as_ref())),
+            )
+            .await
+            {
+                Err(_) => return Err(timeout_status(&self.retry_policy)),
+                Ok(Err(e)) => return Err(worker_status(&e)),
+                Ok(Ok(r)) => r,
+            };
+            let error = match result {
+                Err(e) => e,
+                Ok(v) => return Ok(v),
+            };
+            let tdx_error = match error.downcast_ref::<TdxError>() {
+                None => return Err(to_status(&error)),
+                Some(t) => t.clone(),
+            };
+            let delay = match self.retry_policy.retry_delay(tdx_error, retries, deadline) {
+                Err(TdxError::Timeout(_)) => return Err(timeout_status(&self.retry_policy)),
+                Err(_) => return Err(to_status(&error)),
+                Ok(d) => d,
+            };
+            time::sleep(delay).await;
+            retries += 1;
+        }
+    }
+
+    //the TEE named in a request, the preferred one when it names none
+    async fn requested_tee(&self, tee_type: &str) -> Result<Arc<dyn TeeBackend>, Status> {
+        let requested = match tee_type {
+            "" => None,
+            t => match t.parse::<TeeType>() {
+                Err(e) => return Err(Status::invalid_argument(format!("{:?}", e))),
+                Ok(t) => Some(t),
+            },
+        };
+        match self.select_tee(requested) {
+            None => Err(Status::failed_precondition(format!(
+                "[requested_tee] {} is not available on this platform",
+                tee_type
+            ))),
+            Some(t) => Ok(t),
         }
     }
+
+    //run the operation with the TEE named in a request, the preferred one when it names none
+    async fn run_on_requested_tee<T, F>(
+        &self,
+        workers: &WorkerPool,
+        tee_type: &str,
+        operation: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(&dyn TeeBackend) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let local_tee = self.requested_tee(tee_type).await?;
+        self.run_on_tee(workers, local_tee, operation).await
+    }
+
+    //run the operation with the TEE and report data mode the request selects
+    async fn run_request<T, F>(&self, req: &GetQuoteRequest, operation: F) -> Result<T, Status>
+    where
+        F: Fn(&dyn TeeBackend, ReportDataMode) -> anyhow::Result<T> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        println!(
+            "Got a request with: user_data = {:?}, nonce = {:?}, tee_type = {:?}",
+            req.user_data, req.nonce, req.tee_type
+        );
+        let mode = match report_data_mode(req.report_data_mode) {
+            None => {
+                return Err(Status::invalid_argument(format!(
+                    "[run_request] Unknown report data mode {}",
+                    req.report_data_mode
+                )))
+            }
+            Some(m) => m,
+        };
+        let local_tee = self.requested_tee(&req.tee_type).await?;
+        self.run_with_retries(
+            local_tee,
+            Arc::new(move |tee: &dyn TeeBackend| operation(tee, mode)),
+        )
+        .await
+    }
 }
 
 #[tonic::async_trait]
 impl GetQuote for CCNPGetQuote {
+    type GetEventLogStream = Iter<IntoIter<Result<GetEventLogResponse, Status>>>;
+
     async fn get_quote(
         &self,
         request: Request<GetQuoteRequest>,
     ) -> Result<Response<GetQuoteResponse>, Status> {
-        let msg;
         let req = request.into_inner();
+        let (user_data, nonce) = (req.user_data.clone(), req.nonce.clone());
+        let (tee_type, quote) = self
+            .run_request(&req, move |tee, mode| {
+                Ok((
+                    tee.tee_type(),
+                    get_quote(tee, Some(user_data.clone()), nonce.clone(), mode)?,
+                ))
+            })
+            .await?;
+        Ok(Response::new(quote_server::GetQuoteResponse {
+            quote,
+            quote_type: format!("{:?}", tee_type),
+        }))
+    }
 
-        println!(
-            "Got a request with: user_data = {:?}, nonce = {:?}",
-            req.user_data, req.nonce
-        );
-        let result = get_quote(self.local_tee.clone(), req.user_data, req.nonce);
-        match result {
-            Ok(q) => {
-                msg = Response::new(quote_server::GetQuoteResponse {
-                    quote: q,
-                    quote_type: format!("{:?}", self.local_tee).to_string(),
+    async fn get_quote_v2(
+        &self,
+        request: Request<GetQuoteRequest>,
+    ) -> Result<Response<GetQuoteV2Response>, Status> {
+        let req = request.into_inner();
+        let (user_data, nonce) = (req.user_data.clone(), req.nonce.clone());
+        let evidence = self
+            .run_request(&req, move |tee, mode| {
+                get_evidence(tee, Some(user_data.clone()), nonce.clone(), mode)
+            })
+            .await?;
+        Ok(Response::new(GetQuoteV2Response {
+            quote: evidence.data,
+            tee_type: proto_tee_type(evidence.tee_type) as i32,
+            report_data: evidence.report_data.to_vec(),
+            tdx_version: evidence.version.tee_version.unwrap_or_default(),
+            quote_version: evidence.version.format_version.unwrap_or_default(),
+            generated_at: Some(evidence.generated_at.into()),
+        }))
+    }
+
+    async fn get_td_report(
+        &self,
+        request: Request<GetTdReportRequest>,
+    ) -> Result<Response<GetTdReportResponse>, Status> {
+        let req = request.into_inner();
+        let mut report_data = [0u8; 64];
+        match req.report_data.len() {
+            0 => (),
+            64 => report_data.copy_from_slice(&req.report_data),
+            n => {
+                return Err(Status::invalid_argument(format!(
+                    "[get_td_report] Report data must be 64 bytes, got {}",
+                    n
+                )))
+            }
+        }
+        //TD reports only come from the TDX module, whatever evidence source is preferred
+        let local_tee = match self.select_tee(Some(TeeType::TDX)) {
+            None => {
+                return Err(Status::failed_precondition(
+                    "[get_td_report] TDX is not available on this platform",
+                ))
+            }
+            Some(t) => t,
+        };
+        let (td_report, parsed) = self
+            .run_on_tee(&self.report_workers, local_tee, move |tee| {
+                get_td_report(tee, &report_data)
+            })
+            .await?;
+        Ok(Response::new(GetTdReportResponse {
+            td_report,
+            parsed: Some(proto_td_report(&parsed)),
+        }))
+    }
+
+    async fn get_measurements(
+        &self,
+        request: Request<GetMeasurementsRequest>,
+    ) -> Result<Response<GetMeasurementsResponse>, Status> {
+        let req = request.into_inner();
+        let (tee_type, registers) = self
+            .run_on_requested_tee(&self.report_workers, &req.tee_type, |tee| {
+                Ok((tee.tee_type(), tee.get_measurements()?))
+            })
+            .await?;
+        Ok(Response::new(GetMeasurementsResponse {
+            tee_type: proto_tee_type(tee_type) as i32,
+            registers: registers
+                .into_iter()
+                .map(|r| quote_server::MeasurementRegister {
+                    name: r.name,
+                    algorithm: hash_alg_name(r.algorithm_id),
+                    algorithm_id: r.algorithm_id as u32,
+                    digest_hex: r.digest.iter().map(|b| format!("{:02x}", b)).collect(),
+                    digest: r.digest,
                 })
+                .collect(),
+        }))
+    }
+
+    async fn get_event_log(
+        &self,
+        request: Request<GetEventLogRequest>,
+    ) -> Result<Response<Self::GetEventLogStream>, Status> {
+        let req = request.into_inner();
+        let format = match quote_server::EventLogFormat::from_i32(req.format) {
+            None => {
+                return Err(Status::invalid_argument(format!(
+                    "[get_event_log] Unknown event log format {}",
+                    req.format
+                )))
             }
-            Err(e) => return Err(Status::internal(e.to_string())),
+            Some(f) => f,
+        };
+        let log = self
+            .run_on_requested_tee(&self.report_workers, &req.tee_type, |tee| {
+                tee.get_event_log()
+            })
+            .await?;
+        //raw entries can only be parsed with the digest sizes the Spec ID event declares
+        let mut header = match format {
+            quote_server::EventLogFormat::Raw => EventLog {
+                spec_id_event: log.spec_id_event,
+                events: Vec::new(),
+            }
+            .to_bytes(),
+            quote_server::EventLogFormat::Decoded => Vec::new(),
+        };
+        let events = log.events;
+
+        //positions are stable while the log only grows, so pages can be fetched one by one
+        let count = match req.count {
+            0 => events.len(),
+            c => c as usize,
+        };
+        let entries: Vec<Result<GetEventLogResponse, Status>> = events
+            .into_iter()
+            .enumerate()
+            .skip(req.start_position as usize)
+            .take(count)
+            .map(|(position, event)| {
+                let entry = match format {
+                    quote_server::EventLogFormat::Raw => Entry::Raw(event.to_bytes()),
+                    quote_server::EventLogFormat::Decoded => {
+                        Entry::Decoded(proto_event_log_entry(event))
+                    }
+                };
+                GetEventLogResponse {
+                    position: position as u32,
+                    entry: Some(entry),
+                    header: std::mem::take(&mut header),
+                }
+            })
+            .map(Ok)
+            .collect();
+        Ok(Response::new(tokio_stream::iter(entries)))
+    }
+}
+
+fn proto_td_report(report: &TdReport) -> quote_server::TdReport {
+    let mac_struct = &report.report_mac_struct;
+    let tcb_info = &report.tee_tcb_info;
+    let td_info = &report.td_info;
+    quote_server::TdReport {
+        report_mac_struct: Some(quote_server::ReportMacStruct {
+            tee_type: mac_struct.report_type.tee_type as u32,
+            subtype: mac_struct.report_type.subtype as u32,
+            version: mac_struct.report_type.version as u32,
+            cpusvn: mac_struct.cpusvn.to_vec(),
+            tee_tcb_info_hash: mac_struct.tee_tcb_info_hash.to_vec(),
+            tee_info_hash: mac_struct.tee_info_hash.to_vec(),
+            report_data: mac_struct.report_data.to_vec(),
+            mac: mac_struct.mac.to_vec(),
+        }),
+        tee_tcb_info: Some(quote_server::TeeTcbInfo {
+            valid: tcb_info.valid.to_vec(),
+            tee_tcb_svn: tcb_info.tee_tcb_svn.to_vec(),
+            mrseam: tcb_info.mrseam.to_vec(),
+            mrsignerseam: tcb_info.mrsignerseam.to_vec(),
+            attributes: tcb_info.attributes.to_vec(),
+            tee_tcb_svn2: tcb_info.tee_tcb_svn2.to_vec(),
+        }),
+        td_info: Some(quote_server::TdInfo {
+            attributes: td_info.attributes.to_vec(),
+            xfam: td_info.xfam.to_vec(),
+            mrtd: td_info.mrtd.to_vec(),
+            mrconfigid: td_info.mrconfigid.to_vec(),
+            mrowner: td_info.mrowner.to_vec(),
+            mrownerconfig: td_info.mrownerconfig.to_vec(),
+            rtmrs: td_info.rtmrs.iter().map(|r| r.0.to_vec()).collect(),
+            servtd_hash: td_info.servtd_hash.to_vec(),
+        }),
+    }
+}
+
+fn proto_event_log_entry(event: EventLogEntry) -> quote_server::EventLogEntry {
+    quote_server::EventLogEntry {
+        mr_index: event.mr_index,
+        event_type: event.event_type,
+        digests: event
+            .digests
+            .into_iter()
+            .map(|d| quote_server::EventDigest {
+                algorithm: hash_alg_name(d.algorithm_id),
+                algorithm_id: d.algorithm_id as u32,
+                digest: d.digest,
+            })
+            .collect(),
+        event: event.event,
+    }
+}
+
+fn proto_tee_type(tee_type: TeeType) -> quote_server::TeeType {
+    match tee_type {
+        TeeType::TDX => quote_server::TeeType::Tdx,
+        TeeType::SEV => quote_server::TeeType::Sev,
+        TeeType::TPM => quote_server::TeeType::Tpm,
+    }
+}
+
+fn report_data_mode(mode: i32) -> Option<ReportDataMode> {
+    match quote_server::ReportDataMode::from_i32(mode)? {
+        quote_server::ReportDataMode::Sha512 => Some(ReportDataMode::Sha512),
+        quote_server::ReportDataMode::Raw => Some(ReportDataMode::Raw),
+        quote_server::ReportDataMode::Sha384 => Some(ReportDataMode::Sha384),
+        quote_server::ReportDataMode::DomainSeparatedV1 => Some(ReportDataMode::DomainSeparatedV1),
+    }
+}
+
+//map a failed request to the gRPC status code matching its TDX error
+fn to_status(e: &anyhow::Error) -> Status {
+    let tdx_error = match e.downcast_ref::<TdxError>() {
+        Some(t) => t,
+        None => return Status::internal(format!("{:?}", e)),
+    };
+
+    let message = format!("{:?}", e);
+    match tdx_error {
+        TdxError::DeviceNotFound | TdxError::DeprecatedDevice(_) => {
+            Status::failed_precondition(message)
         }
-        Ok(msg)
+        TdxError::PermissionDenied(_) => Status::permission_denied(message),
+        TdxError::IoctlFailed(Errno::EBUSY | Errno::EAGAIN | Errno::EINTR) => {
+            Status::unavailable(message)
+        }
+        TdxError::QgsError(_) | TdxError::QgsTransport(_) => Status::unavailable(message),
+        TdxError::QuoteInFlight | TdxError::QuoteServiceUnavailable | TdxError::DeviceBusy => {
+            Status::unavailable(message)
+        }
+        TdxError::Timeout(_) => Status::deadline_exceeded(message),
+        TdxError::ConcurrentModification(_, _) => Status::aborted(message),
+        TdxError::BadReportData(_) => Status::invalid_argument(message),
+        TdxError::QuoteTooLarge(_) => Status::resource_exhausted(message),
+        _ => Status::internal(message),
+    }
+}
+
+//the request ran out of time, in an attempt or waiting for the next one
+fn timeout_status(retry_policy: &RetryPolicy) -> Status {
+    to_status(&anyhow::Error::new(TdxError::Timeout(retry_policy.timeout)))
+}
+
+//a full queue is back-pressure towards the client, a lost worker an internal failure
+fn worker_status(e: &WorkerError) -> Status {
+    match e {
+        WorkerError::QueueFull(_) => Status::resource_exhausted(e.to_string()),
+        WorkerError::Stopped => Status::internal(e.to_string()),
     }
 }
 
@@ -74,12 +498,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     let uds_stream = UnixListenerStream::new(uds);
 
-    let getquote = CCNPGetQuote::new({
-        match tee::get_tee_type() {
-            tee::TeeType::PLAIN => panic!("[quote-server]: Not found any TEE device!"),
-            t => t,
-        }
-    });
+    let preferred = match preferred_tee() {
+        Err(e) => panic!("[quote-server]: Invalid {}: {}", PREFERRED_TEE_ENV, e),
+        Ok(p) => p,
+    };
+    let retry_policy = match quote_retry_policy() {
+        Err(e) => panic!("[quote-server]: Invalid quote retry policy: {}", e),
+        Ok(p) => p,
+    };
+    let local_tees = match TeeRegistry::platform(retry_policy.clone()).detect_all(preferred) {
+        Err(e) => panic!("[quote-server]: Fail to detect TEE device: {}", e),
+        Ok(t) => t,
+    };
+    for tee in &local_tees {
+        println!(
+            "Detected {:?} evidence source with {:?}",
+            tee.tee_type(),
+            tee.capabilities()
+        );
+    }
+    let tee_type = local_tees[0].tee_type();
+    match preferred {
+        Some(p) if p != tee_type => println!(
+            "Preferred {:?} evidence source is not available, fall back to {:?}",
+            p, tee_type
+        ),
+        _ => (),
+    }
+    let getquote = CCNPGetQuote::new(
+        local_tees,
+        WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+        WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+    )
+    .with_retry_policy(retry_policy);
 
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     health_reporter
@@ -92,8 +543,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap();
 
     println!(
-        "Starting quote server in {} enviroment...",
-        format!("{:?}", tee::get_tee_type()).to_string()
+        "Starting quote server in {:?} enviroment by default...",
+        tee_type
     );
 
     Server::builder()
@@ -109,11 +560,98 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 mod quote_server_tests {
     use super::*;
     use crate::quote_server::get_quote_client::GetQuoteClient;
+    use crate::tpm::{TpmBackend, TpmDevice};
     use serial_test::serial;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tdx_attest::eventlog::TPM_ALG_SHA384;
+    use tdx_attest::qgs::{AttKeyId, QgsQuoteResponse};
+    use tdx_attest::{RuntimeEventLog, TdxDevice, TdxInterface, TdxSimulator};
     use tokio::net::UnixStream;
+    use tokio_stream::StreamExt;
     use tonic::transport::{Endpoint, Uri};
     use tower::service_fn;
 
+    //simulator whose first quote is still in flight at the VMM, every attempt is signalled
+    struct InFlightOnceDevice {
+        simulator: TdxSimulator,
+        in_flight: AtomicBool,
+        attempts: tokio::sync::mpsc::UnboundedSender<()>,
+    }
+
+    impl TdxDevice for InFlightOnceDevice {
+        fn get_td_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+            self.simulator.get_td_report(report_data)
+        }
+
+        fn get_quote_with_key_ids(
+            &self,
+            report_data: &[u8; 64],
+            att_key_ids: &[AttKeyId],
+        ) -> Result<QgsQuoteResponse, TdxError> {
+            let _ = self.attempts.send(());
+            if self.in_flight.swap(false, Ordering::SeqCst) {
+                return Err(TdxError::QuoteInFlight);
+            }
+            self.simulator
+                .get_quote_with_key_ids(report_data, att_key_ids)
+        }
+
+        fn interface(&self) -> Result<TdxInterface, TdxError> {
+            self.simulator.interface()
+        }
+
+        fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+            self.simulator.extend_rtmr_register(index, digest)
+        }
+
+        fn runtime_event_log(&self) -> &RuntimeEventLog {
+            self.simulator.runtime_event_log()
+        }
+    }
+
+    //simulator laid out like the guest device, TD reports and quotes lock device nodes of their
+    //own. a quote holds its node and blocks on the gate like a GetQuote waiting for the QGS
+    struct BlockingQuoteDevice {
+        simulator: TdxSimulator,
+        report_node: std::sync::Mutex<()>,
+        quote_node: std::sync::Mutex<()>,
+        started: tokio::sync::mpsc::UnboundedSender<()>,
+        gate: std::sync::Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl TdxDevice for BlockingQuoteDevice {
+        fn get_td_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+            let _report_node = self.report_node.lock().unwrap();
+            self.simulator.get_td_report(report_data)
+        }
+
+        fn get_quote_with_key_ids(
+            &self,
+            report_data: &[u8; 64],
+            att_key_ids: &[AttKeyId],
+        ) -> Result<QgsQuoteResponse, TdxError> {
+            let _quote_node = self.quote_node.lock().unwrap();
+            let _ = self.started.send(());
+            let _ = self.gate.lock().unwrap().recv();
+            self.simulator
+                .get_quote_with_key_ids(report_data, att_key_ids)
+        }
+
+        fn interface(&self) -> Result<TdxInterface, TdxError> {
+            self.simulator.interface()
+        }
+
+        fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+            let _report_node = self.report_node.lock().unwrap();
+            self.simulator.extend_rtmr_register(index, digest)
+        }
+
+        fn runtime_event_log(&self) -> &RuntimeEventLog {
+            self.simulator.runtime_event_log()
+        }
+    }
+
     async fn creat_server() {
         let path = "/tmp/quote-server.sock";
         let _ = std::fs::remove_file(path);
@@ -123,12 +661,12 @@ mod quote_server_tests {
         };
         let uds_stream = UnixListenerStream::new(uds);
 
-        let getquote = CCNPGetQuote::new({
-            match tee::get_tee_type() {
-                tee::TeeType::PLAIN => panic!("[quote-server]: Not found any TEE device!"),
-                t => t,
-            }
-        });
+        //serve from the TDX simulator so that the gRPC stack runs without TDX hardware
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(TdxSimulator::default())))],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
 
         tokio::spawn(async {
             Server::builder()
@@ -139,6 +677,519 @@ mod quote_server_tests {
         });
     }
 
+    #[test]
+    //TDX errors are mapped to the matching gRPC status codes
+    fn to_status_maps_tdx_errors() {
+        let code = |e: TdxError| to_status(&anyhow::Error::new(e)).code();
+        assert_eq!(
+            code(TdxError::DeviceNotFound),
+            tonic::Code::FailedPrecondition
+        );
+        assert_eq!(
+            code(TdxError::PermissionDenied("/dev/tdx_guest".to_string())),
+            tonic::Code::PermissionDenied
+        );
+        assert_eq!(
+            code(TdxError::IoctlFailed(Errno::EBUSY)),
+            tonic::Code::Unavailable
+        );
+        assert_eq!(
+            code(TdxError::IoctlFailed(Errno::EINVAL)),
+            tonic::Code::Internal
+        );
+        assert_eq!(code(TdxError::QgsError(0x12001)), tonic::Code::Unavailable);
+        assert_eq!(
+            code(TdxError::QuoteServiceUnavailable),
+            tonic::Code::Unavailable
+        );
+        assert_eq!(code(TdxError::DeviceBusy), tonic::Code::Unavailable);
+        assert_eq!(
+            code(TdxError::Timeout(std::time::Duration::from_secs(30))),
+            tonic::Code::DeadlineExceeded
+        );
+        assert_eq!(
+            code(TdxError::BadReportData("".to_string())),
+            tonic::Code::InvalidArgument
+        );
+        assert_eq!(
+            code(TdxError::QuoteTooLarge(8192)),
+            tonic::Code::ResourceExhausted
+        );
+
+        //the TDX error stays visible behind added context
+        let e = anyhow::Error::new(TdxError::DeviceNotFound).context("[get_tdx_quote]");
+        assert_eq!(to_status(&e).code(), tonic::Code::FailedPrecondition);
+        assert_eq!(
+            to_status(&anyhow::anyhow!("TPM to be supported!")).code(),
+            tonic::Code::Internal
+        );
+    }
+
+    #[tokio::test]
+    //a quote waiting out its backoff gives the only quote worker to other requests
+    async fn request_served_while_quote_backs_off() {
+        let (attempt_sender, mut attempts) = tokio::sync::mpsc::unbounded_channel();
+        let device = InFlightOnceDevice {
+            simulator: TdxSimulator::default(),
+            in_flight: AtomicBool::new(true),
+            attempts: attempt_sender,
+        };
+        let getquote = Arc::new(
+            CCNPGetQuote::new(
+                vec![Box::new(TdxBackend::new(Arc::new(device)))],
+                WorkerPool::new(1, QUOTE_QUEUE_LEN),
+                WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+            )
+            .with_retry_policy(RetryPolicy {
+                max_retries: 5,
+                initial_backoff: Duration::from_secs(2),
+                max_backoff: Duration::from_secs(2),
+                timeout: Duration::from_secs(10),
+            }),
+        );
+        let request = || {
+            Request::new(GetQuoteRequest {
+                user_data: "".to_string(),
+                nonce: "12345678".to_string(),
+                tee_type: "".to_string(),
+                report_data_mode: 0,
+            })
+        };
+
+        let backing_off = {
+            let (getquote, request) = (getquote.clone(), request());
+            tokio::spawn(async move { getquote.get_quote(request).await })
+        };
+        attempts.recv().await.unwrap();
+
+        //served within the backoff of the first request
+        let started = Instant::now();
+        let response = getquote.get_quote(request()).await.unwrap().into_inner();
+        assert_eq!(response.quote_type, "TDX");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(!backing_off.is_finished());
+
+        let response = backing_off.await.unwrap().unwrap().into_inner();
+        assert_eq!(response.quote_type, "TDX");
+        let mut count = 1;
+        while attempts.try_recv().is_ok() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    //a quote still in flight at the deadline fails with DEADLINE_EXCEEDED
+    async fn request_deadline_while_quote_backs_off() {
+        let (attempt_sender, _attempts) = tokio::sync::mpsc::unbounded_channel();
+        let device = InFlightOnceDevice {
+            simulator: TdxSimulator::default(),
+            in_flight: AtomicBool::new(true),
+            attempts: attempt_sender,
+        };
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(device)))],
+            WorkerPool::new(1, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        )
+        .with_retry_policy(RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(2),
+            timeout: Duration::from_millis(500),
+        });
+        let request = Request::new(GetQuoteRequest {
+            user_data: "".to_string(),
+            nonce: "12345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
+        });
+        let status = getquote.get_quote(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    //requests are turned away with RESOURCE_EXHAUSTED while the workers and queue are taken
+    async fn request_rejected_when_queue_full() {
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(TdxSimulator::default())))],
+            WorkerPool::new(1, 1),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+
+        let (started_sender, started_receiver) = std::sync::mpsc::channel();
+        let (gate_sender, gate_receiver) = std::sync::mpsc::channel::<()>();
+        let busy = getquote
+            .quote_workers
+            .submit(move || {
+                started_sender.send(()).unwrap();
+                gate_receiver.recv().unwrap();
+            })
+            .unwrap();
+        started_receiver.recv().unwrap();
+        let queued = getquote.quote_workers.submit(|| ()).unwrap();
+
+        let request = Request::new(GetQuoteRequest {
+            user_data: "".to_string(),
+            nonce: "12345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
+        });
+        let status = getquote.get_quote(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        gate_sender.send(()).unwrap();
+        busy.await.unwrap();
+        queued.await.unwrap();
+
+        let request = Request::new(GetQuoteRequest {
+            user_data: "".to_string(),
+            nonce: "12345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
+        });
+        let response = getquote.get_quote(request).await.unwrap().into_inner();
+        assert_eq!(response.quote_type, "TDX");
+    }
+
+    #[tokio::test]
+    //the request picks the evidence source, quote_type names the one that produced the quote
+    async fn request_chooses_tee() {
+        //nothing listens on the swtpm port, the preferred TPM fails to produce evidence
+        let getquote = CCNPGetQuote::new(
+            vec![
+                Box::new(TpmBackend::new(TpmDevice::Swtpm("127.0.0.1:1".to_string()))),
+                Box::new(TdxBackend::new(Arc::new(TdxSimulator::default()))),
+            ],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+        let request = |tee_type: &str| {
+            Request::new(GetQuoteRequest {
+                user_data: "".to_string(),
+                nonce: "12345678".to_string(),
+                tee_type: tee_type.to_string(),
+                report_data_mode: 0,
+            })
+        };
+
+        assert!(getquote.get_quote(request("")).await.is_err());
+        let response = getquote
+            .get_quote(request("tdx"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.quote_type, "TDX");
+        assert_ne!(response.quote.len(), 0);
+
+        let status = getquote.get_quote(request("SEV")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let status = getquote.get_quote(request("plain")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    //raw report data ends up in the quote unchanged, unknown modes are rejected
+    async fn request_report_data_mode() {
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(TdxSimulator::default())))],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+        let raw = [0x5a; 64];
+        let request = |report_data_mode: i32| {
+            Request::new(GetQuoteRequest {
+                user_data: base64::encode(raw),
+                nonce: "".to_string(),
+                tee_type: "".to_string(),
+                report_data_mode,
+            })
+        };
+
+        let response = getquote
+            .get_quote(request(quote_server::ReportDataMode::Raw as i32))
+            .await
+            .unwrap()
+            .into_inner();
+        let quote = base64::decode(response.quote.replace("\"", "")).unwrap();
+        let parsed_quote = tdx_attest::Quote::from_bytes(&quote).unwrap();
+        assert_eq!(parsed_quote.report_data(), &raw);
+
+        let status = getquote.get_quote(request(42)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    //the v2 response carries the raw quote, the report data it is bound to and its versions
+    async fn request_quote_v2() {
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(TdxSimulator::default())))],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+        let request = Request::new(GetQuoteRequest {
+            user_data: "YWJjZGVmZw==".to_string(),
+            nonce: "MTIzNDU2Nzg=".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
+        });
+        let response = getquote.get_quote_v2(request).await.unwrap().into_inner();
+
+        let expected_report_data = [
+            93, 71, 28, 83, 115, 189, 166, 130, 87, 137, 126, 119, 140, 209, 163, 215, 13, 175,
+            225, 101, 64, 195, 196, 202, 15, 37, 166, 241, 141, 49, 128, 157, 164, 132, 67, 50, 9,
+            32, 162, 89, 243, 191, 177, 131, 4, 159, 156, 104, 11, 193, 18, 217, 92, 215, 194, 98,
+            145, 191, 211, 85, 187, 118, 39, 80,
+        ];
+        assert_eq!(response.tee_type(), quote_server::TeeType::Tdx);
+        assert_eq!(response.report_data, expected_report_data);
+        let parsed_quote = tdx_attest::Quote::from_bytes(&response.quote).unwrap();
+        assert_eq!(parsed_quote.report_data(), &expected_report_data);
+        assert_eq!(response.quote_version, parsed_quote.header.version as u32);
+        assert!(!response.tdx_version.is_empty());
+        assert!(response.generated_at.is_some());
+    }
+
+    #[tokio::test]
+    //the TD report is returned raw and parsed, bound to the given report data
+    async fn request_td_report() {
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(TdxSimulator::default())))],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+        let request = Request::new(GetTdReportRequest {
+            report_data: vec![0x5a; 64],
+        });
+        let response = getquote.get_td_report(request).await.unwrap().into_inner();
+
+        let report = TdReport::from_bytes(&response.td_report).unwrap();
+        let parsed = response.parsed.unwrap();
+        let mac_struct = parsed.report_mac_struct.unwrap();
+        assert_eq!(mac_struct.report_data, vec![0x5a; 64]);
+        assert_eq!(mac_struct.tee_type, 0x81);
+        let td_info = parsed.td_info.unwrap();
+        assert_eq!(td_info.mrtd, report.td_info.mrtd.to_vec());
+        assert_eq!(td_info.rtmrs.len(), 4);
+
+        let request = Request::new(GetTdReportRequest {
+            report_data: vec![0x5a; 48],
+        });
+        let status = getquote.get_td_report(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    //reports and measurements are served while a quote holds the device waiting for the QGS
+    async fn request_td_report_with_blocked_quote() {
+        let (started_sender, mut started) = tokio::sync::mpsc::unbounded_channel();
+        let (gate_sender, gate_receiver) = std::sync::mpsc::channel::<()>();
+        let device = BlockingQuoteDevice {
+            simulator: TdxSimulator::default(),
+            report_node: std::sync::Mutex::new(()),
+            quote_node: std::sync::Mutex::new(()),
+            started: started_sender,
+            gate: std::sync::Mutex::new(gate_receiver),
+        };
+        let getquote = Arc::new(CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(Arc::new(device)))],
+            WorkerPool::new(1, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        ));
+
+        let blocked = {
+            let getquote = getquote.clone();
+            let request = Request::new(GetQuoteRequest {
+                user_data: "".to_string(),
+                nonce: "12345678".to_string(),
+                tee_type: "".to_string(),
+                report_data_mode: 0,
+            });
+            tokio::spawn(async move { getquote.get_quote(request).await })
+        };
+        started.recv().await.unwrap();
+
+        let request = Request::new(GetTdReportRequest {
+            report_data: vec![0x5a; 64],
+        });
+        let response = time::timeout(Duration::from_secs(5), getquote.get_td_report(request))
+            .await
+            .unwrap()
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.td_report.len(), 1024);
+
+        let request = Request::new(GetMeasurementsRequest {
+            tee_type: "".to_string(),
+        });
+        let response = time::timeout(Duration::from_secs(5), getquote.get_measurements(request))
+            .await
+            .unwrap()
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.registers.len(), 8);
+        assert!(!blocked.is_finished());
+
+        drop(gate_sender);
+        let response = blocked.await.unwrap().unwrap().into_inner();
+        assert_eq!(response.quote_type, "TDX");
+    }
+
+    #[tokio::test]
+    //the measurement registers of a fresh TD report are returned as bytes and hex
+    async fn request_measurements() {
+        let simulator = Arc::new(TdxSimulator::default());
+        simulator.extend_rtmr(2, &[0x22; 48]).unwrap();
+        let rtmr2 = TdReport::from_bytes(&simulator.get_td_report(&[0; 64]).unwrap())
+            .unwrap()
+            .td_info
+            .rtmrs[2]
+            .0;
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(simulator))],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+        let request = Request::new(GetMeasurementsRequest {
+            tee_type: "".to_string(),
+        });
+        let response = getquote
+            .get_measurements(request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(response.tee_type(), quote_server::TeeType::Tdx);
+        let names: Vec<&str> = response.registers.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "MRTD",
+                "RTMR0",
+                "RTMR1",
+                "RTMR2",
+                "RTMR3",
+                "MRCONFIGID",
+                "MROWNER",
+                "MROWNERCONFIG"
+            ]
+        );
+        let register = &response.registers[3];
+        assert_eq!(register.algorithm, "sha384");
+        assert_eq!(register.digest, rtmr2.to_vec());
+        let rtmr2_hex: String = rtmr2.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(register.digest_hex, rtmr2_hex);
+
+        let request = Request::new(GetMeasurementsRequest {
+            tee_type: "TPM".to_string(),
+        });
+        let status = getquote.get_measurements(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    //event log entries are paged by position and served decoded or in the TCG format
+    async fn request_event_log() {
+        let simulator = Arc::new(TdxSimulator::default());
+        for i in 0..3 {
+            simulator.extend_rtmr(i, &[0x11 * i as u8; 48]).unwrap();
+        }
+        let events = simulator.runtime_event_log().events();
+        let getquote = CCNPGetQuote::new(
+            vec![Box::new(TdxBackend::new(simulator))],
+            WorkerPool::new(QUOTE_WORKERS, QUOTE_QUEUE_LEN),
+            WorkerPool::new(REPORT_WORKERS, REPORT_QUEUE_LEN),
+        );
+        let request = |start_position: u32, count: u32, format: quote_server::EventLogFormat| {
+            Request::new(GetEventLogRequest {
+                tee_type: "".to_string(),
+                start_position,
+                count,
+                format: format as i32,
+            })
+        };
+        let entries = |stream: <CCNPGetQuote as GetQuote>::GetEventLogStream| async move {
+            stream
+                .map(|r| r.unwrap())
+                .collect::<Vec<GetEventLogResponse>>()
+                .await
+        };
+
+        let decoded = quote_server::EventLogFormat::Decoded;
+        let all = entries(
+            getquote
+                .get_event_log(request(0, 0, decoded))
+                .await
+                .unwrap()
+                .into_inner(),
+        )
+        .await;
+        assert_eq!(all.len(), 3);
+
+        let page = entries(
+            getquote
+                .get_event_log(request(1, 1, decoded))
+                .await
+                .unwrap()
+                .into_inner(),
+        )
+        .await;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].position, 1);
+        match &page[0].entry {
+            Some(Entry::Decoded(e)) => {
+                assert_eq!(e.mr_index, 2);
+                assert_eq!(e.digests[0].algorithm, "sha384");
+                assert_eq!(e.digests[0].digest, events[1].digests[0].digest);
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+
+        let page = entries(
+            getquote
+                .get_event_log(request(2, 5, quote_server::EventLogFormat::Raw))
+                .await
+                .unwrap()
+                .into_inner(),
+        )
+        .await;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].entry, Some(Entry::Raw(events[2].to_bytes())));
+        assert!(!page[0].header.is_empty());
+        assert!(all.iter().all(|e| e.header.is_empty()));
+
+        //the header and the raw entries of a stream form a TCG event log
+        let raw = entries(
+            getquote
+                .get_event_log(request(0, 0, quote_server::EventLogFormat::Raw))
+                .await
+                .unwrap()
+                .into_inner(),
+        )
+        .await;
+        assert!(raw[1..].iter().all(|e| e.header.is_empty()));
+        let mut log = raw[0].header.clone();
+        for response in &raw {
+            match &response.entry {
+                Some(Entry::Raw(bytes)) => log.extend_from_slice(bytes),
+                e => panic!("unexpected entry {:?}", e),
+            }
+        }
+        let log = EventLog::from_bytes(&log).unwrap();
+        assert_eq!(log.spec_id_event.digest_size(TPM_ALG_SHA384), Some(48));
+        assert_eq!(log.events, events);
+
+        let past_end = entries(
+            getquote
+                .get_event_log(request(3, 0, decoded))
+                .await
+                .unwrap()
+                .into_inner(),
+        )
+        .await;
+        assert!(past_end.is_empty());
+    }
+
     #[tokio::test]
     #[serial]
     //test start server and send request
@@ -159,6 +1210,8 @@ mod quote_server_tests {
         let request = tonic::Request::new(GetQuoteRequest {
             user_data: base64::encode("123456781234567812345678123456781234567812345678"),
             nonce: "12345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
         });
 
         let response = client.get_quote(request).await.unwrap().into_inner();
@@ -184,6 +1237,8 @@ mod quote_server_tests {
         let request = tonic::Request::new(GetQuoteRequest {
             user_data: "".to_string(),
             nonce: "12345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
         });
 
         let response = client.get_quote(request).await.unwrap().into_inner();
@@ -210,6 +1265,8 @@ mod quote_server_tests {
         let request = tonic::Request::new(GetQuoteRequest {
             user_data: "123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678".to_string(),
             nonce: "12345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
         });
 
         let response = client.get_quote(request).await.unwrap().into_inner();
@@ -236,6 +1293,8 @@ mod quote_server_tests {
         let request = tonic::Request::new(GetQuoteRequest {
             user_data: "123456781234567812345678123456781234567812345678".to_string(),
             nonce: "".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
         });
 
         let response = client.get_quote(request).await.unwrap().into_inner();
@@ -262,6 +1321,8 @@ mod quote_server_tests {
         let request = tonic::Request::new(GetQuoteRequest {
             user_data: "123456781234567812345678123456781234567812345678".to_string(),
             nonce: "123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678123456781234567812345678".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
         });
 
         let response = client.get_quote(request).await.unwrap().into_inner();
@@ -288,6 +1349,8 @@ mod quote_server_tests {
         let request = tonic::Request::new(GetQuoteRequest {
             user_data: "YWJjZGVmZw==".to_string(),
             nonce: "MTIzNDU2Nzg=".to_string(),
+            tee_type: "".to_string(),
+            report_data_mode: 0,
         });
 
         let response = client.get_quote(request).await.unwrap().into_inner();
@@ -301,8 +1364,7 @@ mod quote_server_tests {
 
         assert_eq!(response.quote_type, "TDX");
         let quote = base64::decode(response.quote.replace("\"", "")).unwrap();
-        let mut report_data_in_quote: [u8; 64 as usize] = [0; 64 as usize];
-        report_data_in_quote.copy_from_slice(&quote[568..632]);
-        assert_eq!(report_data_in_quote, expected_report_data);
+        let parsed_quote = tdx_attest::Quote::from_bytes(&quote).unwrap();
+        assert_eq!(parsed_quote.report_data(), &expected_report_data);
     }
 }
@@ -0,0 +1,654 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::tee::{MeasurementRegister, TeeBackend, TeeCapabilities, TeeType};
+use anyhow::*;
+use nix::errno::Errno;
+use nix::ioctl_readwrite;
+use std::fmt;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::result::Result::Ok;
+use tdx_attest::eventlog::{EventLog, TPM_ALG_SHA384};
+
+pub const SEV_GUEST_PATH: &str = "/dev/sev-guest";
+const SNP_GUEST_REQ_IOC_TYPE: u8 = b'S';
+const SNP_GET_REPORT: u8 = 0x0;
+const SNP_GET_EXT_REPORT: u8 = 0x2;
+const SNP_MSG_VERSION: u8 = 1;
+//the host asks for a larger certificate buffer with this VMM error
+const SNP_GUEST_VMM_ERR_INVALID_LEN: u32 = 1;
+const SNP_REPORT_RESP_LEN: usize = 4000;
+//MSG_REPORT_RSP: status, report size and reserved bytes ahead of the report
+const SNP_REPORT_RESP_HEADER_LEN: usize = 32;
+pub const SNP_REPORT_LEN: usize = 1184;
+const SNP_CERTS_PAGE_LEN: usize = 4096;
+const SNP_CERT_ENTRY_LEN: usize = 24;
+
+//GUIDs of the certificate table entries defined by the GHCB specification
+const ARK_GUID: &str = "c0b406a4-a803-4952-9743-3fb6014cd0ae";
+const ASK_GUID: &str = "4ab7b379-bbac-4fe4-a02f-05aef327c782";
+const VCEK_GUID: &str = "63da758d-e664-4564-adc5-f4b93be8accd";
+const VLEK_GUID: &str = "a8074bc2-a25a-483e-aae6-39c045a0b8a1";
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct SnpReportReq {
+    pub user_data: [u8; 64], // Report data bound into the report
+    pub vmpl: u32,           // VMPL the report is requested for
+    rsvd: [u8; 28],
+}
+
+#[repr(C)]
+pub struct SnpReportResp {
+    pub data: [u8; SNP_REPORT_RESP_LEN], // MSG_REPORT_RSP from the firmware
+}
+
+#[repr(C)]
+struct SnpExtReportReq {
+    data: SnpReportReq,
+    certs_address: u64,
+    certs_len: u32,
+}
+
+#[repr(C)]
+struct SnpGuestRequestIoctl {
+    msg_version: u8,
+    req_data: u64,
+    resp_data: u64,
+    exitinfo2: u64, // Firmware error in the low, VMM error in the high 32 bits
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SevGuestError {
+    CertsTooSmall(usize),     // Certificate buffer length the host needs
+    Request(Errno, u32, u32), // Errno, firmware and VMM error of a failed guest request
+    Device(String),
+}
+
+impl fmt::Display for SevGuestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SevGuestError::CertsTooSmall(len) => {
+                write!(f, "certificate buffer too small, {} bytes needed", len)
+            }
+            SevGuestError::Request(errno, fw_error, vmm_error) => write!(
+                f,
+                "guest request failed: {}, firmware error {:#x}, VMM error {:#x}",
+                errno, fw_error, vmm_error
+            ),
+            SevGuestError::Device(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SevGuestError {}
+
+//the guest request channel to the AMD secure processor
+pub trait SevGuestDevice: Send + Sync {
+    fn get_report(&self, req: &SnpReportReq) -> Result<SnpReportResp, SevGuestError>;
+
+    //the certificate table is written to certs, CertsTooSmall asks for a larger buffer
+    fn get_ext_report(
+        &self,
+        req: &SnpReportReq,
+        certs: &mut [u8],
+    ) -> Result<SnpReportResp, SevGuestError>;
+}
+
+//the sev-guest driver of the Linux kernel
+pub struct SevGuest {
+    path: String,
+}
+
+impl Default for SevGuest {
+    fn default() -> Self {
+        SevGuest {
+            path: SEV_GUEST_PATH.to_string(),
+        }
+    }
+}
+
+impl SevGuest {
+    fn open(&self) -> Result<File, SevGuestError> {
+        match File::options().read(true).write(true).open(&self.path) {
+            Err(e) => Err(SevGuestError::Device(format!(
+                "[open] Fail to open {}: {:?}",
+                self.path, e
+            ))),
+            Ok(f) => Ok(f),
+        }
+    }
+}
+
+fn request_error(errno: Errno, ioctl: &SnpGuestRequestIoctl) -> SevGuestError {
+    SevGuestError::Request(
+        errno,
+        ioctl.exitinfo2 as u32,
+        (ioctl.exitinfo2 >> 32) as u32,
+    )
+}
+
+impl SevGuestDevice for SevGuest {
+    fn get_report(&self, req: &SnpReportReq) -> Result<SnpReportResp, SevGuestError> {
+        let device = self.open()?;
+        let mut resp = SnpReportResp {
+            data: [0; SNP_REPORT_RESP_LEN],
+        };
+        let mut ioctl = SnpGuestRequestIoctl {
+            msg_version: SNP_MSG_VERSION,
+            req_data: ptr::addr_of!(*req) as u64,
+            resp_data: ptr::addr_of_mut!(resp) as u64,
+            exitinfo2: 0,
+        };
+
+        ioctl_readwrite!(
+            snp_get_report,
+            SNP_GUEST_REQ_IOC_TYPE,
+            SNP_GET_REPORT,
+            SnpGuestRequestIoctl
+        );
+        if let Err(e) = unsafe { snp_get_report(device.as_raw_fd(), &mut ioctl) } {
+            return Err(request_error(e, &ioctl));
+        }
+        Ok(resp)
+    }
+
+    fn get_ext_report(
+        &self,
+        req: &SnpReportReq,
+        certs: &mut [u8],
+    ) -> Result<SnpReportResp, SevGuestError> {
+        let device = self.open()?;
+        let mut resp = SnpReportResp {
+            data: [0; SNP_REPORT_RESP_LEN],
+        };
+        let mut ext_req = SnpExtReportReq {
+            data: req.clone(),
+            certs_address: certs.as_mut_ptr() as u64,
+            certs_len: certs.len() as u32,
+        };
+        let mut ioctl = SnpGuestRequestIoctl {
+            msg_version: SNP_MSG_VERSION,
+            req_data: ptr::addr_of_mut!(ext_req) as u64,
+            resp_data: ptr::addr_of_mut!(resp) as u64,
+            exitinfo2: 0,
+        };
+
+        ioctl_readwrite!(
+            snp_get_ext_report,
+            SNP_GUEST_REQ_IOC_TYPE,
+            SNP_GET_EXT_REPORT,
+            SnpGuestRequestIoctl
+        );
+        if let Err(e) = unsafe { snp_get_ext_report(device.as_raw_fd(), &mut ioctl) } {
+            //the driver updates certs_len with the length the host needs
+            if (ioctl.exitinfo2 >> 32) as u32 == SNP_GUEST_VMM_ERR_INVALID_LEN {
+                return Err(SevGuestError::CertsTooSmall(ext_req.certs_len as usize));
+            }
+            return Err(request_error(e, &ioctl));
+        }
+        Ok(resp)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcbVersion {
+    pub boot_loader: u8,
+    pub tee: u8,
+    pub snp: u8,
+    pub microcode: u8,
+}
+
+impl TcbVersion {
+    fn from_bytes(tcb: &[u8; 8]) -> Self {
+        TcbVersion {
+            boot_loader: tcb[0],
+            tee: tcb[1],
+            snp: tcb[6],
+            microcode: tcb[7],
+        }
+    }
+}
+
+//ATTESTATION_REPORT of the SEV-SNP firmware ABI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationReport {
+    pub version: u32,
+    pub guest_svn: u32,
+    pub policy: u64,
+    pub family_id: [u8; 16],
+    pub image_id: [u8; 16],
+    pub vmpl: u32,
+    pub signature_algo: u32,
+    pub current_tcb: TcbVersion,
+    pub platform_info: u64,
+    pub report_data: [u8; 64],
+    pub measurement: [u8; 48],
+    pub host_data: [u8; 32],
+    pub id_key_digest: [u8; 48],
+    pub author_key_digest: [u8; 48],
+    pub report_id: [u8; 32],
+    pub report_id_ma: [u8; 32],
+    pub reported_tcb: TcbVersion,
+    pub chip_id: [u8; 64],
+    pub committed_tcb: TcbVersion,
+    pub current_version: (u8, u8, u8), // Major, minor, build of the firmware
+    pub committed_version: (u8, u8, u8), // Major, minor, build of the firmware
+    pub launch_tcb: TcbVersion,
+    pub signature: Vec<u8>, // ECDSA P-384 r and s, little endian
+}
+
+fn array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N]
+        .try_into()
+        .expect("[array] Range checked against the report length")
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(array(bytes, offset))
+}
+
+impl AttestationReport {
+    pub fn from_bytes(report: &[u8]) -> Result<Self> {
+        if report.len() < SNP_REPORT_LEN {
+            return Err(anyhow!(
+                "[AttestationReport] Report is {} bytes, expected {}",
+                report.len(),
+                SNP_REPORT_LEN
+            ));
+        }
+        Ok(AttestationReport {
+            version: u32_at(report, 0x00),
+            guest_svn: u32_at(report, 0x04),
+            policy: u64::from_le_bytes(array(report, 0x08)),
+            family_id: array(report, 0x10),
+            image_id: array(report, 0x20),
+            vmpl: u32_at(report, 0x30),
+            signature_algo: u32_at(report, 0x34),
+            current_tcb: TcbVersion::from_bytes(&array(report, 0x38)),
+            platform_info: u64::from_le_bytes(array(report, 0x40)),
+            report_data: array(report, 0x50),
+            measurement: array(report, 0x90),
+            host_data: array(report, 0xc0),
+            id_key_digest: array(report, 0xe0),
+            author_key_digest: array(report, 0x110),
+            report_id: array(report, 0x140),
+            report_id_ma: array(report, 0x160),
+            reported_tcb: TcbVersion::from_bytes(&array(report, 0x180)),
+            chip_id: array(report, 0x1a0),
+            committed_tcb: TcbVersion::from_bytes(&array(report, 0x1e0)),
+            current_version: (report[0x1ea], report[0x1e9], report[0x1e8]),
+            committed_version: (report[0x1ee], report[0x1ed], report[0x1ec]),
+            launch_tcb: TcbVersion::from_bytes(&array(report, 0x1f0)),
+            signature: report[0x2a0..SNP_REPORT_LEN].to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnpCertificate {
+    pub guid: String, // Entry GUID, e.g. the VCEK GUID
+    pub data: Vec<u8>,
+}
+
+impl SnpCertificate {
+    //ark, ask, vcek or vlek, None for vendor specific entries
+    pub fn kind(&self) -> Option<&'static str> {
+        match self.guid.as_str() {
+            ARK_GUID => Some("ark"),
+            ASK_GUID => Some("ask"),
+            VCEK_GUID => Some("vcek"),
+            VLEK_GUID => Some("vlek"),
+            _ => None,
+        }
+    }
+}
+
+fn format_guid(guid: &[u8; 16]) -> String {
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex(&guid[0..4]),
+        hex(&guid[4..6]),
+        hex(&guid[6..8]),
+        hex(&guid[8..10]),
+        hex(&guid[10..16])
+    )
+}
+
+//certificate table entries up to the all-zero terminator
+pub fn parse_cert_table(certs: &[u8]) -> Result<Vec<SnpCertificate>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        //a host without certificates leaves the buffer zeroed, which ends the table at once
+        if certs.len() - offset < SNP_CERT_ENTRY_LEN {
+            return Err(anyhow!(
+                "[parse_cert_table] Certificate table not terminated"
+            ));
+        }
+        let guid: [u8; 16] = array(certs, offset);
+        let cert_offset = u32_at(certs, offset + 16) as usize;
+        let cert_len = u32_at(certs, offset + 20) as usize;
+        if guid == [0; 16] {
+            return Ok(entries);
+        }
+        let data = match certs.get(cert_offset..cert_offset.saturating_add(cert_len)) {
+            Some(d) => d.to_vec(),
+            None => {
+                return Err(anyhow!(
+                    "[parse_cert_table] Certificate {} out of the table",
+                    format_guid(&guid)
+                ))
+            }
+        };
+        entries.push(SnpCertificate {
+            guid: format_guid(&guid),
+            data,
+        });
+        offset += SNP_CERT_ENTRY_LEN;
+    }
+}
+
+//the report from a MSG_REPORT_RSP after checking the firmware status
+fn report_from_response(resp: &SnpReportResp) -> Result<Vec<u8>> {
+    let status = u32_at(&resp.data, 0);
+    if status != 0 {
+        return Err(anyhow!(
+            "[report_from_response] Firmware status {:#x}",
+            status
+        ));
+    }
+    let report_size = u32_at(&resp.data, 4) as usize;
+    if !(SNP_REPORT_LEN..=SNP_REPORT_RESP_LEN - SNP_REPORT_RESP_HEADER_LEN).contains(&report_size) {
+        return Err(anyhow!(
+            "[report_from_response] Report size {} out of range",
+            report_size
+        ));
+    }
+    Ok(resp.data[SNP_REPORT_RESP_HEADER_LEN..SNP_REPORT_RESP_HEADER_LEN + report_size].to_vec())
+}
+
+pub struct SevBackend {
+    device: Box<dyn SevGuestDevice>,
+    vmpl: u32,
+}
+
+impl Default for SevBackend {
+    fn default() -> Self {
+        SevBackend::new(Box::<SevGuest>::default())
+    }
+}
+
+impl SevBackend {
+    pub fn new(_device: Box<dyn SevGuestDevice>) -> Self {
+        SevBackend {
+            device: _device,
+            vmpl: 0,
+        }
+    }
+
+    fn report_req(&self, report_data: &[u8; 64]) -> SnpReportReq {
+        SnpReportReq {
+            user_data: *report_data,
+            vmpl: self.vmpl,
+            rsvd: [0; 28],
+        }
+    }
+
+    pub fn get_attestation_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        let resp = self.device.get_report(&self.report_req(report_data))?;
+        report_from_response(&resp)
+    }
+
+    //the report with the certificate table the host provides for it
+    pub fn get_extended_report(
+        &self,
+        report_data: &[u8; 64],
+    ) -> Result<(Vec<u8>, Vec<SnpCertificate>)> {
+        let req = self.report_req(report_data);
+        let mut certs = vec![0u8; SNP_CERTS_PAGE_LEN];
+        let resp = match self.device.get_ext_report(&req, &mut certs) {
+            Err(SevGuestError::CertsTooSmall(len)) if len > certs.len() => {
+                certs = vec![0u8; len.next_multiple_of(SNP_CERTS_PAGE_LEN)];
+                self.device.get_ext_report(&req, &mut certs)?
+            }
+            Err(e) => return Err(Error::new(e)),
+            Ok(r) => r,
+        };
+        Ok((report_from_response(&resp)?, parse_cert_table(&certs)?))
+    }
+}
+
+impl TeeBackend for SevBackend {
+    fn tee_type(&self) -> TeeType {
+        TeeType::SEV
+    }
+
+    fn detect(&self) -> Result<bool> {
+        //only SNP guests have the sev-guest device, /dev/sev of the host or a plain SEV guest
+        //cannot serve attestation reports
+        Ok(Path::new(SEV_GUEST_PATH).exists())
+    }
+
+    fn capabilities(&self) -> TeeCapabilities {
+        TeeCapabilities {
+            event_log: false,
+            ..TeeCapabilities::default()
+        }
+    }
+
+    //the attestation report and the certificate chain as JSON
+    fn get_evidence(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        let (report, certs) = self.get_extended_report(report_data)?;
+        let certificates: Vec<serde_json::Value> = certs
+            .iter()
+            .map(|c| {
+                serde_json::json!({
+                    "guid": c.guid,
+                    "type": c.kind(),
+                    "data": base64::encode(&c.data),
+                })
+            })
+            .collect();
+        let evidence = serde_json::json!({
+            "report": base64::encode(report),
+            "certificates": certificates,
+        });
+        serde_json::to_vec(&evidence).map_err(|e| anyhow!("[get_evidence]: {:?}", e))
+    }
+
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        self.get_attestation_report(report_data)
+    }
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+        let report = AttestationReport::from_bytes(&self.get_attestation_report(&[0; 64])?)?;
+        Ok(vec![MeasurementRegister {
+            name: "MEASUREMENT".to_string(),
+            algorithm_id: TPM_ALG_SHA384,
+            digest: report.measurement.to_vec(),
+        }])
+    }
+
+    fn get_event_log(&self) -> Result<EventLog> {
+        Err(anyhow!("[get_event_log] SEV-SNP has no event log"))
+    }
+}
+
+#[cfg(test)]
+mod sev_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn guid_bytes(guid: &str) -> [u8; 16] {
+        let hex: String = guid.chars().filter(|c| *c != '-').collect();
+        let mut bytes = [0u8; 16];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    fn sample_report(report_data: &[u8; 64]) -> Vec<u8> {
+        let mut report = vec![0u8; SNP_REPORT_LEN];
+        report[0x00..0x04].copy_from_slice(&2u32.to_le_bytes());
+        report[0x08..0x10].copy_from_slice(&0x30000u64.to_le_bytes());
+        report[0x38..0x40].copy_from_slice(&[3, 0, 0, 0, 0, 0, 8, 115]);
+        report[0x50..0x90].copy_from_slice(report_data);
+        report[0x90..0xc0].copy_from_slice(&[0x4d; 48]);
+        report[0x1e8..0x1eb].copy_from_slice(&[21, 55, 1]);
+        report
+    }
+
+    fn report_resp(status: u32, report: &[u8]) -> SnpReportResp {
+        let mut resp = SnpReportResp {
+            data: [0; SNP_REPORT_RESP_LEN],
+        };
+        resp.data[0..4].copy_from_slice(&status.to_le_bytes());
+        resp.data[4..8].copy_from_slice(&(report.len() as u32).to_le_bytes());
+        resp.data[32..32 + report.len()].copy_from_slice(report);
+        resp
+    }
+
+    fn cert_table(certs: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut table = Vec::new();
+        let mut offset = (certs.len() + 1) * SNP_CERT_ENTRY_LEN;
+        for (guid, data) in certs {
+            table.extend_from_slice(&guid_bytes(guid));
+            table.extend_from_slice(&(offset as u32).to_le_bytes());
+            table.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            offset += data.len();
+        }
+        table.extend_from_slice(&[0; SNP_CERT_ENTRY_LEN]);
+        for (_, data) in certs {
+            table.extend_from_slice(data);
+        }
+        table
+    }
+
+    //answers from the request, keeps the certificate buffer lengths it was given
+    struct FakeSevGuest {
+        status: u32,
+        certs: Vec<u8>,
+        certs_lens: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl SevGuestDevice for FakeSevGuest {
+        fn get_report(&self, req: &SnpReportReq) -> Result<SnpReportResp, SevGuestError> {
+            assert_eq!(req.vmpl, 0);
+            Ok(report_resp(self.status, &sample_report(&req.user_data)))
+        }
+
+        fn get_ext_report(
+            &self,
+            req: &SnpReportReq,
+            certs: &mut [u8],
+        ) -> Result<SnpReportResp, SevGuestError> {
+            self.certs_lens.lock().unwrap().push(certs.len());
+            if certs.len() < self.certs.len() {
+                return Err(SevGuestError::CertsTooSmall(self.certs.len()));
+            }
+            certs[..self.certs.len()].copy_from_slice(&self.certs);
+            self.get_report(req)
+        }
+    }
+
+    fn fake_backend(status: u32, certs: Vec<u8>) -> SevBackend {
+        SevBackend::new(Box::new(FakeSevGuest {
+            status,
+            certs,
+            certs_lens: Arc::new(Mutex::new(Vec::new())),
+        }))
+    }
+
+    #[test]
+    //the attestation report is decoded from the MSG_REPORT_RSP
+    fn attestation_report_decode() {
+        let tee = fake_backend(0, Vec::new());
+        let report = tee.get_attestation_report(&[0x42; 64]).unwrap();
+        assert_eq!(report.len(), SNP_REPORT_LEN);
+
+        let decoded = AttestationReport::from_bytes(&report).unwrap();
+        assert_eq!(decoded.version, 2);
+        assert_eq!(decoded.policy, 0x30000);
+        assert_eq!(decoded.report_data, [0x42; 64]);
+        assert_eq!(decoded.measurement, [0x4d; 48]);
+        assert_eq!(
+            decoded.current_tcb,
+            TcbVersion {
+                boot_loader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115
+            }
+        );
+        assert_eq!(decoded.current_version, (1, 55, 21));
+        assert_eq!(decoded.signature.len(), 512);
+        assert!(AttestationReport::from_bytes(&report[..1000]).is_err());
+
+        let registers = tee.get_measurements().unwrap();
+        assert_eq!(registers[0].digest, vec![0x4d; 48]);
+    }
+
+    #[test]
+    //a failed firmware status or a malformed report size is rejected
+    fn report_response_errors() {
+        let tee = fake_backend(0x16, Vec::new());
+        assert!(tee.get_attestation_report(&[0; 64]).is_err());
+
+        let resp = report_resp(0, &[0; 100]);
+        assert!(report_from_response(&resp).is_err());
+    }
+
+    #[test]
+    //the certificate buffer is grown to the length the host asks for
+    fn extended_report_certificates() {
+        let vcek = vec![0x30; 5000];
+        let certs = cert_table(&[(ARK_GUID, b"ark"), (ASK_GUID, b"ask"), (VCEK_GUID, &vcek)]);
+        let certs_lens = Arc::new(Mutex::new(Vec::new()));
+        let tee = SevBackend::new(Box::new(FakeSevGuest {
+            status: 0,
+            certs,
+            certs_lens: certs_lens.clone(),
+        }));
+
+        let (report, certs) = tee.get_extended_report(&[0x42; 64]).unwrap();
+        assert_eq!(report[0x50..0x90], [0x42; 64]);
+        let kinds: Vec<Option<&str>> = certs.iter().map(|c| c.kind()).collect();
+        assert_eq!(kinds, vec![Some("ark"), Some("ask"), Some("vcek")]);
+        assert_eq!(certs[2].data, vcek);
+        assert_eq!(certs[2].guid, VCEK_GUID);
+        assert_eq!(*certs_lens.lock().unwrap(), vec![4096, 8192]);
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&tee.get_evidence(&[0x42; 64]).unwrap()).unwrap();
+        assert_eq!(json["certificates"][2]["type"], "vcek");
+    }
+
+    #[test]
+    //malformed certificate tables are rejected, an empty one has no entries
+    fn cert_table_errors() {
+        assert_eq!(parse_cert_table(&[0; 4096]).unwrap(), vec![]);
+
+        let mut table = cert_table(&[(VCEK_GUID, b"vcek")]);
+        table[16..20].copy_from_slice(&4000u32.to_le_bytes());
+        assert!(parse_cert_table(&table).is_err());
+
+        let table = cert_table(&[(VCEK_GUID, b"vcek")]);
+        assert!(parse_cert_table(&table[..SNP_CERT_ENTRY_LEN]).is_err());
+    }
+
+    #[test]
+    //request structures have the layout of the sev-guest uapi
+    fn request_layout() {
+        assert_eq!(std::mem::size_of::<SnpReportReq>(), 96);
+        assert_eq!(std::mem::size_of::<SnpExtReportReq>(), 112);
+        assert_eq!(std::mem::size_of::<SnpGuestRequestIoctl>(), 32);
+    }
+}
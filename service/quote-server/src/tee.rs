@@ -3,109 +3,514 @@
 * SPDX-License-Identifier: Apache-2.0
 */
 
+use crate::sev::SevBackend;
+use crate::tpm::TpmBackend;
 use anyhow::*;
-use sha2::{Digest, Sha512};
+use sha2::{Digest, Sha384, Sha512};
+use std::env;
 use std::path::Path;
 use std::result::Result::Ok;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use tdx_attest::device::GuestDevice;
+use tdx_attest::eventlog::{AlgorithmDigestSize, EventLog, SpecIdEvent, TPM_ALG_SHA384};
+use tdx_attest::{
+    Ccel, Quote, RetryPolicy, TdReport, TdxDevice, TdxError, TdxInterface, TdxVersion,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TeeType {
     TDX,
     SEV,
     TPM,
-    PLAIN,
 }
 
-pub fn get_tee_type() -> TeeType {
-    if Path::new("/dev/tpm0").exists() {
-        TeeType::TPM
-    } else if Path::new("/dev/tdx-guest").exists()
-        || Path::new("/dev/tdx-attest").exists()
-        || Path::new("/dev/tdx_guest").exists()
-    {
-        if Path::new("/dev/tdx-attest").exists() {
-            panic!("[get_tee_type]: Deprecated device node /dev/tdx-attest, please upgrade to use /dev/tdx-guest or /dev/tdx_guest");
+//names accepted from the operator and in requests, e.g. "tdx" or "TDX"
+impl FromStr for TeeType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "TDX" => Ok(TeeType::TDX),
+            "SEV" => Ok(TeeType::SEV),
+            "TPM" => Ok(TeeType::TPM),
+            _ => Err(anyhow!("[from_str] Unknown TEE type: {:?}", s)),
         }
-        TeeType::TDX
-    } else if Path::new("/dev/sev-guest").exists() || Path::new("/dev/sev").exists() {
-        TeeType::SEV
-    } else {
-        TeeType::PLAIN
     }
 }
 
-fn generate_tdx_report_data(
-    report_data: Option<String>,
-    nonce: String,
-) -> Result<String, anyhow::Error> {
-    let nonce_decoded = match base64::decode(nonce) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(anyhow!(
-                "[generate_tdx_report_data] nonce is not base64 encoded: {:?}",
-                e
-            ))
+//evidence source the operator prefers when several TEEs are present
+pub const PREFERRED_TEE_ENV: &str = "QUOTE_SERVER_PREFERRED_TEE";
+
+pub fn preferred_tee() -> Result<Option<TeeType>> {
+    match env::var(PREFERRED_TEE_ENV) {
+        Err(_) => Ok(None),
+        Ok(v) if v.is_empty() => Ok(None),
+        Ok(v) => Ok(Some(v.parse()?)),
+    }
+}
+
+//retries and overall deadline of TDX quote requests, the tdx_attest defaults when unset
+pub const QUOTE_RETRIES_ENV: &str = "QUOTE_SERVER_QUOTE_RETRIES";
+pub const QUOTE_TIMEOUT_ENV: &str = "QUOTE_SERVER_QUOTE_TIMEOUT_SECS";
+
+pub fn quote_retry_policy() -> Result<RetryPolicy> {
+    retry_policy_from(
+        env::var(QUOTE_RETRIES_ENV).ok().as_deref(),
+        env::var(QUOTE_TIMEOUT_ENV).ok().as_deref(),
+    )
+}
+
+fn retry_policy_from(retries: Option<&str>, timeout_secs: Option<&str>) -> Result<RetryPolicy> {
+    let mut policy = RetryPolicy::default();
+    if let Some(r) = retries.filter(|r| !r.is_empty()) {
+        policy.max_retries = match r.parse::<u32>() {
+            Err(e) => bail!("[retry_policy_from] Invalid quote retries {:?}: {}", r, e),
+            Ok(v) => v,
+        };
+    }
+    if let Some(t) = timeout_secs.filter(|t| !t.is_empty()) {
+        policy.timeout = match t.parse::<u64>() {
+            Err(e) => bail!("[retry_policy_from] Invalid quote timeout {:?}: {}", t, e),
+            Ok(0) => bail!("[retry_policy_from] Quote timeout must not be 0"),
+            Ok(v) => Duration::from_secs(v),
+        };
+    }
+    Ok(policy)
+}
+
+//what a backend serves besides its evidence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeeCapabilities {
+    pub report: bool,       // Report only verifiable on this platform
+    pub measurements: bool, // Measurement registers
+    pub event_log: bool,    // Events extending the measurement registers
+}
+
+impl Default for TeeCapabilities {
+    fn default() -> Self {
+        TeeCapabilities {
+            report: true,
+            measurements: true,
+            event_log: true,
         }
-    };
-    let mut hasher = Sha512::new();
-    hasher.update(nonce_decoded);
-    let _ret = match report_data {
-        Some(_encoded_report_data) => {
-            if _encoded_report_data.is_empty() {
-                hasher.update("")
-            } else {
-                let decoded_report_data = match base64::decode(_encoded_report_data) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        return Err(anyhow!(
-                            "[generate_tdx_report_data] user data is not base64 encoded: {:?}",
-                            e
-                        ))
-                    }
-                };
-                hasher.update(decoded_report_data)
+    }
+}
+
+//versions a relying party needs to pick the parser and policy for the evidence
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvidenceVersion {
+    pub tee_version: Option<String>, // Version of the TEE, e.g. 1.5 for a TDX 1.5 quote
+    pub format_version: Option<u32>, // Version of the evidence format, e.g. the TDX quote version
+}
+
+//evidence together with the report data it is bound to
+#[derive(Debug, Clone)]
+pub struct Evidence {
+    pub tee_type: TeeType,
+    pub data: Vec<u8>,
+    pub report_data: [u8; 64],
+    pub version: EvidenceVersion,
+    pub generated_at: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeasurementRegister {
+    pub name: String,      // MRTD, RTMR0-3, PCR0-23, ...
+    pub algorithm_id: u16, // TPM_ALG_ID of the digest
+    pub digest: Vec<u8>,
+}
+
+//a TEE the quote server can serve evidence from, registered in a TeeRegistry
+pub trait TeeBackend: Send + Sync {
+    fn tee_type(&self) -> TeeType;
+
+    //whether the TEE is present on this platform
+    fn detect(&self) -> Result<bool>;
+
+    fn capabilities(&self) -> TeeCapabilities {
+        TeeCapabilities::default()
+    }
+
+    //evidence for a remote verifier bound to the report data, e.g. the TDX quote
+    fn get_evidence(&self, report_data: &[u8; 64]) -> Result<Vec<u8>>;
+
+    //versions read from the evidence alone, without another request to the device
+    fn evidence_version(&self, _evidence: &[u8]) -> Result<EvidenceVersion> {
+        Ok(EvidenceVersion::default())
+    }
+
+    //report of the TEE only verifiable on this platform, e.g. the TD report
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>>;
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>>;
+
+    //boot and runtime events extending the measurement registers, in log order, with the
+    //Spec ID event describing the log format
+    fn get_event_log(&self) -> Result<EventLog>;
+}
+
+//the TEE backends known to the quote server, in default preference
+pub struct TeeRegistry {
+    backends: Vec<Box<dyn TeeBackend>>,
+}
+
+impl Default for TeeRegistry {
+    fn default() -> Self {
+        TeeRegistry::platform(RetryPolicy::default())
+    }
+}
+
+impl TeeRegistry {
+    //the backends of the platform, a TDX quote attempt is bounded by the policy's timeout
+    pub fn platform(retry_policy: RetryPolicy) -> Self {
+        //the simulator stands in for the platform, nothing else is detected
+        if tdx_attest::device::simulator_requested() {
+            let tdx = TdxBackend::new(tdx_attest::device::default_device());
+            return TeeRegistry::new().with_backend(Box::new(tdx));
+        }
+        //a guest device of its own, the process-wide default device would ignore the policy
+        //when the library created it first. the server retries quotes itself, off its workers,
+        //so the device only bounds a single attempt by the deadline
+        let tdx = TdxBackend::new(Arc::new(GuestDevice::default().with_retry_policy(
+            RetryPolicy {
+                max_retries: 0,
+                ..retry_policy
+            },
+        )));
+        //a vTPM often coexists with the confidential VM, its evidence is preferred last
+        TeeRegistry::new()
+            .with_backend(Box::new(tdx))
+            .with_backend(Box::new(SevBackend::default()))
+            .with_backend(Box::new(TpmBackend::default()))
+    }
+
+    pub fn new() -> Self {
+        TeeRegistry {
+            backends: Vec::new(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: Box<dyn TeeBackend>) -> Self {
+        self.backends.push(backend);
+        self
+    }
+
+    //every registered backend whose TEE is present, the preferred one first and the others
+    //in registration order. a backend failing to detect is skipped so that it cannot hide
+    //the others, it is an error only when no TEE is detected at all
+    pub fn detect_all(self, preferred: Option<TeeType>) -> Result<Vec<Box<dyn TeeBackend>>> {
+        let mut detected = Vec::new();
+        let mut failures = Vec::new();
+        for backend in self.backends {
+            match backend.detect() {
+                Err(e) => {
+                    println!("Skip {:?} evidence source: {}", backend.tee_type(), e);
+                    failures.push(format!("{:?}: {}", backend.tee_type(), e));
+                }
+                Ok(true) => detected.push(backend),
+                Ok(false) => (),
             }
         }
-        None => hasher.update(""),
-    };
-    let hash_array: [u8; 64] = hasher
-        .finalize()
-        .as_slice()
-        .try_into()
-        .expect("[generate_tdx_report_data] Wrong length of report data");
-    Ok(base64::encode(hash_array))
+        if detected.is_empty() {
+            if failures.is_empty() {
+                return Err(anyhow!("[detect_all] Not found any TEE device"));
+            }
+            return Err(anyhow!(
+                "[detect_all] Not found any TEE device, failed to detect {}",
+                failures.join(", ")
+            ));
+        }
+        if let Some(p) = detected
+            .iter()
+            .position(|b| Some(b.tee_type()) == preferred)
+        {
+            let backend = detected.remove(p);
+            detected.insert(0, backend);
+        }
+        Ok(detected)
+    }
+}
+
+pub struct TdxBackend {
+    device: Arc<dyn TdxDevice>,
+    ccel: Ccel,
+    tdx_version: OnceLock<TdxVersion>, // TDX module version, read from the first TD report
 }
 
-fn get_tdx_quote(report_data: Option<String>, nonce: String) -> Result<String> {
-    let tdx_report_data = match generate_tdx_report_data(report_data, nonce) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(anyhow!("[get_tdx_quote]: {:?}", e));
+impl TdxBackend {
+    pub fn new(_device: Arc<dyn TdxDevice>) -> Self {
+        TdxBackend {
+            device: _device,
+            ccel: Ccel::default(),
+            tdx_version: OnceLock::new(),
         }
-    };
+    }
+
+    pub fn with_ccel(mut self, ccel: Ccel) -> Self {
+        self.ccel = ccel;
+        self
+    }
+
+    fn is_simulator(&self) -> bool {
+        matches!(self.device.interface(), Ok(TdxInterface::Simulator))
+    }
 
-    let quote = match tdx_attest::get_tdx_quote(tdx_report_data) {
-        Err(e) => panic!("[get_tdx_quote] Fail to get TDX quote: {:?}", e),
-        Ok(q) => base64::encode(q),
+    fn td_report(&self) -> Result<TdReport> {
+        let report = self.get_report(&[0; 64])?;
+        TdReport::from_bytes(&report).map_err(Error::new)
+    }
+
+    //REPORTTYPE.version of a TD report, the module does not change while the TD runs
+    fn tdx_version(&self) -> Result<TdxVersion> {
+        if let Some(v) = self.tdx_version.get() {
+            return Ok(*v);
+        }
+        let version = match self.td_report()?.tdx_version() {
+            Err(e) => return Err(tdx_error(e, "[tdx_version] Fail to get TDX module version")),
+            Ok(v) => v,
+        };
+        Ok(*self.tdx_version.get_or_init(|| version))
+    }
+}
+
+fn tdx_configfs_available() -> bool {
+    let tsm = tdx_attest::configfs::ConfigfsTsm::default();
+    tsm.is_available() && matches!(tsm.provider(), Ok(p) if p == tdx_attest::configfs::TDX_PROVIDER)
+}
+
+//keep the TdxError as the root cause so that callers can classify the failure
+fn tdx_error(e: TdxError, context: &'static str) -> Error {
+    Error::new(e).context(context)
+}
+
+impl TeeBackend for TdxBackend {
+    fn tee_type(&self) -> TeeType {
+        TeeType::TDX
+    }
+
+    fn detect(&self) -> Result<bool> {
+        let detected = if self.is_simulator() || tdx_configfs_available() {
+            true
+        } else if Path::new("/dev/tdx-attest").exists() {
+            return Err(Error::new(TdxError::DeprecatedDevice(
+                "/dev/tdx-attest".to_string(),
+            )));
+        } else {
+            Path::new("/dev/tdx-guest").exists() || Path::new("/dev/tdx_guest").exists()
+        };
+        //cache the module version up front, it is read again on the first quote otherwise
+        if detected {
+            if let Err(e) = self.tdx_version() {
+                println!("TDX module version not known yet: {:?}", e);
+            }
+        }
+        Ok(detected)
+    }
+
+    fn get_evidence(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        self.device
+            .get_quote(report_data)
+            .map_err(|e| tdx_error(e, "[get_evidence] Fail to get TDX quote"))
+    }
+
+    //TDX module version of the TD and the version of the quote
+    fn evidence_version(&self, evidence: &[u8]) -> Result<EvidenceVersion> {
+        let quote = match Quote::from_bytes(evidence) {
+            Err(e) => return Err(tdx_error(e, "[evidence_version] Fail to parse TDX quote")),
+            Ok(q) => q,
+        };
+        let tdx_version = match self.tdx_version()? {
+            TdxVersion::TDX_1_0 => "1.0",
+            TdxVersion::TDX_1_5 => "1.5",
+        };
+        Ok(EvidenceVersion {
+            tee_version: Some(tdx_version.to_string()),
+            format_version: Some(quote.header.version as u32),
+        })
+    }
+
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        self.device
+            .get_td_report(report_data)
+            .map_err(|e| tdx_error(e, "[get_report] Fail to get TD report"))
+    }
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+        let td_info = self.td_report()?.td_info;
+        let mut registers = vec![("MRTD".to_string(), td_info.mrtd)];
+        for (i, rtmr) in td_info.rtmrs.iter().enumerate() {
+            registers.push((format!("RTMR{}", i), rtmr.0));
+        }
+        registers.push(("MRCONFIGID".to_string(), td_info.mrconfigid));
+        registers.push(("MROWNER".to_string(), td_info.mrowner));
+        registers.push(("MROWNERCONFIG".to_string(), td_info.mrownerconfig));
+        Ok(registers
+            .into_iter()
+            .map(|(name, digest)| MeasurementRegister {
+                name,
+                algorithm_id: TPM_ALG_SHA384,
+                digest: digest.to_vec(),
+            })
+            .collect())
+    }
+
+    //CCEL events of the firmware and boot chain followed by the runtime RTMR extensions
+    fn get_event_log(&self) -> Result<EventLog> {
+        //a simulated TD has no firmware, only runtime events
+        let mut log = if self.is_simulator() && !self.ccel.is_available() {
+            EventLog {
+                spec_id_event: runtime_spec_id_event(),
+                events: Vec::new(),
+            }
+        } else {
+            match self.ccel.read_event_log() {
+                Err(e) => return Err(tdx_error(e, "[get_event_log] Fail to read CCEL")),
+                Ok(log) => log,
+            }
+        };
+        log.events.extend(self.device.runtime_event_log().events());
+        Ok(log)
+    }
+}
+
+//Spec ID event of a log holding only the SHA-384 RTMR extensions made at runtime
+fn runtime_spec_id_event() -> SpecIdEvent {
+    SpecIdEvent {
+        platform_class: 0,
+        spec_version_minor: 0,
+        spec_version_major: 2,
+        spec_errata: 0,
+        uintn_size: 2,
+        digest_sizes: vec![AlgorithmDigestSize {
+            algorithm_id: TPM_ALG_SHA384,
+            digest_size: 48,
+        }],
+        vendor_info: Vec::new(),
+    }
+}
+
+//how the report data of the evidence is derived from the nonce and the user data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportDataMode {
+    Sha512,            // SHA-512(nonce || user_data)
+    Raw,               // User data as is, exactly 64 bytes
+    Sha384,            // SHA-384(nonce || user_data) zero-padded to 64 bytes
+    DomainSeparatedV1, // SHA-512 over the length-prefixed tag, nonce and user data
+}
+
+//tag of the version 1 domain-separated derivation, bumped whenever its encoding changes
+pub const REPORT_DATA_DOMAIN_V1: &[u8] = b"CCNP-REPORT-DATA-V1";
+
+fn decode_base64(name: &str, value: &str) -> Result<Vec<u8>, TdxError> {
+    match base64::decode(value) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(TdxError::BadReportData(format!(
+            "[generate_tdx_report_data] {} is not base64 encoded: {:?}",
+            name, e
+        ))),
+    }
+}
+
+fn generate_tdx_report_data(
+    report_data: Option<String>,
+    nonce: String,
+    mode: ReportDataMode,
+) -> Result<[u8; 64], TdxError> {
+    let nonce_decoded = decode_base64("nonce", &nonce)?;
+    let user_data = match report_data {
+        Some(_encoded_report_data) => decode_base64("user data", &_encoded_report_data)?,
+        None => Vec::new(),
     };
 
-    serde_json::to_string(&quote).map_err(|e| anyhow!("[get_tdx_quote]: {:?}", e))
+    let mut hash_array = [0u8; 64];
+    match mode {
+        ReportDataMode::Sha512 => {
+            let mut hasher = Sha512::new();
+            hasher.update(nonce_decoded);
+            hasher.update(user_data);
+            hash_array.copy_from_slice(&hasher.finalize());
+        }
+        ReportDataMode::Raw => {
+            //a nonce could not be bound, the caller must put the freshness into the user data
+            if !nonce_decoded.is_empty() {
+                return Err(TdxError::BadReportData(
+                    "[generate_tdx_report_data] nonce must be empty for raw report data"
+                        .to_string(),
+                ));
+            }
+            if user_data.len() != hash_array.len() {
+                return Err(TdxError::BadReportData(format!(
+                    "[generate_tdx_report_data] raw report data must be 64 bytes, got {}",
+                    user_data.len()
+                )));
+            }
+            hash_array.copy_from_slice(&user_data);
+        }
+        ReportDataMode::Sha384 => {
+            let mut hasher = Sha384::new();
+            hasher.update(nonce_decoded);
+            hasher.update(user_data);
+            hash_array[..48].copy_from_slice(&hasher.finalize());
+        }
+        ReportDataMode::DomainSeparatedV1 => {
+            //every field is prefixed with its length as u32 big endian, so that no two
+            //different nonce and user data pairs hash the same input
+            let mut hasher = Sha512::new();
+            for field in [REPORT_DATA_DOMAIN_V1, &nonce_decoded, &user_data] {
+                hasher.update((field.len() as u32).to_be_bytes());
+                hasher.update(field);
+            }
+            hash_array.copy_from_slice(&hasher.finalize());
+        }
+    }
+    Ok(hash_array)
 }
 
-fn get_tpm_quote() -> Result<String> {
-    Err(anyhow!("TPM to be supported!"))
+pub fn get_evidence(
+    tee: &dyn TeeBackend,
+    user_data: Option<String>,
+    nonce: String,
+    mode: ReportDataMode,
+) -> Result<Evidence> {
+    let report_data = generate_tdx_report_data(user_data, nonce, mode)?;
+    let data = tee.get_evidence(&report_data)?;
+    let version = tee.evidence_version(&data)?;
+    Ok(Evidence {
+        tee_type: tee.tee_type(),
+        data,
+        report_data,
+        version,
+        generated_at: SystemTime::now(),
+    })
 }
 
-fn get_sev_quote() -> Result<String> {
-    Err(anyhow!("SEV to be supported!"))
+//base64 of the evidence as a JSON string, the quote format of the v1 response.
+//the v1 response has no version fields, so nothing beyond the evidence is requested
+pub fn get_quote(
+    tee: &dyn TeeBackend,
+    user_data: Option<String>,
+    nonce: String,
+    mode: ReportDataMode,
+) -> Result<String> {
+    let report_data = generate_tdx_report_data(user_data, nonce, mode)?;
+    let quote = base64::encode(tee.get_evidence(&report_data)?);
+    serde_json::to_string(&quote).map_err(|e| anyhow!("[get_quote]: {:?}", e))
 }
 
-pub fn get_quote(local_tee: TeeType, user_data: String, nonce: String) -> Result<String> {
-    match local_tee {
-        TeeType::TDX => get_tdx_quote(Some(user_data), nonce),
-        TeeType::TPM => get_tpm_quote(),
-        TeeType::SEV => get_sev_quote(),
-        _ => Err(anyhow!("Unexpected case!")),
+//TD report for local attestation, raw and parsed. it does not need the quote generation service
+pub fn get_td_report(tee: &dyn TeeBackend, report_data: &[u8; 64]) -> Result<(Vec<u8>, TdReport)> {
+    if tee.tee_type() != TeeType::TDX {
+        return Err(anyhow!(
+            "[get_td_report] TD reports are provided by TDX only, not {:?}",
+            tee.tee_type()
+        ));
+    }
+    let raw = tee.get_report(report_data)?;
+    match TdReport::from_bytes(&raw) {
+        Err(e) => Err(tdx_error(e, "[get_td_report] Fail to parse TD report")),
+        Ok(report) => Ok((raw, report)),
     }
 }
 
@@ -113,26 +518,112 @@ pub fn get_quote(local_tee: TeeType, user_data: String, nonce: String) -> Result
 mod tests {
 
     use super::*;
+    use crate::tpm::TpmDevice;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use tdx_attest::qgs::{AttKeyId, QgsQuoteResponse};
+    use tdx_attest::{RuntimeEventLog, TdxSimulator};
+
+    fn simulator_backend() -> TdxBackend {
+        TdxBackend::new(Arc::new(TdxSimulator::default()))
+    }
+
+    //a device whose TD reports fail once quotes are served, e.g. a transient ioctl failure
+    struct QuoteOnlyDevice(TdxSimulator);
+
+    impl TdxDevice for QuoteOnlyDevice {
+        fn get_td_report(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+            Err(TdxError::Io("TD report unavailable".to_string()))
+        }
+
+        fn get_quote_with_key_ids(
+            &self,
+            report_data: &[u8; 64],
+            att_key_ids: &[AttKeyId],
+        ) -> Result<QgsQuoteResponse, TdxError> {
+            self.0.get_quote_with_key_ids(report_data, att_key_ids)
+        }
+
+        fn interface(&self) -> Result<TdxInterface, TdxError> {
+            self.0.interface()
+        }
+
+        fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+            self.0.extend_rtmr_register(index, digest)
+        }
+
+        fn runtime_event_log(&self) -> &RuntimeEventLog {
+            self.0.runtime_event_log()
+        }
+    }
+
+    //a simulator counting its TD reports, which fail once fail_reports is set
+    struct ReportCountingDevice {
+        simulator: TdxSimulator,
+        td_reports: AtomicU32,
+        fail_reports: AtomicBool,
+    }
+
+    impl TdxDevice for ReportCountingDevice {
+        fn get_td_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+            self.td_reports.fetch_add(1, Ordering::SeqCst);
+            if self.fail_reports.load(Ordering::SeqCst) {
+                return Err(TdxError::Io("TD report unavailable".to_string()));
+            }
+            self.simulator.get_td_report(report_data)
+        }
+
+        fn get_quote_with_key_ids(
+            &self,
+            report_data: &[u8; 64],
+            att_key_ids: &[AttKeyId],
+        ) -> Result<QgsQuoteResponse, TdxError> {
+            self.simulator
+                .get_quote_with_key_ids(report_data, att_key_ids)
+        }
+
+        fn interface(&self) -> Result<TdxInterface, TdxError> {
+            self.simulator.interface()
+        }
+
+        fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+            self.simulator.extend_rtmr_register(index, digest)
+        }
+
+        fn runtime_event_log(&self) -> &RuntimeEventLog {
+            self.simulator.runtime_event_log()
+        }
+    }
 
     #[test]
     //generate_tdx_report allow empty nonce
     fn generate_tdx_report_data_empty_nonce() {
-        let result = generate_tdx_report_data(Some("YWJjZGVmZw==".to_string()), "".to_string());
+        let result = generate_tdx_report_data(
+            Some("YWJjZGVmZw==".to_string()),
+            "".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
     //generate_tdx_report allow optional report data
     fn tdx_get_quote_report_data_no_report_data() {
-        let result = generate_tdx_report_data(None, "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = generate_tdx_report_data(
+            None,
+            "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
     //generate_tdx_report allow empty report data string
     fn generate_tdx_report_data_report_data_size_0() {
-        let result =
-            generate_tdx_report_data(Some("".to_string()), "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = generate_tdx_report_data(
+            Some("".to_string()),
+            "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_ok());
     }
 
@@ -142,6 +633,7 @@ mod tests {
         let result = generate_tdx_report_data(
             Some("YWJjZGVmZw==".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
@@ -154,6 +646,7 @@ mod tests {
         let result = generate_tdx_report_data(
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
@@ -165,6 +658,7 @@ mod tests {
         let result = generate_tdx_report_data(
             Some("XD^%*!x".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_err());
     }
@@ -176,6 +670,7 @@ mod tests {
         let result = generate_tdx_report_data(
             Some("IXUKoBO1XEFBPwopN4sY".to_string()),
             "XD^%*!x".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_err());
     }
@@ -184,8 +679,11 @@ mod tests {
     //generate_tdx_report require nonce string is base64 encoded
     fn generate_tdx_report_data_nonce_short_not_base64_encoded() {
         //coming in nonce should always be base64 encoded
-        let result =
-            generate_tdx_report_data(Some("IXUKoBO1XEFBPwopN4sY".to_string()), "123".to_string());
+        let result = generate_tdx_report_data(
+            Some("IXUKoBO1XEFBPwopN4sY".to_string()),
+            "123".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_err());
     }
 
@@ -193,8 +691,11 @@ mod tests {
     //generate_tdx_report require report data string is base64 encoded
     fn generate_tdx_report_data_report_data_short_not_base64_encoded() {
         //coming in report data should always be base64 encoded
-        let result =
-            generate_tdx_report_data(Some("123".to_string()), "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = generate_tdx_report_data(
+            Some("123".to_string()),
+            "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_err());
     }
 
@@ -202,17 +703,19 @@ mod tests {
     //generate_tdx_report check result as expected
     //original report_data = "abcdefgh", orginal nonce = "12345678"
     fn generate_tdx_report_data_report_data_nonce_base64_encoded_as_expected() {
-        let result =
-            generate_tdx_report_data(Some("YWJjZGVmZw==".to_string()), "MTIzNDU2Nzg=".to_string())
-                .unwrap();
+        let result = generate_tdx_report_data(
+            Some("YWJjZGVmZw==".to_string()),
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::Sha512,
+        )
+        .unwrap();
         let expected_hash = [
             93, 71, 28, 83, 115, 189, 166, 130, 87, 137, 126, 119, 140, 209, 163, 215, 13, 175,
             225, 101, 64, 195, 196, 202, 15, 37, 166, 241, 141, 49, 128, 157, 164, 132, 67, 50, 9,
             32, 162, 89, 243, 191, 177, 131, 4, 159, 156, 104, 11, 193, 18, 217, 92, 215, 194, 98,
             145, 191, 211, 85, 187, 118, 39, 80,
         ];
-        let generated_hash = base64::decode(result).unwrap();
-        assert_eq!(generated_hash, expected_hash);
+        assert_eq!(result, expected_hash);
     }
 
     #[test]
@@ -230,6 +733,7 @@ mod tests {
                     .to_string(),
             ),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
@@ -247,6 +751,7 @@ mod tests {
             Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2\
             NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4Cg=="
                 .to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
@@ -257,80 +762,178 @@ mod tests {
         let report_data_hashed = match generate_tdx_report_data(
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         ) {
             Ok(r) => r,
             Err(_) => todo!(),
         };
-        let generated_hash_len = base64::decode(report_data_hashed).unwrap().len();
+        let generated_hash_len = report_data_hashed.len();
         assert_eq!(generated_hash_len, 64);
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow empty nonce
+    //raw report data is passed through, it must be 64 bytes and come without nonce
+    fn generate_tdx_report_data_raw() {
+        let raw: Vec<u8> = (0..64).collect();
+        let result = generate_tdx_report_data(
+            Some(base64::encode(&raw)),
+            "".to_string(),
+            ReportDataMode::Raw,
+        )
+        .unwrap();
+        assert_eq!(result.to_vec(), raw);
+
+        let result = generate_tdx_report_data(
+            Some(base64::encode(&raw[..48])),
+            "".to_string(),
+            ReportDataMode::Raw,
+        );
+        assert!(matches!(result, Err(TdxError::BadReportData(_))));
+        let result = generate_tdx_report_data(
+            Some(base64::encode(&raw)),
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::Raw,
+        );
+        assert!(matches!(result, Err(TdxError::BadReportData(_))));
+    }
+
+    #[test]
+    //SHA-384 report data is zero-padded to 64 bytes
+    //original report_data = "abcdefg", orginal nonce = "12345678"
+    fn generate_tdx_report_data_sha384() {
+        let result = generate_tdx_report_data(
+            Some("YWJjZGVmZw==".to_string()),
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::Sha384,
+        )
+        .unwrap();
+        let expected_hash = [
+            218, 45, 189, 190, 195, 58, 126, 94, 13, 89, 24, 180, 188, 247, 252, 249, 237, 147, 16,
+            217, 244, 46, 19, 185, 49, 207, 191, 9, 7, 199, 164, 196, 107, 135, 219, 224, 187, 91,
+            115, 31, 205, 145, 188, 209, 18, 171, 31, 100,
+        ];
+        assert_eq!(result[..48], expected_hash);
+        assert_eq!(result[48..], [0; 16]);
+    }
+
+    #[test]
+    //domain-separated report data hashes the tag and the length-prefixed fields
+    //original report_data = "abcdefg", orginal nonce = "12345678"
+    fn generate_tdx_report_data_domain_separated_v1() {
+        let result = generate_tdx_report_data(
+            Some("YWJjZGVmZw==".to_string()),
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::DomainSeparatedV1,
+        )
+        .unwrap();
+        let expected_hash = [
+            47, 177, 92, 137, 86, 123, 228, 38, 50, 11, 97, 139, 27, 37, 136, 251, 133, 183, 129,
+            86, 55, 222, 197, 102, 213, 233, 159, 236, 106, 39, 32, 142, 253, 8, 124, 157, 59, 143,
+            211, 156, 162, 138, 0, 117, 166, 74, 249, 102, 229, 37, 133, 90, 14, 191, 19, 149, 4,
+            64, 132, 178, 26, 129, 78, 224,
+        ];
+        assert_eq!(result, expected_hash);
+
+        //moving bytes between nonce and user data changes the report data
+        let shifted = generate_tdx_report_data(
+            Some("OGFiY2RlZmc=".to_string()),
+            "MTIzNDU2Nw==".to_string(),
+            ReportDataMode::DomainSeparatedV1,
+        )
+        .unwrap();
+        assert_ne!(result, shifted);
+    }
+
+    #[test]
+    //tdx_get_quote allow empty nonce
     fn tdx_get_quote_empty_nonce() {
-        let result = get_tdx_quote(Some("YWJjZGVmZw==".to_string()), "".to_string());
+        let result = get_quote(
+            &simulator_backend(),
+            Some("YWJjZGVmZw==".to_string()),
+            "".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow 0 bytes report data string
+    //tdx_get_quote allow 0 bytes report data string
     fn tdx_get_quote_report_data_size_0() {
-        let result = get_tdx_quote(Some("".to_string()), "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = get_quote(
+            &simulator_backend(),
+            Some("".to_string()),
+            "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow 8 bytes report data string
+    //tdx_get_quote allow 8 bytes report data string
     fn tdx_get_quote_report_data_size_8() {
         // "YWJjZGVmZw==" is base64 of "abcdefg", 8 bytes
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &simulator_backend(),
             Some("YWJjZGVmZw==".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow 48 bytes report data string
+    //tdx_get_quote allow 48 bytes report data string
     fn tdx_get_quote_report_data_size_48() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &simulator_backend(),
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow optional report data
+    //tdx_get_quote allow optional report data
     fn tdx_get_quote_report_data_null() {
-        let result = get_tdx_quote(None, "IXUKoBO1XEFBPwopN4sY".to_string());
+        let result = get_quote(
+            &simulator_backend(),
+            None,
+            "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote require report data string is base64 encoded
+    //tdx_get_quote require report data string is base64 encoded
     fn tdx_get_quote_report_data_not_base64_encoded() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &simulator_backend(),
             Some("XD^%*!x".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_err());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote require nonce string is base64 encoded
+    //tdx_get_quote require nonce string is base64 encoded
     fn tdx_get_quote_nonce_not_base64_encoded() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &simulator_backend(),
             Some("IXUKoBO1XEFBPwopN4sY".to_string()),
             "XD^%*!x".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_err());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow long report data string
+    //tdx_get_quote allow long report data string
     fn tdx_get_quote_long_tdx_report_data() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &simulator_backend(),
             Some(
                 "MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2Nzgx\
                 MjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEy\
@@ -342,14 +945,16 @@ mod tests {
                     .to_string(),
             ),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: tdx_get_quote allow long nonce string
+    //tdx_get_quote allow long nonce string
     fn tdx_get_quote_long_nonce() {
-        let result = get_tdx_quote(
+        let result = get_quote(
+            &simulator_backend(),
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2Nzgx\
             MjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEy\
@@ -359,16 +964,19 @@ mod tests {
             Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2\
             NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4Cg=="
                 .to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_ok());
     }
 
     #[test]
-    //TDX ENV required: get_tdx_quote return non-empty encoded quote string
+    //get_tdx_quote return non-empty encoded quote string
     fn tdx_get_quote_report_data_encoded_quote_is_not_0_bytes() {
-        let quote = match get_tdx_quote(
+        let quote = match get_quote(
+            &simulator_backend(),
             Some("MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4MTIzNDU2NzgxMjM0NTY3ODEyMzQ1Njc4".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         ) {
             Ok(r) => r,
             Err(_) => todo!(),
@@ -377,49 +985,275 @@ mod tests {
     }
 
     #[test]
-    //get_quote does not allow tee type beyond TDX/SEV/TPM
-    fn get_quote_wrong_tee_type() {
+    //SEV backend does not provide evidence for now
+    fn get_quote_sev_tee_type() {
         let result = get_quote(
-            TeeType::PLAIN,
-            "".to_string(),
+            &SevBackend::default(),
+            Some("".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
         assert!(result.is_err());
     }
 
     #[test]
-    //get_quote does not support SEV for now
-    fn get_quote_sev_tee_type() {
-        //does not allow tee type beyond TDX/SEV/TPM
+    //get_quote support TDX now
+    fn get_quote_tdx_tee_type() {
         let result = get_quote(
-            TeeType::SEV,
-            "".to_string(),
+            &simulator_backend(),
+            Some("".to_string()),
             "IXUKoBO1XEFBPwopN4sY".to_string(),
+            ReportDataMode::Sha512,
         );
-        assert!(result.is_err());
+        assert!(result.is_ok());
     }
 
     #[test]
-    //get_quote does not support TPM for now
-    fn get_quote_tpm_tee_type() {
-        //does not allow tee type beyond TDX/SEV/TPM
-        let result = get_quote(
-            TeeType::TPM,
-            "".to_string(),
-            "IXUKoBO1XEFBPwopN4sY".to_string(),
+    //TDX evidence comes with the report data it is bound to and the TDX and quote versions
+    fn tdx_get_evidence() {
+        let evidence = get_evidence(
+            &simulator_backend(),
+            Some("YWJjZGVmZw==".to_string()),
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::Sha512,
+        )
+        .unwrap();
+        assert_eq!(evidence.tee_type, TeeType::TDX);
+        let quote = Quote::from_bytes(&evidence.data).unwrap();
+        assert_eq!(quote.report_data(), &evidence.report_data);
+        assert_eq!(
+            evidence.report_data,
+            generate_tdx_report_data(
+                Some("YWJjZGVmZw==".to_string()),
+                "MTIzNDU2Nzg=".to_string(),
+                ReportDataMode::Sha512
+            )
+            .unwrap()
+        );
+        assert_eq!(evidence.version.tee_version, Some("1.0".to_string()));
+        assert_eq!(
+            evidence.version.format_version,
+            Some(quote.header.version as u32)
         );
-        assert!(result.is_err());
     }
 
     #[test]
-    //get_quote support TDX now
-    fn get_quote_tdx_tee_type() {
-        //does not allow tee type beyond TDX/SEV/TPM
-        let result = get_quote(
-            TeeType::TDX,
-            "".to_string(),
-            "IXUKoBO1XEFBPwopN4sY".to_string(),
+    //v1 quotes need no TD report, the v2 module version cannot be told without one
+    fn tdx_quote_without_td_report() {
+        let tee = TdxBackend::new(Arc::new(QuoteOnlyDevice(TdxSimulator::default())));
+        assert!(get_td_report(&tee, &[0; 64]).is_err());
+
+        let quote = get_quote(
+            &tee,
+            None,
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::Sha512,
         );
-        assert!(result.is_ok());
+        assert!(quote.is_ok());
+        let evidence = get_evidence(
+            &tee,
+            None,
+            "MTIzNDU2Nzg=".to_string(),
+            ReportDataMode::Sha512,
+        );
+        assert!(evidence.is_err());
+    }
+
+    #[test]
+    //the module version is read from one TD report at detection and cached afterwards
+    fn tdx_version_cached() {
+        let device = Arc::new(ReportCountingDevice {
+            simulator: TdxSimulator::default(),
+            td_reports: AtomicU32::new(0),
+            fail_reports: AtomicBool::new(false),
+        });
+        let tee = TdxBackend::new(device.clone());
+        assert!(tee.detect().unwrap());
+        assert_eq!(device.td_reports.load(Ordering::SeqCst), 1);
+
+        //later TD report failures do not affect the quotes
+        device.fail_reports.store(true, Ordering::SeqCst);
+        for _ in 0..3 {
+            let evidence = get_evidence(
+                &tee,
+                None,
+                "MTIzNDU2Nzg=".to_string(),
+                ReportDataMode::Sha512,
+            )
+            .unwrap();
+            assert_eq!(evidence.version.tee_version, Some("1.0".to_string()));
+            assert_eq!(evidence.version.format_version, Some(4));
+        }
+        assert_eq!(device.td_reports.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    //TD reports are bound to the given report data, other TEEs have none
+    fn tdx_get_td_report() {
+        let (raw, report) = get_td_report(&simulator_backend(), &[0x5a; 64]).unwrap();
+        assert_eq!(raw.len(), 1024);
+        assert_eq!(report.report_data(), &[0x5a; 64]);
+        assert_eq!(TdReport::from_bytes(&raw).unwrap(), report);
+
+        assert!(get_td_report(&SevBackend::default(), &[0x5a; 64]).is_err());
+    }
+
+    #[test]
+    //the registry returns every backend whose TEE is present, none on plain platforms
+    fn registry_detect_all() {
+        let missing_tpm = TpmBackend::new(TpmDevice::Path("/nonexistent/tpmrm0".to_string()));
+        let tees = TeeRegistry::new()
+            .with_backend(Box::new(missing_tpm))
+            .with_backend(Box::new(simulator_backend()))
+            .detect_all(None)
+            .unwrap();
+        let types: Vec<TeeType> = tees.iter().map(|t| t.tee_type()).collect();
+        assert_eq!(types, [TeeType::TDX]);
+
+        assert!(TeeRegistry::new().detect_all(None).is_err());
+    }
+
+    //a backend whose detection fails, e.g. on an unsupported device node
+    struct DetectFailure;
+
+    impl TeeBackend for DetectFailure {
+        fn tee_type(&self) -> TeeType {
+            TeeType::SEV
+        }
+
+        fn detect(&self) -> Result<bool> {
+            Err(anyhow!("unsupported device"))
+        }
+
+        fn get_evidence(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+            unreachable!()
+        }
+
+        fn get_report(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+            unreachable!()
+        }
+
+        fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+            unreachable!()
+        }
+
+        fn get_event_log(&self) -> Result<EventLog> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    //a backend failing to detect is skipped, the registry fails only without any TEE
+    fn registry_detect_all_skips_failures() {
+        let tees = TeeRegistry::new()
+            .with_backend(Box::new(DetectFailure))
+            .with_backend(Box::new(simulator_backend()))
+            .detect_all(Some(TeeType::SEV))
+            .unwrap();
+        let types: Vec<TeeType> = tees.iter().map(|t| t.tee_type()).collect();
+        assert_eq!(types, [TeeType::TDX]);
+
+        let error = match TeeRegistry::new()
+            .with_backend(Box::new(DetectFailure))
+            .detect_all(None)
+        {
+            Err(e) => e,
+            Ok(_) => panic!("no TEE detected without an error"),
+        };
+        assert!(error.to_string().contains("SEV: unsupported device"));
+    }
+
+    #[test]
+    //the preferred TEE comes first when present, registration order is kept otherwise
+    fn registry_detect_all_preferred() {
+        //a configured swtpm is detected without connecting to it
+        let registry = || {
+            TeeRegistry::new()
+                .with_backend(Box::new(simulator_backend()))
+                .with_backend(Box::new(TpmBackend::new(TpmDevice::Swtpm(
+                    "127.0.0.1:2321".to_string(),
+                ))))
+        };
+        let types = |tees: Vec<Box<dyn TeeBackend>>| -> Vec<TeeType> {
+            tees.iter().map(|t| t.tee_type()).collect()
+        };
+
+        assert_eq!(
+            types(registry().detect_all(None).unwrap()),
+            [TeeType::TDX, TeeType::TPM]
+        );
+        assert_eq!(
+            types(registry().detect_all(Some(TeeType::TPM)).unwrap()),
+            [TeeType::TPM, TeeType::TDX]
+        );
+        assert_eq!(
+            types(registry().detect_all(Some(TeeType::SEV)).unwrap()),
+            [TeeType::TDX, TeeType::TPM]
+        );
+    }
+
+    #[test]
+    //TEE types are parsed regardless of case
+    fn tee_type_from_str() {
+        assert_eq!("tdx".parse::<TeeType>().unwrap(), TeeType::TDX);
+        assert_eq!("SEV".parse::<TeeType>().unwrap(), TeeType::SEV);
+        assert_eq!("Tpm".parse::<TeeType>().unwrap(), TeeType::TPM);
+        assert!("plain".parse::<TeeType>().is_err());
+    }
+
+    #[test]
+    //the operator overrides the retries and deadline of TDX quotes
+    fn retry_policy_from_env_values() {
+        assert_eq!(
+            retry_policy_from(None, None).unwrap(),
+            RetryPolicy::default()
+        );
+        assert_eq!(
+            retry_policy_from(Some(""), Some("")).unwrap(),
+            RetryPolicy::default()
+        );
+
+        let policy = retry_policy_from(Some("2"), Some("10")).unwrap();
+        assert_eq!(policy.max_retries, 2);
+        assert_eq!(policy.timeout, Duration::from_secs(10));
+        assert_eq!(
+            policy.initial_backoff,
+            RetryPolicy::default().initial_backoff
+        );
+
+        assert!(retry_policy_from(Some("-1"), None).is_err());
+        assert!(retry_policy_from(None, Some("soon")).is_err());
+        assert!(retry_policy_from(None, Some("0")).is_err());
+    }
+
+    #[test]
+    //TDX measurements and runtime events are served from the device
+    fn tdx_backend_measurements_and_event_log() {
+        let simulator = Arc::new(TdxSimulator::default());
+        simulator.extend_rtmr(3, &[0x33; 48]).unwrap();
+        let tee = TdxBackend::new(simulator);
+
+        let registers = tee.get_measurements().unwrap();
+        let names: Vec<&str> = registers.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "MRTD",
+                "RTMR0",
+                "RTMR1",
+                "RTMR2",
+                "RTMR3",
+                "MRCONFIGID",
+                "MROWNER",
+                "MROWNERCONFIG"
+            ]
+        );
+        assert_eq!(registers[0].digest, vec![0x4d; 48]);
+        assert_ne!(registers[4].digest, vec![0; 48]);
+
+        let log = tee.get_event_log().unwrap();
+        assert_eq!(log.spec_id_event, runtime_spec_id_event());
+        assert_eq!(log.events.len(), 1);
+        assert_eq!(log.events[0].rtmr_index(), Some(3));
     }
 }
@@ -0,0 +1,994 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::tee::{MeasurementRegister, TeeBackend, TeeCapabilities, TeeType};
+use anyhow::*;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::result::Result::Ok;
+use std::sync::Mutex;
+use tdx_attest::eventlog::{
+    EventLog, TPM_ALG_SHA1, TPM_ALG_SHA256, TPM_ALG_SHA384, TPM_ALG_SHA512,
+};
+
+pub const TPM_DEVICE_PATH: &str = "/dev/tpmrm0";
+pub const TPM_EVENT_LOG_PATH: &str = "/sys/kernel/security/tpm0/binary_bios_measurements";
+//persistent handle the attestation key is created at or loaded from
+pub const DEFAULT_AK_HANDLE: u32 = 0x8101_0002;
+//TPM device path, or swtpm:HOST:PORT for the TCP command port of a swtpm
+const TPM_DEVICE_ENV: &str = "TPM_ATTEST_DEVICE";
+//PCR selection in the tpm2-tools format, e.g. sha256:0,1,2,3+sha384:0
+const TPM_PCRS_ENV: &str = "TPM_ATTEST_PCRS";
+const SWTPM_PREFIX: &str = "swtpm:";
+const DEFAULT_PCR_SELECTION: &str =
+    "sha256:0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23";
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_ST_ATTEST_QUOTE: u16 = 0x8018;
+const TPM_CC_EVICT_CONTROL: u32 = 0x0120;
+const TPM_CC_CREATE_PRIMARY: u32 = 0x0131;
+const TPM_CC_QUOTE: u32 = 0x0158;
+const TPM_CC_FLUSH_CONTEXT: u32 = 0x0165;
+const TPM_CC_READ_PUBLIC: u32 = 0x0173;
+const TPM_CC_PCR_READ: u32 = 0x017e;
+const TPM_RH_OWNER: u32 = 0x4000_0001;
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_GENERATED_VALUE: u32 = 0xff54_4347;
+const TPM_ALG_ECC: u16 = 0x0023;
+const TPM_ALG_ECDSA: u16 = 0x0018;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+//fixedTPM | fixedParent | sensitiveDataOrigin | userWithAuth | restricted | sign
+const AK_OBJECT_ATTRIBUTES: u32 = 0x0005_0072;
+const PCR_SELECT_LEN: usize = 3;
+const PCR_COUNT: u32 = 24;
+const TPM_RESPONSE_HEADER_LEN: usize = 10;
+const TPM_MAX_RESPONSE_LEN: usize = 4096;
+//PCRs extended between reading and quoting them are read again
+const QUOTE_ATTEMPTS: usize = 3;
+const SWTPM_SEND_COMMAND: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcrSelection {
+    pub hash_alg: u16,  // TPM_ALG_ID of the PCR bank
+    pub pcrs: Vec<u32>, // Sorted PCR indexes
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcrValue {
+    pub hash_alg: u16,
+    pub index: u32,
+    pub digest: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TpmQuote {
+    pub attest: Vec<u8>,     // TPMS_ATTEST signed by the attestation key
+    pub signature: Vec<u8>,  // TPMT_SIGNATURE over attest
+    pub pcrs: Vec<PcrValue>, // Values of the quoted PCRs
+    pub ak_public: Vec<u8>,  // TPMT_PUBLIC of the attestation key
+}
+
+impl TpmQuote {
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let pcrs: Vec<serde_json::Value> = self
+            .pcrs
+            .iter()
+            .map(|p| {
+                serde_json::json!({
+                    "bank": hash_alg_name(p.hash_alg),
+                    "index": p.index,
+                    "digest": base64::encode(&p.digest),
+                })
+            })
+            .collect();
+        let quote = serde_json::json!({
+            "attest": base64::encode(&self.attest),
+            "signature": base64::encode(&self.signature),
+            "pcrs": pcrs,
+            "ak_public": base64::encode(&self.ak_public),
+        });
+        serde_json::to_vec(&quote).map_err(|e| anyhow!("[to_json]: {:?}", e))
+    }
+}
+
+//bank name as used by tpm2-tools, e.g. sha256
+pub fn hash_alg_name(hash_alg: u16) -> String {
+    match hash_alg {
+        TPM_ALG_SHA1 => "sha1".to_string(),
+        TPM_ALG_SHA256 => "sha256".to_string(),
+        TPM_ALG_SHA384 => "sha384".to_string(),
+        TPM_ALG_SHA512 => "sha512".to_string(),
+        _ => format!("{:#06x}", hash_alg),
+    }
+}
+
+//parse a selection like sha256:0,1,7+sha384:0
+pub fn parse_pcr_selection(selection: &str) -> Result<Vec<PcrSelection>> {
+    let mut banks = Vec::new();
+    for bank in selection.split('+') {
+        let (name, pcrs) = match bank.split_once(':') {
+            Some(b) => b,
+            None => {
+                return Err(anyhow!(
+                    "[parse_pcr_selection] Missing PCR list in {}",
+                    bank
+                ))
+            }
+        };
+        let hash_alg = match name {
+            "sha1" => TPM_ALG_SHA1,
+            "sha256" => TPM_ALG_SHA256,
+            "sha384" => TPM_ALG_SHA384,
+            "sha512" => TPM_ALG_SHA512,
+            _ => return Err(anyhow!("[parse_pcr_selection] Unknown PCR bank {}", name)),
+        };
+        let mut indexes = Vec::new();
+        for pcr in pcrs.split(',') {
+            match pcr.trim().parse::<u32>() {
+                Ok(i) if i < PCR_COUNT => indexes.push(i),
+                _ => return Err(anyhow!("[parse_pcr_selection] Invalid PCR index {}", pcr)),
+            }
+        }
+        indexes.sort_unstable();
+        indexes.dedup();
+        banks.push(PcrSelection {
+            hash_alg,
+            pcrs: indexes,
+        });
+    }
+    Ok(banks)
+}
+
+//a channel carrying TPM command and response buffers
+pub trait TpmTransport: Send {
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>>;
+}
+
+//the kernel resource manager, one command per write and one response per read
+pub struct DeviceTransport {
+    device: File,
+}
+
+impl DeviceTransport {
+    pub fn open(path: &str) -> Result<Self> {
+        match File::options().read(true).write(true).open(path) {
+            Err(e) => Err(anyhow!("[open] Fail to open {}: {:?}", path, e)),
+            Ok(device) => Ok(DeviceTransport { device }),
+        }
+    }
+}
+
+impl TpmTransport for DeviceTransport {
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        self.device.write_all(command)?;
+        let mut response = vec![0u8; TPM_MAX_RESPONSE_LEN];
+        let len = self.device.read(&mut response)?;
+        response.truncate(len);
+        Ok(response)
+    }
+}
+
+//the TCP command port of swtpm, which frames commands like the TPM reference simulator
+pub struct SwtpmTransport {
+    stream: TcpStream,
+}
+
+impl SwtpmTransport {
+    pub fn connect(address: &str) -> Result<Self> {
+        match TcpStream::connect(address) {
+            Err(e) => Err(anyhow!(
+                "[connect] Fail to connect swtpm {}: {:?}",
+                address,
+                e
+            )),
+            Ok(stream) => Ok(SwtpmTransport { stream }),
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let mut value = [0u8; 4];
+        self.stream.read_exact(&mut value)?;
+        Ok(u32::from_be_bytes(value))
+    }
+}
+
+impl TpmTransport for SwtpmTransport {
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        let mut frame = SWTPM_SEND_COMMAND.to_be_bytes().to_vec();
+        frame.push(0); // Locality
+        frame.extend_from_slice(&(command.len() as u32).to_be_bytes());
+        frame.extend_from_slice(command);
+        self.stream.write_all(&frame)?;
+
+        let len = self.read_u32()? as usize;
+        if len > TPM_MAX_RESPONSE_LEN {
+            return Err(anyhow!("[transmit] swtpm response of {} bytes", len));
+        }
+        let mut response = vec![0u8; len];
+        self.stream.read_exact(&mut response)?;
+        //every response is acknowledged with a trailing zero
+        self.read_u32()?;
+        Ok(response)
+    }
+}
+
+struct Command {
+    buffer: Vec<u8>,
+}
+
+impl Command {
+    fn new(tag: u16, command_code: u32) -> Self {
+        let mut buffer = tag.to_be_bytes().to_vec();
+        buffer.extend_from_slice(&[0; 4]);
+        buffer.extend_from_slice(&command_code.to_be_bytes());
+        Command { buffer }
+    }
+
+    fn u8(mut self, value: u8) -> Self {
+        self.buffer.push(value);
+        self
+    }
+
+    fn u16(mut self, value: u16) -> Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn bytes(mut self, value: &[u8]) -> Self {
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    fn tpm2b(self, value: &[u8]) -> Self {
+        self.u16(value.len() as u16).bytes(value)
+    }
+
+    //authorization area with an empty password session
+    fn password_session(self) -> Self {
+        self.u32(9).u32(TPM_RS_PW).tpm2b(&[]).u8(0).tpm2b(&[])
+    }
+
+    fn pcr_selection(mut self, selection: &[PcrSelection]) -> Self {
+        self.buffer
+            .extend_from_slice(&marshal_pcr_selection(selection));
+        self
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.buffer.len() as u32;
+        self.buffer[2..6].copy_from_slice(&len.to_be_bytes());
+        self.buffer
+    }
+}
+
+//TPML_PCR_SELECTION
+fn marshal_pcr_selection(selection: &[PcrSelection]) -> Vec<u8> {
+    let mut buffer = (selection.len() as u32).to_be_bytes().to_vec();
+    for bank in selection {
+        let mut bitmap = [0u8; PCR_SELECT_LEN];
+        for pcr in bank.pcrs.iter() {
+            bitmap[*pcr as usize / 8] |= 1 << (pcr % 8);
+        }
+        buffer.extend_from_slice(&bank.hash_alg.to_be_bytes());
+        buffer.push(PCR_SELECT_LEN as u8);
+        buffer.extend_from_slice(&bitmap);
+    }
+    buffer
+}
+
+struct ResponseReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ResponseReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ResponseReader { data, offset: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.offset < len {
+            return Err(anyhow!(
+                "[ResponseReader] Response truncated at offset {}",
+                self.offset
+            ));
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    fn tpm2b(&mut self) -> Result<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    //TPML_PCR_SELECTION
+    fn pcr_selection(&mut self) -> Result<Vec<PcrSelection>> {
+        let count = self.u32()?;
+        let mut selection = Vec::new();
+        for _ in 0..count {
+            let hash_alg = self.u16()?;
+            let select_len = self.u8()? as usize;
+            let bitmap = self.bytes(select_len)?;
+            let pcrs = (0..select_len as u32 * 8)
+                .filter(|i| bitmap[*i as usize / 8] & (1 << (i % 8)) != 0)
+                .collect();
+            selection.push(PcrSelection { hash_alg, pcrs });
+        }
+        Ok(selection)
+    }
+}
+
+//AK template: restricted ECDSA P-256 signing key with SHA256 name and signatures
+fn ak_template() -> Vec<u8> {
+    Command { buffer: Vec::new() }
+        .u16(TPM_ALG_ECC)
+        .u16(TPM_ALG_SHA256)
+        .u32(AK_OBJECT_ATTRIBUTES)
+        .tpm2b(&[]) // authPolicy
+        .u16(TPM_ALG_NULL) // symmetric
+        .u16(TPM_ALG_ECDSA)
+        .u16(TPM_ALG_SHA256)
+        .u16(TPM_ECC_NIST_P256)
+        .u16(TPM_ALG_NULL) // kdf
+        .tpm2b(&[]) // unique.x
+        .tpm2b(&[]) // unique.y
+        .buffer
+}
+
+//TPM2 commands over a transport
+pub struct Tpm {
+    transport: Box<dyn TpmTransport>,
+}
+
+impl Tpm {
+    pub fn new(_transport: Box<dyn TpmTransport>) -> Self {
+        Tpm {
+            transport: _transport,
+        }
+    }
+
+    //send the command and return the response after the header
+    fn execute(&mut self, name: &str, command: Vec<u8>) -> Result<Vec<u8>> {
+        let response = self.transport.transmit(&command)?;
+        let mut reader = ResponseReader::new(&response);
+        let (_tag, len, rc) = match (reader.u16(), reader.u32(), reader.u32()) {
+            (Ok(tag), Ok(len), Ok(rc)) => (tag, len as usize, rc),
+            _ => return Err(anyhow!("[{}] Response of {} bytes", name, response.len())),
+        };
+        if len != response.len() {
+            return Err(anyhow!(
+                "[{}] Response declares {} bytes, got {}",
+                name,
+                len,
+                response.len()
+            ));
+        }
+        if rc != 0 {
+            return Err(anyhow!("[{}] TPM response code {:#x}", name, rc));
+        }
+        Ok(response[TPM_RESPONSE_HEADER_LEN..].to_vec())
+    }
+
+    //TPMT_PUBLIC of a loaded or persistent object
+    pub fn read_public(&mut self, handle: u32) -> Result<Vec<u8>> {
+        let command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_READ_PUBLIC)
+            .u32(handle)
+            .finish();
+        let response = self.execute("read_public", command)?;
+        Ok(ResponseReader::new(&response).tpm2b()?.to_vec())
+    }
+
+    //the attestation key at the persistent handle, created there on first use
+    pub fn load_or_create_ak(&mut self, handle: u32) -> Result<Vec<u8>> {
+        if let Ok(public) = self.read_public(handle) {
+            return Ok(public);
+        }
+
+        let template = ak_template();
+        let command = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE_PRIMARY)
+            .u32(TPM_RH_OWNER)
+            .password_session()
+            .tpm2b(&[0; 4]) // inSensitive, empty userAuth and data
+            .tpm2b(&template)
+            .tpm2b(&[]) // outsideInfo
+            .u32(0) // creationPCR
+            .finish();
+        let response = self.execute("create_primary", command)?;
+        let mut reader = ResponseReader::new(&response);
+        let transient = reader.u32()?;
+        let _parameter_size = reader.u32()?;
+        let public = reader.tpm2b()?.to_vec();
+
+        let command = Command::new(TPM_ST_SESSIONS, TPM_CC_EVICT_CONTROL)
+            .u32(TPM_RH_OWNER)
+            .u32(transient)
+            .password_session()
+            .u32(handle)
+            .finish();
+        let persisted = self.execute("evict_control", command);
+        //the transient copy is not needed either way
+        let command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_FLUSH_CONTEXT)
+            .u32(transient)
+            .finish();
+        self.execute("flush_context", command)?;
+        persisted?;
+        Ok(public)
+    }
+
+    //PCR values in selection order, read in as many rounds as the TPM needs
+    pub fn read_pcrs(&mut self, selection: &[PcrSelection]) -> Result<Vec<PcrValue>> {
+        let mut remaining = selection.to_vec();
+        let mut values = Vec::new();
+        while remaining.iter().any(|b| !b.pcrs.is_empty()) {
+            let command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_PCR_READ)
+                .pcr_selection(&remaining)
+                .finish();
+            let response = self.execute("read_pcrs", command)?;
+            let mut reader = ResponseReader::new(&response);
+            let _update_counter = reader.u32()?;
+            let selected = reader.pcr_selection()?;
+            let digest_count = reader.u32()?;
+            if digest_count == 0 {
+                return Err(anyhow!("[read_pcrs] No PCR read from the selection"));
+            }
+
+            for bank in selected.iter() {
+                for index in bank.pcrs.iter() {
+                    values.push(PcrValue {
+                        hash_alg: bank.hash_alg,
+                        index: *index,
+                        digest: reader.tpm2b()?.to_vec(),
+                    });
+                    for r in remaining.iter_mut().filter(|r| r.hash_alg == bank.hash_alg) {
+                        r.pcrs.retain(|i| i != index);
+                    }
+                }
+            }
+        }
+
+        let position = |v: &PcrValue| selection.iter().position(|b| b.hash_alg == v.hash_alg);
+        values.sort_by_key(|v| (position(v), v.index));
+        Ok(values)
+    }
+
+    //TPM2_Quote over the selection with SHA-256 of the report data as qualifyingData.
+    //TPM2B_DATA holds at most sizeof(TPMT_HA), 50 bytes on a TPM without a SHA-512 bank,
+    //so the 64 bytes of report data are bound through their digest, which always fits.
+    //verifiers compare extraData of the TPMS_ATTEST with SHA-256 of the expected report data
+    pub fn quote(
+        &mut self,
+        ak_handle: u32,
+        report_data: &[u8],
+        selection: &[PcrSelection],
+    ) -> Result<TpmQuote> {
+        let ak_public = self.load_or_create_ak(ak_handle)?;
+        let qualifying_data = qualifying_data(report_data);
+        for _ in 0..QUOTE_ATTEMPTS {
+            let pcrs = self.read_pcrs(selection)?;
+            let command = Command::new(TPM_ST_SESSIONS, TPM_CC_QUOTE)
+                .u32(ak_handle)
+                .password_session()
+                .tpm2b(&qualifying_data)
+                .u16(TPM_ALG_NULL) // inScheme, the scheme of the key
+                .pcr_selection(selection)
+                .finish();
+            let response = self.execute("quote", command)?;
+            let mut reader = ResponseReader::new(&response);
+            let parameter_size = reader.u32()? as usize;
+            let attest = reader.tpm2b()?.to_vec();
+            let signature_len = match parameter_size.checked_sub(2 + attest.len()) {
+                Some(l) => l,
+                None => return Err(anyhow!("[quote] Malformed quote response")),
+            };
+            let signature = reader.bytes(signature_len)?.to_vec();
+
+            let pcr_digest = quoted_pcr_digest(&attest, &qualifying_data)?;
+            let mut hasher = Sha256::new();
+            for pcr in pcrs.iter() {
+                hasher.update(&pcr.digest);
+            }
+            if hasher.finalize()[..] == pcr_digest[..] {
+                return Ok(TpmQuote {
+                    attest,
+                    signature,
+                    pcrs,
+                    ak_public,
+                });
+            }
+        }
+        Err(anyhow!(
+            "[quote] PCRs kept changing while quoting, gave up after {} attempts",
+            QUOTE_ATTEMPTS
+        ))
+    }
+}
+
+//the qualifyingData a quote over the report data carries as extraData
+pub fn qualifying_data(report_data: &[u8]) -> Vec<u8> {
+    Sha256::digest(report_data).to_vec()
+}
+
+//pcrDigest of a TPMS_ATTEST of a quote, after checking it carries the qualifyingData
+fn quoted_pcr_digest(attest: &[u8], qualifying_data: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = ResponseReader::new(attest);
+    if reader.u32()? != TPM_GENERATED_VALUE || reader.u16()? != TPM_ST_ATTEST_QUOTE {
+        return Err(anyhow!(
+            "[quoted_pcr_digest] TPMS_ATTEST is not a TPM quote"
+        ));
+    }
+    let _qualified_signer = reader.tpm2b()?;
+    if reader.tpm2b()? != qualifying_data {
+        return Err(anyhow!(
+            "[quoted_pcr_digest] TPMS_ATTEST does not carry the report data"
+        ));
+    }
+    let _clock = reader.bytes(17)?;
+    let _firmware_version = reader.u64()?;
+    let _pcr_selection = reader.pcr_selection()?;
+    Ok(reader.tpm2b()?.to_vec())
+}
+
+pub enum TpmDevice {
+    Path(String),
+    Swtpm(String), // HOST:PORT of the swtpm command port
+}
+
+impl TpmDevice {
+    fn from_env() -> Self {
+        match env::var(TPM_DEVICE_ENV) {
+            Ok(v) => match v.strip_prefix(SWTPM_PREFIX) {
+                Some(address) => TpmDevice::Swtpm(address.to_string()),
+                None => TpmDevice::Path(v),
+            },
+            Err(_) => TpmDevice::Path(TPM_DEVICE_PATH.to_string()),
+        }
+    }
+
+    fn open(&self) -> Result<Tpm> {
+        let transport: Box<dyn TpmTransport> = match self {
+            TpmDevice::Path(path) => Box::new(DeviceTransport::open(path)?),
+            TpmDevice::Swtpm(address) => Box::new(SwtpmTransport::connect(address)?),
+        };
+        Ok(Tpm::new(transport))
+    }
+}
+
+pub struct TpmBackend {
+    device: TpmDevice,
+    pcr_selection: Vec<PcrSelection>,
+    ak_handle: u32,
+    event_log_path: String,
+    tpm: Mutex<Option<Tpm>>,
+}
+
+impl Default for TpmBackend {
+    fn default() -> Self {
+        let selection = env::var(TPM_PCRS_ENV).unwrap_or(DEFAULT_PCR_SELECTION.to_string());
+        let pcr_selection = match parse_pcr_selection(&selection) {
+            Err(e) => panic!("[TpmBackend] Invalid {}: {}", TPM_PCRS_ENV, e),
+            Ok(s) => s,
+        };
+        TpmBackend::new(TpmDevice::from_env()).with_pcr_selection(pcr_selection)
+    }
+}
+
+impl TpmBackend {
+    pub fn new(_device: TpmDevice) -> Self {
+        TpmBackend {
+            device: _device,
+            pcr_selection: parse_pcr_selection(DEFAULT_PCR_SELECTION)
+                .expect("[TpmBackend] Malformed default PCR selection"),
+            ak_handle: DEFAULT_AK_HANDLE,
+            event_log_path: TPM_EVENT_LOG_PATH.to_string(),
+            tpm: Mutex::new(None),
+        }
+    }
+
+    pub fn with_pcr_selection(mut self, pcr_selection: Vec<PcrSelection>) -> Self {
+        self.pcr_selection = pcr_selection;
+        self
+    }
+
+    //run the commands on the open TPM, a failed connection is reopened on the next request
+    fn with_tpm<T, F>(&self, op: F) -> Result<T>
+    where
+        F: FnOnce(&mut Tpm) -> Result<T>,
+    {
+        let mut guard = match self.tpm.lock() {
+            Err(e) => e.into_inner(),
+            Ok(g) => g,
+        };
+        let mut tpm = match guard.take() {
+            Some(t) => t,
+            None => self.device.open()?,
+        };
+        let result = op(&mut tpm);
+        if !matches!(&result, Err(e) if e.is::<std::io::Error>()) {
+            *guard = Some(tpm);
+        }
+        result
+    }
+
+    pub fn get_tpm_quote(&self, report_data: &[u8]) -> Result<TpmQuote> {
+        self.with_tpm(|tpm| tpm.quote(self.ak_handle, report_data, &self.pcr_selection))
+    }
+}
+
+impl TeeBackend for TpmBackend {
+    fn tee_type(&self) -> TeeType {
+        TeeType::TPM
+    }
+
+    fn detect(&self) -> Result<bool> {
+        match &self.device {
+            TpmDevice::Path(path) => Ok(Path::new(path).exists()),
+            TpmDevice::Swtpm(_) => Ok(true),
+        }
+    }
+
+    fn capabilities(&self) -> TeeCapabilities {
+        TeeCapabilities {
+            report: false,
+            ..TeeCapabilities::default()
+        }
+    }
+
+    //TPMS_ATTEST, signature, PCR values and AK public area as JSON
+    fn get_evidence(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        self.get_tpm_quote(report_data)?.to_json()
+    }
+
+    fn get_report(&self, _report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        Err(anyhow!("[get_report] TPM has no local attestation report"))
+    }
+
+    fn get_measurements(&self) -> Result<Vec<MeasurementRegister>> {
+        let pcrs = self.with_tpm(|tpm| tpm.read_pcrs(&self.pcr_selection))?;
+        Ok(pcrs
+            .into_iter()
+            .map(|p| MeasurementRegister {
+                name: format!("PCR{}", p.index),
+                algorithm_id: p.hash_alg,
+                digest: p.digest,
+            })
+            .collect())
+    }
+
+    fn get_event_log(&self) -> Result<EventLog> {
+        let log = match fs::read(&self.event_log_path) {
+            Err(e) => {
+                return Err(anyhow!(
+                    "[get_event_log] Fail to read {}: {:?}",
+                    self.event_log_path,
+                    e
+                ))
+            }
+            Ok(l) => l,
+        };
+        Ok(EventLog::from_bytes(&log)?)
+    }
+}
+
+#[cfg(test)]
+mod tpm_tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+
+    //replays canned responses and keeps the commands it was sent
+    struct ScriptedTransport {
+        responses: VecDeque<Vec<u8>>,
+        commands: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl TpmTransport for ScriptedTransport {
+        fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+            self.commands.lock().unwrap().push(command.to_vec());
+            self.responses
+                .pop_front()
+                .ok_or_else(|| anyhow!("no response left"))
+        }
+    }
+
+    fn scripted_tpm(responses: Vec<Vec<u8>>) -> (Tpm, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let transport = ScriptedTransport {
+            responses: responses.into(),
+            commands: commands.clone(),
+        };
+        (Tpm::new(Box::new(transport)), commands)
+    }
+
+    fn response(tag: u16, rc: u32, body: &[u8]) -> Vec<u8> {
+        Command::new(tag, rc).bytes(body).finish()
+    }
+
+    fn pcr_read_response(selection: &[PcrSelection], fill: u8) -> Vec<u8> {
+        let mut body = 7u32.to_be_bytes().to_vec();
+        body.extend_from_slice(&marshal_pcr_selection(selection));
+        let count: usize = selection.iter().map(|b| b.pcrs.len()).sum();
+        body.extend_from_slice(&(count as u32).to_be_bytes());
+        for bank in selection {
+            for pcr in bank.pcrs.iter() {
+                body.extend_from_slice(&32u16.to_be_bytes());
+                body.extend_from_slice(&[fill + *pcr as u8; 32]);
+            }
+        }
+        response(TPM_ST_NO_SESSIONS, 0, &body)
+    }
+
+    fn attest(extra_data: &[u8], selection: &[PcrSelection], pcr_digest: &[u8]) -> Vec<u8> {
+        Command { buffer: Vec::new() }
+            .u32(TPM_GENERATED_VALUE)
+            .u16(TPM_ST_ATTEST_QUOTE)
+            .tpm2b(&[0x51; 34])
+            .tpm2b(extra_data)
+            .bytes(&[0; 17])
+            .bytes(&[0; 8])
+            .pcr_selection(selection)
+            .tpm2b(pcr_digest)
+            .buffer
+    }
+
+    fn quote_response(attest: &[u8], signature: &[u8]) -> Vec<u8> {
+        let mut body = ((2 + attest.len() + signature.len()) as u32)
+            .to_be_bytes()
+            .to_vec();
+        body.extend_from_slice(&(attest.len() as u16).to_be_bytes());
+        body.extend_from_slice(attest);
+        body.extend_from_slice(signature);
+        //authorization area of the password session
+        body.extend_from_slice(&[0, 0, 1, 0, 0]);
+        response(TPM_ST_SESSIONS, 0, &body)
+    }
+
+    fn pcr_digest(pcrs: &[u32], fill: u8) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        for pcr in pcrs {
+            hasher.update([fill + *pcr as u8; 32]);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    #[test]
+    //PCR selections in the tpm2-tools format are parsed sorted and deduplicated
+    fn parse_pcr_selection_banks() {
+        let selection = parse_pcr_selection("sha256:7,0,7+sha384:23").unwrap();
+        assert_eq!(
+            selection,
+            vec![
+                PcrSelection {
+                    hash_alg: TPM_ALG_SHA256,
+                    pcrs: vec![0, 7]
+                },
+                PcrSelection {
+                    hash_alg: TPM_ALG_SHA384,
+                    pcrs: vec![23]
+                },
+            ]
+        );
+        assert_eq!(
+            parse_pcr_selection(DEFAULT_PCR_SELECTION).unwrap()[0]
+                .pcrs
+                .len(),
+            24
+        );
+        assert!(parse_pcr_selection("sha256:24").is_err());
+        assert!(parse_pcr_selection("md5:0").is_err());
+        assert!(parse_pcr_selection("sha256").is_err());
+    }
+
+    #[test]
+    //PCRs the TPM leaves out of a PCR_Read are read in a further round
+    fn read_pcrs_in_rounds() {
+        let selection = parse_pcr_selection("sha256:0,1,9").unwrap();
+        let first = parse_pcr_selection("sha256:0,1").unwrap();
+        let second = parse_pcr_selection("sha256:9").unwrap();
+        let (mut tpm, commands) = scripted_tpm(vec![
+            pcr_read_response(&first, 0x10),
+            pcr_read_response(&second, 0x10),
+        ]);
+
+        let values = tpm.read_pcrs(&selection).unwrap();
+        let indexes: Vec<u32> = values.iter().map(|v| v.index).collect();
+        assert_eq!(indexes, vec![0, 1, 9]);
+        assert_eq!(values[2].digest, vec![0x19; 32]);
+
+        let commands = commands.lock().unwrap();
+        assert_eq!(
+            commands[0],
+            vec![
+                0x80, 0x01, 0, 0, 0, 0x14, 0, 0, 0x01, 0x7e, 0, 0, 0, 1, 0, 0x0b, 3, 0x03, 0x02, 0
+            ]
+        );
+        assert_eq!(&commands[1][14..], &[0, 0x0b, 3, 0, 0x02, 0]);
+    }
+
+    #[test]
+    //the quote carries the report data and the PCR values matching its PCR digest
+    fn quote_with_existing_ak() {
+        let selection = parse_pcr_selection("sha256:0,7").unwrap();
+        let report_data = [0x42; 64];
+        let attest = attest(
+            &qualifying_data(&report_data),
+            &selection,
+            &pcr_digest(&[0, 7], 0x20),
+        );
+        let (mut tpm, commands) = scripted_tpm(vec![
+            response(TPM_ST_NO_SESSIONS, 0, &[0, 3, 0xaa, 0xbb, 0xcc, 0, 0, 0, 0]),
+            pcr_read_response(&selection, 0x20),
+            quote_response(&attest, &[0x00, 0x18, 0x00, 0x0b, 0, 1, 0x0e, 0, 1, 0x0f]),
+        ]);
+
+        let quote = tpm
+            .quote(DEFAULT_AK_HANDLE, &report_data, &selection)
+            .unwrap();
+        assert_eq!(quote.attest, attest);
+        assert_eq!(quote.signature.len(), 10);
+        assert_eq!(quote.ak_public, vec![0xaa, 0xbb, 0xcc]);
+        assert_eq!(quote.pcrs.len(), 2);
+
+        let commands = commands.lock().unwrap();
+        let quote_command = &commands[2];
+        assert_eq!(&quote_command[6..10], &TPM_CC_QUOTE.to_be_bytes());
+        assert_eq!(&quote_command[10..14], &DEFAULT_AK_HANDLE.to_be_bytes());
+        assert_eq!(&quote_command[27..29], &[0, 32]);
+        assert_eq!(&quote_command[29..61], &Sha256::digest(report_data)[..]);
+
+        let json: serde_json::Value = serde_json::from_slice(&quote.to_json().unwrap()).unwrap();
+        assert_eq!(json["pcrs"][1]["index"], 7);
+        assert_eq!(json["pcrs"][1]["bank"], "sha256");
+    }
+
+    #[test]
+    //the AK is created, persisted and flushed when the persistent handle is empty
+    fn quote_creates_ak() {
+        let selection = parse_pcr_selection("sha256:0").unwrap();
+        let report_data = [0x42; 64];
+        let attest = attest(
+            &qualifying_data(&report_data),
+            &selection,
+            &pcr_digest(&[0], 0x20),
+        );
+        let mut create_primary = 0x8000_0000u32.to_be_bytes().to_vec();
+        create_primary.extend_from_slice(&[0, 0, 0, 5, 0, 3, 0xaa, 0xbb, 0xcc]);
+        let (mut tpm, commands) = scripted_tpm(vec![
+            response(TPM_ST_NO_SESSIONS, 0x18b, &[]),
+            response(TPM_ST_SESSIONS, 0, &create_primary),
+            response(TPM_ST_SESSIONS, 0, &[0, 0, 0, 0]),
+            response(TPM_ST_NO_SESSIONS, 0, &[]),
+            pcr_read_response(&selection, 0x20),
+            quote_response(&attest, &[0; 10]),
+        ]);
+
+        let quote = tpm
+            .quote(DEFAULT_AK_HANDLE, &report_data, &selection)
+            .unwrap();
+        assert_eq!(quote.ak_public, vec![0xaa, 0xbb, 0xcc]);
+
+        let commands = commands.lock().unwrap();
+        let codes: Vec<&[u8]> = commands.iter().map(|c| &c[6..10]).collect();
+        assert_eq!(
+            codes,
+            vec![
+                &TPM_CC_READ_PUBLIC.to_be_bytes()[..],
+                &TPM_CC_CREATE_PRIMARY.to_be_bytes()[..],
+                &TPM_CC_EVICT_CONTROL.to_be_bytes()[..],
+                &TPM_CC_FLUSH_CONTEXT.to_be_bytes()[..],
+                &TPM_CC_PCR_READ.to_be_bytes()[..],
+                &TPM_CC_QUOTE.to_be_bytes()[..],
+            ]
+        );
+        assert_eq!(&commands[2][14..18], &0x8000_0000u32.to_be_bytes());
+        assert_eq!(&commands[3][10..14], &0x8000_0000u32.to_be_bytes());
+    }
+
+    #[test]
+    //a quote whose extraData is not the digest of the report data is rejected
+    fn quote_rejects_other_extra_data() {
+        let selection = parse_pcr_selection("sha256:0").unwrap();
+        let report_data = [0x42; 64];
+        for extra_data in [&report_data[..], &qualifying_data(&[0x43; 64])[..]] {
+            let attest = attest(extra_data, &selection, &pcr_digest(&[0], 0x20));
+            let (mut tpm, _) = scripted_tpm(vec![
+                response(TPM_ST_NO_SESSIONS, 0, &[0, 1, 0xaa, 0, 0, 0, 0]),
+                pcr_read_response(&selection, 0x20),
+                quote_response(&attest, &[0; 10]),
+            ]);
+            let error = tpm
+                .quote(DEFAULT_AK_HANDLE, &report_data, &selection)
+                .unwrap_err();
+            assert!(error.to_string().contains("does not carry the report data"));
+        }
+    }
+
+    #[test]
+    //PCRs changing between PCR_Read and the quote are read and quoted again
+    fn quote_retries_on_pcr_change() {
+        let selection = parse_pcr_selection("sha256:0").unwrap();
+        let report_data = [0x42; 64];
+        let attest = attest(
+            &qualifying_data(&report_data),
+            &selection,
+            &pcr_digest(&[0], 0x30),
+        );
+        let mut responses = vec![response(TPM_ST_NO_SESSIONS, 0, &[0, 1, 0xaa, 0, 0, 0, 0])];
+        for _ in 0..QUOTE_ATTEMPTS {
+            responses.push(pcr_read_response(&selection, 0x20));
+            responses.push(quote_response(&attest, &[0; 10]));
+        }
+        let (mut tpm, _) = scripted_tpm(responses);
+        assert!(tpm
+            .quote(DEFAULT_AK_HANDLE, &report_data, &selection)
+            .is_err());
+
+        let (mut tpm, _) = scripted_tpm(vec![
+            response(TPM_ST_NO_SESSIONS, 0, &[0, 1, 0xaa, 0, 0, 0, 0]),
+            pcr_read_response(&selection, 0x20),
+            quote_response(&attest, &[0; 10]),
+            pcr_read_response(&selection, 0x30),
+            quote_response(&attest, &[0; 10]),
+        ]);
+        let quote = tpm
+            .quote(DEFAULT_AK_HANDLE, &report_data, &selection)
+            .unwrap();
+        assert_eq!(quote.pcrs[0].digest, vec![0x30; 32]);
+    }
+
+    #[test]
+    //TPM response codes and malformed responses are reported
+    fn execute_rejects_failures() {
+        let (mut tpm, _) = scripted_tpm(vec![
+            response(TPM_ST_NO_SESSIONS, 0x101, &[]),
+            vec![0x80, 0x01, 0, 0],
+        ]);
+        assert!(tpm
+            .read_pcrs(&parse_pcr_selection("sha256:0").unwrap())
+            .is_err());
+        assert!(tpm.read_public(DEFAULT_AK_HANDLE).is_err());
+    }
+
+    #[test]
+    #[ignore]
+    //quote against a local swtpm started with
+    //swtpm socket --tpm2 --server type=tcp,port=2321 --ctrl type=tcp,port=2322
+    //  --tpmstate dir=/tmp/swtpm --flags not-need-init,startup-clear
+    fn swtpm_quote() {
+        let tee = TpmBackend::new(TpmDevice::Swtpm("127.0.0.1:2321".to_string()));
+        let quote = tee.get_tpm_quote(&[0x42; 64]).unwrap();
+        assert_eq!(quote.pcrs.len(), 24);
+        assert!(!quote.signature.is_empty());
+        assert_eq!(tee.get_measurements().unwrap().len(), 24);
+    }
+}
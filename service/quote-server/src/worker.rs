@@ -0,0 +1,161 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::result::Result;
+use std::result::Result::Ok;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkerError {
+    QueueFull(usize), // All workers are busy and the queue already holds this many jobs
+    Stopped,          // Workers are gone or the job panicked, no result was produced
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerError::QueueFull(queue_len) => {
+                write!(
+                    f,
+                    "request queue is full with {} waiting requests",
+                    queue_len
+                )
+            }
+            WorkerError::Stopped => write!(f, "device worker stopped before answering"),
+        }
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+//fixed set of threads running blocking device requests off the async runtime,
+//jobs wait in a bounded queue and are rejected once it is full
+pub struct WorkerPool {
+    sender: SyncSender<Job>,
+    queue_len: usize,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize, queue_len: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue_len);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            if let Err(e) = thread::Builder::new()
+                .name(format!("device-worker-{}", i))
+                .spawn(move || worker_loop(receiver))
+            {
+                panic!("[WorkerPool::new] Fail to spawn device worker: {:?}", e);
+            }
+        }
+        WorkerPool { sender, queue_len }
+    }
+
+    //queue the job without waiting, the receiver yields its result once a worker ran it
+    pub fn submit<T, F>(&self, job: F) -> Result<oneshot::Receiver<T>, WorkerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = result_sender.send(job());
+        });
+        match self.sender.try_send(job) {
+            Err(TrySendError::Full(_)) => Err(WorkerError::QueueFull(self.queue_len)),
+            Err(TrySendError::Disconnected(_)) => Err(WorkerError::Stopped),
+            Ok(()) => Ok(result_receiver),
+        }
+    }
+
+    //run the job on a worker and wait for its result without blocking the runtime
+    pub async fn run<T, F>(&self, job: F) -> Result<T, WorkerError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.submit(job)?.await {
+            Err(_) => Err(WorkerError::Stopped),
+            Ok(v) => Ok(v),
+        }
+    }
+}
+
+fn worker_loop(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match receiver.lock() {
+            Err(_) => return,
+            Ok(r) => r.recv(),
+        };
+        match job {
+            //the pool is dropped
+            Err(_) => return,
+            //a panicking job drops its result sender, the worker itself keeps going
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod worker_tests {
+    use super::*;
+
+    #[test]
+    //jobs run on the workers and hand back their results
+    fn worker_pool_run() {
+        let pool = WorkerPool::new(2, 4);
+        let receivers: Vec<_> = (0..4)
+            .map(|i| pool.submit(move || i * 2).unwrap())
+            .collect();
+        let results: Vec<_> = receivers
+            .into_iter()
+            .map(|r| r.blocking_recv().unwrap())
+            .collect();
+        assert_eq!(results, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    //jobs are rejected instead of queued once all workers are busy and the queue is full
+    fn worker_pool_queue_full() {
+        let pool = WorkerPool::new(1, 1);
+        let (started_sender, started_receiver) = mpsc::channel();
+        let (gate_sender, gate_receiver) = mpsc::channel::<()>();
+
+        let first = pool
+            .submit(move || {
+                started_sender.send(()).unwrap();
+                gate_receiver.recv().unwrap();
+                1
+            })
+            .unwrap();
+        started_receiver.recv().unwrap();
+
+        let second = pool.submit(|| 2).unwrap();
+        assert_eq!(pool.submit(|| 3).err(), Some(WorkerError::QueueFull(1)));
+
+        gate_sender.send(()).unwrap();
+        assert_eq!(first.blocking_recv().unwrap(), 1);
+        assert_eq!(second.blocking_recv().unwrap(), 2);
+        assert_eq!(pool.submit(|| 4).unwrap().blocking_recv().unwrap(), 4);
+    }
+
+    #[tokio::test]
+    //a panicking job fails its own request only
+    async fn worker_pool_job_panics() {
+        let pool = WorkerPool::new(1, 1);
+        let result: Result<(), WorkerError> = pool.run(|| panic!("job failed")).await;
+        assert_eq!(result, Err(WorkerError::Stopped));
+        assert_eq!(pool.run(|| 5).await, Ok(5));
+    }
+}
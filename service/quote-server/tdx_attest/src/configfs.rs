@@ -0,0 +1,263 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::result::Result;
+use std::result::Result::Ok;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const TSM_REPORT_PATH: &str = "/sys/kernel/config/tsm/report";
+pub const TDX_PROVIDER: &str = "tdx_guest";
+
+static ENTRY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+//quote generation through the configfs-tsm report interface of newer kernels
+pub struct ConfigfsTsm {
+    root: PathBuf,
+}
+
+impl Default for ConfigfsTsm {
+    fn default() -> Self {
+        ConfigfsTsm::new(TSM_REPORT_PATH)
+    }
+}
+
+impl ConfigfsTsm {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ConfigfsTsm { root: root.into() }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.root.is_dir()
+    }
+
+    //name of the TSM provider backing the report interface, e.g. tdx_guest
+    pub fn provider(&self) -> Result<String, TdxError> {
+        TsmReport::create(&self.root)?.provider()
+    }
+
+    pub fn get_quote(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+        TsmReport::create(&self.root)?.get_quote(report_data)
+    }
+}
+
+//a single report entry under the configfs-tsm report directory
+pub struct TsmReport {
+    path: PathBuf,
+    owned: bool,
+}
+
+impl TsmReport {
+    //create a new report entry, which is removed again when dropped
+    pub fn create(root: &Path) -> Result<Self, TdxError> {
+        let name = format!(
+            "ccnp-{}-{}",
+            process::id(),
+            ENTRY_COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        let path = root.join(name);
+        match fs::create_dir(&path) {
+            Err(e) => Err(TdxError::from_io(&path.display().to_string(), e)),
+            Ok(_) => Ok(TsmReport { path, owned: true }),
+        }
+    }
+
+    //use an existing report entry without taking ownership of it
+    pub fn open(path: &Path) -> Self {
+        TsmReport {
+            path: path.to_path_buf(),
+            owned: false,
+        }
+    }
+
+    pub fn provider(&self) -> Result<String, TdxError> {
+        Ok(self.read_attribute("provider")?.trim().to_string())
+    }
+
+    pub fn generation(&self) -> Result<u64, TdxError> {
+        let generation = self.read_attribute("generation")?;
+        match generation.trim().parse::<u64>() {
+            Err(e) => Err(TdxError::Io(format!(
+                "[TsmReport::generation] Malformed generation {:?}: {:?}",
+                generation, e
+            ))),
+            Ok(g) => Ok(g),
+        }
+    }
+
+    pub fn get_quote(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+        let provider = self.provider()?;
+        if provider != TDX_PROVIDER {
+            return Err(TdxError::Io(format!(
+                "[TsmReport::get_quote] Unexpected TSM provider: {}",
+                provider
+            )));
+        }
+
+        //the inblob write below must advance the generation by exactly one
+        let generation = self.generation()?;
+        let inblob = self.path.join("inblob");
+        if let Err(e) = fs::write(&inblob, report_data) {
+            return Err(TdxError::from_io(&inblob.display().to_string(), e));
+        }
+
+        let outblob = self.path.join("outblob");
+        let quote = match fs::read(&outblob) {
+            Err(e) => return Err(TdxError::from_io(&outblob.display().to_string(), e)),
+            Ok(q) => q,
+        };
+
+        //any other writer to this entry since our inblob write invalidates the quote
+        let expected_generation = generation + 1;
+        let current_generation = self.generation()?;
+        if current_generation != expected_generation {
+            return Err(TdxError::ConcurrentModification(
+                expected_generation,
+                current_generation,
+            ));
+        }
+
+        if quote.is_empty() {
+            return Err(TdxError::InvalidQuote(
+                "[TsmReport::get_quote] Empty quote in outblob".to_string(),
+            ));
+        }
+
+        Ok(quote)
+    }
+
+    fn read_attribute(&self, name: &str) -> Result<String, TdxError> {
+        let attribute = self.path.join(name);
+        match fs::read_to_string(&attribute) {
+            Err(e) => Err(TdxError::from_io(&attribute.display().to_string(), e)),
+            Ok(v) => Ok(v),
+        }
+    }
+}
+
+impl Drop for TsmReport {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = fs::remove_dir(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod configfs_tests {
+    use super::*;
+    use std::io::Write;
+    use std::thread;
+
+    //populate a directory standing in for a configfs-tsm report entry
+    fn fake_entry(dir: &Path, provider: &str, quote: &[u8]) {
+        fs::write(dir.join("provider"), format!("{}\n", provider)).unwrap();
+        fs::write(dir.join("generation"), "1\n").unwrap();
+        fs::write(dir.join("outblob"), quote).unwrap();
+    }
+
+    //stand in for the kernel side of a report entry: inblob and outblob are fifos, and
+    //the generation is set to new_generation once inblob was written and before the
+    //quote is handed out through outblob, returns the report data written to inblob
+    fn fake_kernel(dir: &Path, new_generation: u64, quote: &[u8]) -> thread::JoinHandle<Vec<u8>> {
+        fake_entry(dir, TDX_PROVIDER, &[]);
+        for name in ["inblob", "outblob"] {
+            let blob = dir.join(name);
+            let _ = fs::remove_file(&blob);
+            nix::unistd::mkfifo(&blob, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        }
+
+        let entry = dir.to_path_buf();
+        let quote = quote.to_vec();
+        thread::spawn(move || {
+            let report_data = fs::read(entry.join("inblob")).unwrap();
+            fs::write(entry.join("generation"), format!("{}\n", new_generation)).unwrap();
+            let mut fifo = fs::OpenOptions::new()
+                .write(true)
+                .open(entry.join("outblob"))
+                .unwrap();
+            fifo.write_all(&quote).unwrap();
+            report_data
+        })
+    }
+
+    #[test]
+    //report data goes to inblob and the quote comes back from outblob
+    fn tsm_report_get_quote() {
+        let dir = tempfile::tempdir().unwrap();
+        let kernel = fake_kernel(dir.path(), 2, &[0x04, 0x00, 0x02, 0x00]);
+
+        let quote = TsmReport::open(dir.path()).get_quote(&[0x5a; 64]).unwrap();
+        assert_eq!(quote, vec![0x04, 0x00, 0x02, 0x00]);
+        assert_eq!(kernel.join().unwrap(), vec![0x5a; 64]);
+        assert!(dir.path().exists());
+    }
+
+    #[test]
+    //a provider other than tdx_guest is rejected
+    fn tsm_report_wrong_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_entry(dir.path(), "sev_guest", &[0x01]);
+        assert!(TsmReport::open(dir.path()).get_quote(&[0; 64]).is_err());
+    }
+
+    #[test]
+    //an empty outblob is reported as an error
+    fn tsm_report_empty_outblob() {
+        let dir = tempfile::tempdir().unwrap();
+        let kernel = fake_kernel(dir.path(), 2, &[]);
+        assert!(TsmReport::open(dir.path()).get_quote(&[0; 64]).is_err());
+        kernel.join().unwrap();
+    }
+
+    #[test]
+    //a generation advanced by two reveals a second inblob write by a concurrent writer
+    fn tsm_report_concurrent_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let kernel = fake_kernel(dir.path(), 3, &[0x04, 0x00]);
+
+        let result = TsmReport::open(dir.path()).get_quote(&[0; 64]);
+        kernel.join().unwrap();
+        assert_eq!(result, Err(TdxError::ConcurrentModification(2, 3)));
+    }
+
+    #[test]
+    //a generation not advanced by the inblob write is rejected as well
+    fn tsm_report_generation_not_advanced() {
+        let dir = tempfile::tempdir().unwrap();
+        let kernel = fake_kernel(dir.path(), 1, &[0x04, 0x00]);
+
+        let result = TsmReport::open(dir.path()).get_quote(&[0; 64]);
+        kernel.join().unwrap();
+        assert_eq!(result, Err(TdxError::ConcurrentModification(2, 1)));
+    }
+
+    #[test]
+    //created report entries are removed when dropped
+    fn tsm_report_create_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let tsm = ConfigfsTsm::new(dir.path());
+        assert!(tsm.is_available());
+
+        let entry = TsmReport::create(dir.path()).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        drop(entry);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        //a plain directory does not populate the entry attributes like configfs does
+        assert!(tsm.get_quote(&[0; 64]).is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    //the interface is unavailable when the report directory does not exist
+    fn configfs_tsm_not_available() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!ConfigfsTsm::new(dir.path().join("tsm/report")).is_available());
+    }
+}
@@ -0,0 +1,306 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::configfs::ConfigfsTsm;
+use crate::device_thread::DeviceThread;
+use crate::error::TdxError;
+use crate::eventlog::{EventDigest, EventLogEntry, EV_IPL, TPM_ALG_SHA384};
+use crate::qgs::{AttKeyId, QgsQuoteResponse};
+use crate::report::{TdAttributes, TdReport};
+use crate::retry::RetryPolicy;
+use crate::rtmr::{self, RuntimeEventLog, SysfsRtmrs};
+#[cfg(feature = "simulator")]
+use crate::simulator::TdxSimulator;
+use crate::{TdxInfo, TdxVersion, DEFAULT_QUOTE_BUFFER_LEN};
+#[cfg(feature = "simulator")]
+use std::env;
+use std::result::Result;
+use std::sync::{Arc, Mutex, OnceLock};
+
+//environment variable selecting the TDX device, set to "simulator" to run without TDX hardware.
+//only honored when built with the simulator feature
+pub const TDX_DEVICE_ENV: &str = "TDX_ATTEST_DEVICE";
+pub const TDX_DEVICE_SIMULATOR: &str = "simulator";
+
+//interface a device requests quotes through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TdxInterface {
+    GuestDevice(String), // GetQuote ioctl on the TDX guest device node at this path
+    ConfigfsTsm, // configfs-tsm report interface, TD reports still come from the device node
+    Simulator,   // Software TDX simulator, nothing is backed by hardware
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TdxPlatformInfo {
+    pub tdx_version: TdxVersion,     // TDX module version the TD runs on
+    pub interface: TdxInterface,     // Interface quotes are requested through
+    pub td_attributes: TdAttributes, // Decoded TD ATTRIBUTES of the running TD
+}
+
+//source of TD reports and quotes that the report and quote paths go through
+pub trait TdxDevice: Send + Sync {
+    fn get_td_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError>;
+
+    //quote signed by one of the given attestation keys, or by any key when the list is empty
+    fn get_quote_with_key_ids(
+        &self,
+        report_data: &[u8; 64],
+        att_key_ids: &[AttKeyId],
+    ) -> Result<QgsQuoteResponse, TdxError>;
+
+    fn get_quote(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+        Ok(self.get_quote_with_key_ids(report_data, &[])?.quote)
+    }
+
+    fn interface(&self) -> Result<TdxInterface, TdxError>;
+
+    //module version and TD attributes are taken from a fresh TD report
+    fn platform_info(&self) -> Result<TdxPlatformInfo, TdxError> {
+        let report = TdReport::from_bytes(&self.get_td_report(&[0; 64])?)?;
+        Ok(TdxPlatformInfo {
+            tdx_version: report.tdx_version()?,
+            interface: self.interface()?,
+            td_attributes: report.td_attributes(),
+        })
+    }
+
+    fn is_debug_td(&self) -> Result<bool, TdxError> {
+        Ok(TdReport::from_bytes(&self.get_td_report(&[0; 64])?)?.is_debug())
+    }
+
+    //extend the register only, go through extend_rtmr to keep the runtime event log in step
+    fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError>;
+
+    //events of the RTMR extensions made through this device
+    fn runtime_event_log(&self) -> &RuntimeEventLog;
+
+    fn extend_rtmr(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+        self.extend_rtmr_event(index, digest, EV_IPL, &[])
+    }
+
+    //extend the RTMR and record the extension with the given TCG event type and data
+    fn extend_rtmr_event(
+        &self,
+        index: usize,
+        digest: &[u8; 48],
+        event_type: u32,
+        event: &[u8],
+    ) -> Result<(), TdxError> {
+        rtmr::check_rtmr_index(index)?;
+        let entry = EventLogEntry {
+            mr_index: index as u32 + 1,
+            event_type,
+            digests: vec![EventDigest {
+                algorithm_id: TPM_ALG_SHA384,
+                digest: digest.to_vec(),
+            }],
+            event: event.to_vec(),
+        };
+        self.runtime_event_log()
+            .record(entry, || self.extend_rtmr_register(index, digest))
+    }
+}
+
+//the TDX guest device of the running kernel
+#[derive(Debug)]
+pub struct GuestDevice {
+    retry_policy: RetryPolicy,
+    quote_buffer_len: usize,
+    tdx_info: Mutex<Option<TdxInfo>>, // Device node for TD reports and RTMRs, kept open
+    quote_node: Arc<Mutex<Option<TdxInfo>>>, // Own device node for GetQuote, which may block
+    quote_thread: DeviceThread,       // Runs the GetQuote ioctls, one at a time
+}
+
+impl Default for GuestDevice {
+    fn default() -> Self {
+        GuestDevice {
+            retry_policy: RetryPolicy::default(),
+            quote_buffer_len: DEFAULT_QUOTE_BUFFER_LEN,
+            tdx_info: Mutex::new(None),
+            quote_node: Arc::new(Mutex::new(None)),
+            quote_thread: DeviceThread::new("tdx-quote"),
+        }
+    }
+}
+
+impl GuestDevice {
+    //retry and timeout applied to quote requests through the GetQuote ioctl
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    //initial size of the GetQuote shared buffer, grown on demand up to MAX_QUOTE_BUFFER_LEN
+    pub fn with_quote_buffer_len(mut self, quote_buffer_len: usize) -> Self {
+        self.quote_buffer_len = quote_buffer_len;
+        self
+    }
+
+    fn with_device_node<T, F>(&self, operation: F) -> Result<T, TdxError>
+    where
+        F: FnOnce(&TdxInfo) -> Result<T, TdxError>,
+    {
+        with_device_node(&self.tdx_info, operation)
+    }
+}
+
+//run ioctls on the device node, holding the lock serializes them on the shared descriptor
+fn with_device_node<T, F>(device_node: &Mutex<Option<TdxInfo>>, operation: F) -> Result<T, TdxError>
+where
+    F: FnOnce(&TdxInfo) -> Result<T, TdxError>,
+{
+    let mut guard = match device_node.lock() {
+        Err(e) => e.into_inner(),
+        Ok(g) => g,
+    };
+    let tdx_info = match guard.take() {
+        Some(t) => t,
+        None => crate::open_tdx_device()?,
+    };
+    let result = operation(&tdx_info);
+    *guard = Some(tdx_info);
+    result
+}
+
+impl TdxDevice for GuestDevice {
+    fn get_td_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+        self.with_device_node(|tdx_info| crate::get_td_report_ioctl(tdx_info, report_data))
+    }
+
+    fn get_quote_with_key_ids(
+        &self,
+        report_data: &[u8; 64],
+        att_key_ids: &[AttKeyId],
+    ) -> Result<QgsQuoteResponse, TdxError> {
+        //prefer the configfs-tsm report interface when the kernel provides it,
+        //it has no way to select an attestation key though
+        let tsm = ConfigfsTsm::default();
+        if att_key_ids.is_empty() && tsm.is_available() {
+            return Ok(QgsQuoteResponse {
+                selected_id: None,
+                quote: tsm.get_quote(report_data)?,
+            });
+        }
+        //the device is released between attempts so that other requests get their turn.
+        //attempts run on the quote thread, a GetQuote ioctl hung past the deadline keeps it
+        //and further quotes fail fast until the VMM answers. the quote descriptor is not the one
+        //of TD reports, so a quote waiting for the QGS never holds up local reads
+        let report_data = *report_data;
+        let att_key_ids = att_key_ids.to_vec();
+        let quote_buffer_len = self.quote_buffer_len;
+        self.retry_policy.run(|deadline| {
+            let device_node = self.quote_node.clone();
+            let att_key_ids = att_key_ids.clone();
+            self.quote_thread
+                .run(deadline, self.retry_policy.timeout, move || {
+                    with_device_node(&device_node, |tdx_info| {
+                        crate::get_tdx_quote_ioctl(
+                            tdx_info,
+                            &report_data,
+                            &att_key_ids,
+                            quote_buffer_len,
+                        )
+                    })
+                })
+        })
+    }
+
+    //the sysfs measurement registers of newer kernels, the extend ioctl otherwise
+    fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+        rtmr::check_rtmr_index(index)?;
+        let registers = SysfsRtmrs::default();
+        if registers.is_available() {
+            return registers.extend(index, digest);
+        }
+        self.with_device_node(|tdx_info| crate::extend_rtmr_ioctl(tdx_info, index, digest))
+    }
+
+    fn runtime_event_log(&self) -> &RuntimeEventLog {
+        rtmr::guest_runtime_event_log()
+    }
+
+    fn interface(&self) -> Result<TdxInterface, TdxError> {
+        if ConfigfsTsm::default().is_available() {
+            return Ok(TdxInterface::ConfigfsTsm);
+        }
+        self.with_device_node(|tdx_info| {
+            Ok(TdxInterface::GuestDevice(
+                tdx_info.tdx_version.device_path().to_string(),
+            ))
+        })
+    }
+}
+
+#[cfg(feature = "simulator")]
+pub fn simulator_requested() -> bool {
+    matches!(env::var(TDX_DEVICE_ENV), Ok(v) if v == TDX_DEVICE_SIMULATOR)
+}
+
+//builds without the simulator feature never hand out simulated quotes
+#[cfg(not(feature = "simulator"))]
+pub fn simulator_requested() -> bool {
+    false
+}
+
+//the TDX guest device, or the simulator when requested through TDX_ATTEST_DEVICE.
+//created on first use and shared by the whole process, so the device node is opened only once.
+//quotes use the default retry policy, build a GuestDevice of your own for another one
+pub fn default_device() -> Arc<dyn TdxDevice> {
+    static DEFAULT_DEVICE: OnceLock<Arc<dyn TdxDevice>> = OnceLock::new();
+    DEFAULT_DEVICE
+        .get_or_init(|| {
+            #[cfg(feature = "simulator")]
+            if simulator_requested() {
+                return Arc::new(TdxSimulator::default());
+            }
+            Arc::new(GuestDevice::default())
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod device_tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    //a GetQuote hung in the device holds the quote descriptor only, TD reports still answer
+    fn td_report_while_quote_hangs() {
+        let device = Arc::new(GuestDevice::default());
+        let (release, hung) = mpsc::channel::<()>();
+        let (started_sender, started) = mpsc::channel();
+
+        let quote_node = device.quote_node.clone();
+        let quote_device = device.clone();
+        let quote = thread::spawn(move || {
+            quote_device.quote_thread.run(
+                Instant::now() + Duration::from_secs(10),
+                Duration::from_secs(10),
+                move || {
+                    let _quote_node = quote_node.lock().unwrap();
+                    started_sender.send(()).unwrap();
+                    let _ = hung.recv();
+                    Ok(())
+                },
+            )
+        });
+        started.recv().unwrap();
+
+        //without TDX hardware the report fails to open the device, it must not wait either way
+        let (report_sender, report) = mpsc::channel();
+        let report_device = device.clone();
+        thread::spawn(move || {
+            let _ = report_sender.send(report_device.get_td_report(&[0; 64]).map(|_| ()));
+        });
+        let result = report.recv_timeout(Duration::from_secs(5));
+        assert!(result.is_ok());
+        assert_ne!(result.unwrap(), Err(TdxError::DeviceBusy));
+
+        drop(release);
+        assert_eq!(quote.join().unwrap(), Ok(()));
+    }
+}
@@ -0,0 +1,224 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use std::panic::{self, AssertUnwindSafe};
+use std::result::Result;
+use std::result::Result::Ok;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+//one long-lived thread running blocking device calls, one call at a time.
+//the caller stops waiting at its deadline, a call stuck in the device keeps the thread
+//and later calls fail fast with DeviceBusy instead of stacking up threads behind it
+#[derive(Debug)]
+pub(crate) struct DeviceThread {
+    name: String,
+    busy: Arc<AtomicBool>, // Set while a call is handed over or running
+    sender: Mutex<Option<SyncSender<Job>>>, // Started on first use
+}
+
+impl DeviceThread {
+    pub(crate) fn new(_name: &str) -> Self {
+        DeviceThread {
+            name: _name.to_string(),
+            busy: Arc::new(AtomicBool::new(false)),
+            sender: Mutex::new(None),
+        }
+    }
+
+    //run the operation on the device thread, Timeout when it has not answered by the deadline
+    pub(crate) fn run<T, F>(
+        &self,
+        deadline: Instant,
+        timeout: Duration,
+        operation: F,
+    ) -> Result<T, TdxError>
+    where
+        F: FnOnce() -> Result<T, TdxError> + Send + 'static,
+        T: Send + 'static,
+    {
+        if self
+            .busy
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(TdxError::DeviceBusy);
+        }
+
+        //the flag is cleared before the answer is sent, so the next call never sees a stale busy
+        let busy = self.busy.clone();
+        let (result_sender, result_receiver) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move || {
+            let result = operation();
+            busy.store(false, Ordering::SeqCst);
+            let _ = result_sender.send(result);
+        });
+        if let Err(e) = self.submit(job) {
+            self.busy.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match result_receiver.recv_timeout(remaining) {
+            Err(RecvTimeoutError::Timeout) => Err(TdxError::Timeout(timeout)),
+            //the operation panicked without an answer
+            Err(RecvTimeoutError::Disconnected) => Err(TdxError::Io(
+                "[DeviceThread::run] Device call ended without a result".to_string(),
+            )),
+            Ok(result) => result,
+        }
+    }
+
+    fn submit(&self, job: Job) -> Result<(), TdxError> {
+        let mut sender = match self.sender.lock() {
+            Err(e) => e.into_inner(),
+            Ok(s) => s,
+        };
+        if sender.is_none() {
+            *sender = Some(self.spawn()?);
+        }
+        match sender.as_ref().map(|s| s.send(job)) {
+            Some(Ok(())) => Ok(()),
+            _ => {
+                *sender = None;
+                Err(TdxError::Io(
+                    "[DeviceThread::submit] Device thread stopped".to_string(),
+                ))
+            }
+        }
+    }
+
+    fn spawn(&self) -> Result<SyncSender<Job>, TdxError> {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(1);
+        let busy = self.busy.clone();
+        let spawned = thread::Builder::new()
+            .name(self.name.clone())
+            .spawn(move || {
+                for job in receiver {
+                    //a panicking call only loses its own answer, the thread keeps serving
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        busy.store(false, Ordering::SeqCst);
+                    }
+                }
+            });
+        match spawned {
+            Err(e) => Err(TdxError::Io(format!(
+                "[DeviceThread::spawn] Fail to spawn device thread: {:?}",
+                e
+            ))),
+            Ok(_) => Ok(sender),
+        }
+    }
+}
+
+#[cfg(test)]
+mod device_thread_tests {
+    use super::*;
+    use crate::retry::RetryPolicy;
+    use std::fs;
+
+    //threads of this process carrying the given name
+    fn threads_named(name: &str) -> usize {
+        fs::read_dir("/proc/self/task")
+            .unwrap()
+            .filter_map(|task| fs::read_to_string(task.unwrap().path().join("comm")).ok())
+            .filter(|comm| comm.trim_end() == name)
+            .count()
+    }
+
+    fn short_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(5),
+            max_backoff: Duration::from_millis(5),
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    #[test]
+    //calls run on the device thread and hand back their result
+    fn device_thread_runs_calls() {
+        let device_thread = DeviceThread::new("tdx-test-run");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        for i in 0..3 {
+            assert_eq!(
+                device_thread.run(deadline, Duration::from_secs(5), move || Ok(i)),
+                Ok(i)
+            );
+        }
+        assert_eq!(
+            device_thread.run(
+                deadline,
+                Duration::from_secs(5),
+                || -> Result<(), TdxError> { Err(TdxError::QuoteInFlight) }
+            ),
+            Err(TdxError::QuoteInFlight)
+        );
+        assert_eq!(threads_named("tdx-test-run"), 1);
+    }
+
+    #[test]
+    //a panicking call fails on its own and the thread keeps serving
+    fn device_thread_survives_panic() {
+        let device_thread = DeviceThread::new("tdx-test-panic");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let result: Result<(), TdxError> =
+            device_thread.run(deadline, Duration::from_secs(5), || panic!("ioctl failed"));
+        assert!(matches!(result, Err(TdxError::Io(_))));
+        assert_eq!(
+            device_thread.run(deadline, Duration::from_secs(5), || Ok(1)),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    //a hung call is abandoned at the deadline, later requests fail fast on the same thread
+    fn device_thread_hung_call_keeps_one_thread() {
+        let device_thread = DeviceThread::new("tdx-test-hung");
+        let policy = short_policy();
+        let (release, hung) = mpsc::channel::<()>();
+        let hung = Arc::new(Mutex::new(hung));
+
+        let started = Instant::now();
+        let result: Result<(), TdxError> = policy.run(|deadline| {
+            let hung = hung.clone();
+            device_thread.run(deadline, policy.timeout, move || {
+                let _ = hung.lock().unwrap().recv();
+                Ok(())
+            })
+        });
+        assert_eq!(result, Err(TdxError::Timeout(policy.timeout)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        for _ in 0..10 {
+            let started = Instant::now();
+            let result =
+                policy.run(|deadline| device_thread.run(deadline, policy.timeout, || Ok(())));
+            assert!(matches!(
+                result,
+                Err(TdxError::DeviceBusy) | Err(TdxError::Timeout(_))
+            ));
+            assert!(started.elapsed() < Duration::from_secs(1));
+        }
+        assert_eq!(threads_named("tdx-test-hung"), 1);
+
+        //once the device answers the thread serves requests again
+        drop(release);
+        let policy = RetryPolicy {
+            max_retries: 100,
+            timeout: Duration::from_secs(5),
+            ..short_policy()
+        };
+        let result = policy.run(|deadline| device_thread.run(deadline, policy.timeout, || Ok(7)));
+        assert_eq!(result, Ok(7));
+        assert_eq!(threads_named("tdx-test-hung"), 1);
+    }
+}
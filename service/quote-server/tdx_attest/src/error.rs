@@ -0,0 +1,135 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use nix::errno::Errno;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TdxError {
+    DeviceNotFound,             // No TDX guest device node or configfs-tsm interface found
+    DeprecatedDevice(String),   // Deprecated device node which is not supported any more
+    PermissionDenied(String),   // No permission to access the device node or interface
+    Io(String),                 // Other I/O failure on the device node or interface
+    IoctlFailed(Errno),         // TDX ioctl returned an error
+    QgsError(u32),              // QGS answered with a non-zero error code
+    QgsTransport(String),       // Communication with the QGS failed
+    BadReportData(String),      // Report data is not encoded properly or not 64 bytes
+    QuoteTooLarge(usize),       // Quote does not fit into the quote buffer
+    InvalidReport(String),      // TD report is truncated or malformed
+    InvalidQuote(String),       // Quote or QGS message is truncated or malformed
+    InvalidCertificate(String), // Certificate is malformed or not supported
+    InvalidEventLog(String),    // CCEL table or event log is truncated or malformed
+    InvalidRtmrIndex(usize),    // RTMR index is not in 0-3
+    ConcurrentModification(u64, u64), // configfs-tsm report generation is not the one our inblob write produced
+    QuoteInFlight,                    // VMM has not completed the GetQuote request yet
+    QuoteServiceUnavailable,          // VMM could not reach the quote generation service
+    QuoteStatus(u64), // GetQuote failed with GET_QUOTE_ERROR or an unknown GHCI status
+    Timeout(Duration), // Overall deadline for the request expired
+    DeviceBusy,       // Device thread still serves an earlier, possibly hung request
+}
+
+impl fmt::Display for TdxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TdxError::DeviceNotFound => write!(f, "no TDX device found"),
+            TdxError::DeprecatedDevice(path) => write!(
+                f,
+                "deprecated device node {}, please upgrade to use /dev/tdx-guest or /dev/tdx_guest",
+                path
+            ),
+            TdxError::PermissionDenied(msg) => write!(f, "permission denied: {}", msg),
+            TdxError::Io(msg) => write!(f, "I/O error: {}", msg),
+            TdxError::IoctlFailed(errno) => write!(f, "TDX ioctl failed: {}", errno),
+            TdxError::QgsError(code) => write!(f, "QGS returned error code {:#x}", code),
+            TdxError::QgsTransport(msg) => write!(f, "QGS communication failed: {}", msg),
+            TdxError::BadReportData(msg) => write!(f, "bad report data: {}", msg),
+            TdxError::QuoteTooLarge(len) => write!(f, "quote of {} bytes is too large", len),
+            TdxError::InvalidReport(msg) => write!(f, "invalid TD report: {}", msg),
+            TdxError::InvalidQuote(msg) => write!(f, "invalid quote: {}", msg),
+            TdxError::InvalidCertificate(msg) => write!(f, "invalid certificate: {}", msg),
+            TdxError::InvalidEventLog(msg) => write!(f, "invalid event log: {}", msg),
+            TdxError::InvalidRtmrIndex(index) => {
+                write!(f, "invalid RTMR index {}, expected 0 to 3", index)
+            }
+            TdxError::ConcurrentModification(expected, found) => write!(
+                f,
+                "report entry modified concurrently: expected generation {}, found {}",
+                expected, found
+            ),
+            TdxError::QuoteInFlight => write!(f, "quote request still in flight"),
+            TdxError::QuoteServiceUnavailable => {
+                write!(f, "quote generation service unavailable")
+            }
+            TdxError::QuoteStatus(status) => {
+                write!(f, "quote request failed with GHCI status {:#x}", status)
+            }
+            TdxError::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+            TdxError::DeviceBusy => write!(f, "device busy with an earlier request"),
+        }
+    }
+}
+
+impl std::error::Error for TdxError {}
+
+impl TdxError {
+    //failures that may go away when the request is sent again
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            TdxError::QuoteInFlight
+                | TdxError::QuoteServiceUnavailable
+                | TdxError::DeviceBusy
+                | TdxError::IoctlFailed(Errno::EBUSY | Errno::EAGAIN | Errno::EINTR)
+        )
+    }
+
+    //classify an I/O error on the given device node or interface path
+    pub(crate) fn from_io(path: &str, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => TdxError::DeviceNotFound,
+            io::ErrorKind::PermissionDenied => TdxError::PermissionDenied(path.to_string()),
+            _ => TdxError::Io(format!("{}: {}", path, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    //I/O errors are classified by kind
+    fn tdx_error_from_io() {
+        assert_eq!(
+            TdxError::from_io("/dev/tdx_guest", io::Error::from(io::ErrorKind::NotFound)),
+            TdxError::DeviceNotFound
+        );
+        assert_eq!(
+            TdxError::from_io(
+                "/dev/tdx_guest",
+                io::Error::from(io::ErrorKind::PermissionDenied)
+            ),
+            TdxError::PermissionDenied("/dev/tdx_guest".to_string())
+        );
+        assert!(matches!(
+            TdxError::from_io("/dev/tdx_guest", io::Error::from(io::ErrorKind::Other)),
+            TdxError::Io(_)
+        ));
+    }
+
+    #[test]
+    //only in-flight, unavailable and busy device errors are worth a retry
+    fn tdx_error_is_transient() {
+        assert!(TdxError::QuoteInFlight.is_transient());
+        assert!(TdxError::QuoteServiceUnavailable.is_transient());
+        assert!(TdxError::DeviceBusy.is_transient());
+        assert!(TdxError::IoctlFailed(Errno::EBUSY).is_transient());
+        assert!(!TdxError::IoctlFailed(Errno::EINVAL).is_transient());
+        assert!(!TdxError::QuoteStatus(0x8000000000000000).is_transient());
+        assert!(!TdxError::DeviceNotFound.is_transient());
+    }
+}
@@ -0,0 +1,541 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use crate::report::array;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::result::Result;
+use std::result::Result::Ok;
+
+pub const ACPI_TABLES_PATH: &str = "/sys/firmware/acpi/tables";
+
+//CC Event Log ACPI table as defined by the ACPI specification 6.5
+const CCEL_SIGNATURE: &[u8; 4] = b"CCEL";
+const CCEL_TABLE_LEN: usize = 56;
+
+//event types and the Spec ID event as defined by the TCG PC Client Platform Firmware Profile
+pub const EV_NO_ACTION: u32 = 0x3;
+pub const EV_IPL: u32 = 0xd;
+const SPEC_ID_EVENT03_SIGNATURE: &[u8; 16] = b"Spec ID Event03\0";
+const SHA1_DIGEST_LEN: usize = 20;
+
+//the unused part of the log area is filled with 0xff
+const EVENT_LOG_END: u32 = 0xffff_ffff;
+
+//TCG algorithm IDs of the digests found in TD event logs
+pub const TPM_ALG_SHA1: u16 = 0x0004;
+pub const TPM_ALG_SHA256: u16 = 0x000b;
+pub const TPM_ALG_SHA384: u16 = 0x000c;
+pub const TPM_ALG_SHA512: u16 = 0x000d;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CcelTable {
+    pub revision: u8,                 // Revision of the CCEL table
+    pub oem_id: [u8; 6],              // OEM ID of the table creator
+    pub oem_table_id: [u8; 8],        // OEM table ID of the table creator
+    pub oem_revision: u32,            // OEM revision of the table
+    pub creator_id: u32,              // Vendor ID of the utility that created the table
+    pub creator_revision: u32,        // Revision of the utility that created the table
+    pub cc_type: u8,                  // Confidential computing type: 1 for SEV, 2 for TDX
+    pub cc_subtype: u8,               // CC_TYPE-specific subtype
+    pub log_area_minimum_length: u64, // Size of the log area reserved by the firmware
+    pub log_area_start_address: u64,  // Physical address of the log area
+}
+
+impl CcelTable {
+    pub fn from_bytes(table: &[u8]) -> Result<Self, TdxError> {
+        if table.len() < CCEL_TABLE_LEN {
+            return Err(TdxError::InvalidEventLog(format!(
+                "[CcelTable::from_bytes] Truncated CCEL table: {} bytes, expected {}",
+                table.len(),
+                CCEL_TABLE_LEN
+            )));
+        }
+        if &table[0..4] != CCEL_SIGNATURE {
+            return Err(TdxError::InvalidEventLog(format!(
+                "[CcelTable::from_bytes] Unexpected ACPI table signature {:?}",
+                &table[0..4]
+            )));
+        }
+        let length = u32::from_le_bytes(array(table, 4)) as usize;
+        if length < CCEL_TABLE_LEN || length > table.len() {
+            return Err(TdxError::InvalidEventLog(format!(
+                "[CcelTable::from_bytes] Wrong CCEL table length {} for {} bytes",
+                length,
+                table.len()
+            )));
+        }
+        //all bytes of an ACPI table including the checksum add up to zero
+        if table[..length]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            != 0
+        {
+            return Err(TdxError::InvalidEventLog(
+                "[CcelTable::from_bytes] Wrong CCEL table checksum".to_string(),
+            ));
+        }
+
+        Ok(CcelTable {
+            revision: table[8],
+            oem_id: array(table, 10),
+            oem_table_id: array(table, 16),
+            oem_revision: u32::from_le_bytes(array(table, 24)),
+            creator_id: u32::from_le_bytes(array(table, 28)),
+            creator_revision: u32::from_le_bytes(array(table, 32)),
+            cc_type: table[36],
+            cc_subtype: table[37],
+            log_area_minimum_length: u64::from_le_bytes(array(table, 40)),
+            log_area_start_address: u64::from_le_bytes(array(table, 48)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlgorithmDigestSize {
+    pub algorithm_id: u16, // TCG algorithm ID
+    pub digest_size: u16,  // Size of the digests of this algorithm in bytes
+}
+
+//TCG_EfiSpecIDEvent, the event data of the first event which describes the log format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecIdEvent {
+    pub platform_class: u32,    // 0 for client platforms, 1 for servers
+    pub spec_version_minor: u8, // Minor version of the firmware profile specification
+    pub spec_version_major: u8, // Major version of the firmware profile specification
+    pub spec_errata: u8,        // Errata version of the firmware profile specification
+    pub uintn_size: u8,         // Size of UINTN fields: 1 for 32-bit, 2 for 64-bit
+    pub digest_sizes: Vec<AlgorithmDigestSize>, // Algorithms every event carries a digest of
+    pub vendor_info: Vec<u8>,   // Vendor-specific information
+}
+
+impl SpecIdEvent {
+    pub fn from_bytes(event: &[u8]) -> Result<Self, TdxError> {
+        let mut reader = EventLogReader::new(event);
+        if reader.read_bytes(16)? != SPEC_ID_EVENT03_SIGNATURE {
+            return Err(TdxError::InvalidEventLog(
+                "[SpecIdEvent::from_bytes] Missing Spec ID Event03 signature".to_string(),
+            ));
+        }
+
+        let platform_class = reader.read_u32()?;
+        let spec_version_minor = reader.read_u8()?;
+        let spec_version_major = reader.read_u8()?;
+        let spec_errata = reader.read_u8()?;
+        let uintn_size = reader.read_u8()?;
+        let number_of_algorithms = reader.read_u32()?;
+        let mut digest_sizes = Vec::new();
+        for _ in 0..number_of_algorithms {
+            digest_sizes.push(AlgorithmDigestSize {
+                algorithm_id: reader.read_u16()?,
+                digest_size: reader.read_u16()?,
+            });
+        }
+        let vendor_info_size = reader.read_u8()? as usize;
+        let vendor_info = reader.read_bytes(vendor_info_size)?.to_vec();
+
+        Ok(SpecIdEvent {
+            platform_class,
+            spec_version_minor,
+            spec_version_major,
+            spec_errata,
+            uintn_size,
+            digest_sizes,
+            vendor_info,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(SPEC_ID_EVENT03_SIGNATURE);
+        bytes.extend_from_slice(&self.platform_class.to_le_bytes());
+        bytes.extend_from_slice(&[
+            self.spec_version_minor,
+            self.spec_version_major,
+            self.spec_errata,
+            self.uintn_size,
+        ]);
+        bytes.extend_from_slice(&(self.digest_sizes.len() as u32).to_le_bytes());
+        for d in &self.digest_sizes {
+            bytes.extend_from_slice(&d.algorithm_id.to_le_bytes());
+            bytes.extend_from_slice(&d.digest_size.to_le_bytes());
+        }
+        bytes.push(self.vendor_info.len() as u8);
+        bytes.extend_from_slice(&self.vendor_info);
+        bytes
+    }
+
+    pub fn digest_size(&self, algorithm_id: u16) -> Option<usize> {
+        self.digest_sizes
+            .iter()
+            .find(|d| d.algorithm_id == algorithm_id)
+            .map(|d| d.digest_size as usize)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventDigest {
+    pub algorithm_id: u16, // TCG algorithm ID
+    pub digest: Vec<u8>,   // Digest of the event with this algorithm
+}
+
+//TCG_PCR_EVENT2, a measured event in crypto-agile format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventLogEntry {
+    pub mr_index: u32,   // CC measurement register: 0 for MRTD, 1-4 for RTMR0-3
+    pub event_type: u32, // TCG event type
+    pub digests: Vec<EventDigest>, // Digests the measurement register was extended with
+    pub event: Vec<u8>,  // Event data
+}
+
+impl EventLogEntry {
+    //RTMR extended by this event, None for MRTD
+    pub fn rtmr_index(&self) -> Option<usize> {
+        match self.mr_index {
+            1..=4 => Some(self.mr_index as usize - 1),
+            _ => None,
+        }
+    }
+
+    pub fn digest(&self, algorithm_id: u16) -> Option<&[u8]> {
+        self.digests
+            .iter()
+            .find(|d| d.algorithm_id == algorithm_id)
+            .map(|d| d.digest.as_slice())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.mr_index.to_le_bytes());
+        bytes.extend_from_slice(&self.event_type.to_le_bytes());
+        bytes.extend_from_slice(&(self.digests.len() as u32).to_le_bytes());
+        for d in &self.digests {
+            bytes.extend_from_slice(&d.algorithm_id.to_le_bytes());
+            bytes.extend_from_slice(&d.digest);
+        }
+        bytes.extend_from_slice(&(self.event.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.event);
+        bytes
+    }
+}
+
+//TCG PC Client crypto-agile event log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventLog {
+    pub spec_id_event: SpecIdEvent, // Log format from the leading TCG_PCR_EVENT
+    pub events: Vec<EventLogEntry>, // Events following the Spec ID event in log order
+}
+
+impl EventLog {
+    pub fn from_bytes(log: &[u8]) -> Result<Self, TdxError> {
+        let mut reader = EventLogReader::new(log);
+
+        //the first event is in the SHA1 TCG_PCR_EVENT format and carries the Spec ID event
+        let _mr_index = reader.read_u32()?;
+        let event_type = reader.read_u32()?;
+        if event_type != EV_NO_ACTION {
+            return Err(TdxError::InvalidEventLog(format!(
+                "[EventLog::from_bytes] First event of type {:#x} is not a Spec ID event",
+                event_type
+            )));
+        }
+        reader.read_bytes(SHA1_DIGEST_LEN)?;
+        let event_size = reader.read_u32()? as usize;
+        let spec_id_event = SpecIdEvent::from_bytes(reader.read_bytes(event_size)?)?;
+
+        let mut events = Vec::new();
+        while reader.remaining() >= 4 && reader.peek_u32()? != EVENT_LOG_END {
+            events.push(parse_event(&mut reader, &spec_id_event)?);
+        }
+
+        Ok(EventLog {
+            spec_id_event,
+            events,
+        })
+    }
+
+    //serialize back into the TCG log format, without the unused part of the log area
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let spec_id_event = self.spec_id_event.to_bytes();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&EV_NO_ACTION.to_le_bytes());
+        bytes.extend_from_slice(&[0; SHA1_DIGEST_LEN]);
+        bytes.extend_from_slice(&(spec_id_event.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&spec_id_event);
+        for event in &self.events {
+            bytes.extend_from_slice(&event.to_bytes());
+        }
+        bytes
+    }
+}
+
+fn parse_event(
+    reader: &mut EventLogReader,
+    spec_id_event: &SpecIdEvent,
+) -> Result<EventLogEntry, TdxError> {
+    let mr_index = reader.read_u32()?;
+    let event_type = reader.read_u32()?;
+    let digest_count = reader.read_u32()?;
+    let mut digests = Vec::new();
+    for _ in 0..digest_count {
+        let algorithm_id = reader.read_u16()?;
+        let digest_size = match spec_id_event.digest_size(algorithm_id) {
+            Some(s) => s,
+            None => {
+                return Err(TdxError::InvalidEventLog(format!(
+                    "[parse_event] Digest algorithm {:#x} is not declared in the Spec ID event",
+                    algorithm_id
+                )))
+            }
+        };
+        digests.push(EventDigest {
+            algorithm_id,
+            digest: reader.read_bytes(digest_size)?.to_vec(),
+        });
+    }
+    let event_size = reader.read_u32()? as usize;
+    let event = reader.read_bytes(event_size)?.to_vec();
+
+    Ok(EventLogEntry {
+        mr_index,
+        event_type,
+        digests,
+        event,
+    })
+}
+
+struct EventLogReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> EventLogReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        EventLogReader { data, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], TdxError> {
+        if len > self.remaining() {
+            return Err(TdxError::InvalidEventLog(format!(
+                "[EventLogReader::read_bytes] Truncated event log: {} bytes needed at offset {}, {} left",
+                len,
+                self.offset,
+                self.remaining()
+            )));
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, TdxError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, TdxError> {
+        Ok(u16::from_le_bytes(array(self.read_bytes(2)?, 0)))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, TdxError> {
+        Ok(u32::from_le_bytes(array(self.read_bytes(4)?, 0)))
+    }
+
+    fn peek_u32(&self) -> Result<u32, TdxError> {
+        let mut reader = EventLogReader::new(self.data);
+        reader.offset = self.offset;
+        reader.read_u32()
+    }
+}
+
+//the CCEL ACPI table and the event log in its log area, as exposed by the kernel in sysfs
+pub struct Ccel {
+    tables_dir: PathBuf,
+}
+
+impl Default for Ccel {
+    fn default() -> Self {
+        Ccel::new(ACPI_TABLES_PATH)
+    }
+}
+
+impl Ccel {
+    pub fn new(tables_dir: impl Into<PathBuf>) -> Self {
+        Ccel {
+            tables_dir: tables_dir.into(),
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.table_path().is_file()
+    }
+
+    pub fn read_table(&self) -> Result<CcelTable, TdxError> {
+        CcelTable::from_bytes(&read_file(self.table_path())?)
+    }
+
+    pub fn read_event_log(&self) -> Result<EventLog, TdxError> {
+        let table = self.read_table()?;
+        let mut log = read_file(self.tables_dir.join("data").join("CCEL"))?;
+        //the log never extends past the log area reserved by the firmware
+        log.truncate(table.log_area_minimum_length as usize);
+        EventLog::from_bytes(&log)
+    }
+
+    fn table_path(&self) -> PathBuf {
+        self.tables_dir.join("CCEL")
+    }
+}
+
+fn read_file(path: PathBuf) -> Result<Vec<u8>, TdxError> {
+    match fs::read(&path) {
+        Err(e) => Err(TdxError::from_io(&path.display().to_string(), e)),
+        Ok(data) => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod eventlog_tests {
+    use super::*;
+    use std::path::Path;
+
+    fn sample_spec_id_event() -> SpecIdEvent {
+        SpecIdEvent {
+            platform_class: 0,
+            spec_version_minor: 0,
+            spec_version_major: 2,
+            spec_errata: 0,
+            uintn_size: 2,
+            digest_sizes: vec![AlgorithmDigestSize {
+                algorithm_id: TPM_ALG_SHA384,
+                digest_size: 48,
+            }],
+            vendor_info: vec![],
+        }
+    }
+
+    fn sample_event(mr_index: u32, fill: u8) -> EventLogEntry {
+        EventLogEntry {
+            mr_index,
+            event_type: 0x8000_0001,
+            digests: vec![EventDigest {
+                algorithm_id: TPM_ALG_SHA384,
+                digest: vec![fill; 48],
+            }],
+            event: vec![fill; 10],
+        }
+    }
+
+    fn sample_log() -> EventLog {
+        EventLog {
+            spec_id_event: sample_spec_id_event(),
+            events: vec![
+                sample_event(1, 0x11),
+                sample_event(2, 0x22),
+                sample_event(0, 0x33),
+            ],
+        }
+    }
+
+    fn ccel_table(log_area_minimum_length: u64) -> Vec<u8> {
+        let mut table = vec![0u8; CCEL_TABLE_LEN];
+        table[0..4].copy_from_slice(CCEL_SIGNATURE);
+        table[4..8].copy_from_slice(&(CCEL_TABLE_LEN as u32).to_le_bytes());
+        table[8] = 1;
+        table[36] = 2;
+        table[40..48].copy_from_slice(&log_area_minimum_length.to_le_bytes());
+        table[48..56].copy_from_slice(&0x7f00_0000u64.to_le_bytes());
+        table[9] = 0u8.wrapping_sub(table.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+        table
+    }
+
+    fn fake_tables(dir: &Path, log_area: &[u8]) {
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("CCEL"), ccel_table(log_area.len() as u64)).unwrap();
+        fs::write(dir.join("data").join("CCEL"), log_area).unwrap();
+    }
+
+    #[test]
+    //the CCEL table header is parsed and its checksum verified
+    fn ccel_table_parse() {
+        let table = CcelTable::from_bytes(&ccel_table(0x10000)).unwrap();
+        assert_eq!(table.revision, 1);
+        assert_eq!(table.cc_type, 2);
+        assert_eq!(table.log_area_minimum_length, 0x10000);
+        assert_eq!(table.log_area_start_address, 0x7f00_0000);
+
+        let mut bad_checksum = ccel_table(0x10000);
+        bad_checksum[40] ^= 1;
+        assert!(CcelTable::from_bytes(&bad_checksum).is_err());
+        let mut bad_signature = ccel_table(0x10000);
+        bad_signature[0..4].copy_from_slice(b"TDEL");
+        assert!(CcelTable::from_bytes(&bad_signature).is_err());
+        assert!(CcelTable::from_bytes(&ccel_table(0x10000)[..40]).is_err());
+    }
+
+    #[test]
+    //the Spec ID event, per-algorithm digests and MR indexes are decoded
+    fn event_log_parse() {
+        let log = EventLog::from_bytes(&sample_log().to_bytes()).unwrap();
+        assert_eq!(log.spec_id_event.digest_size(TPM_ALG_SHA384), Some(48));
+        assert_eq!(log.spec_id_event.digest_size(TPM_ALG_SHA256), None);
+        assert_eq!(log.events.len(), 3);
+        assert_eq!(log.events[0].rtmr_index(), Some(0));
+        assert_eq!(log.events[1].rtmr_index(), Some(1));
+        assert_eq!(log.events[2].rtmr_index(), None);
+        assert_eq!(log.events[1].digest(TPM_ALG_SHA384), Some(&[0x22; 48][..]));
+        assert_eq!(log.events[1].event, vec![0x22; 10]);
+        assert_eq!(log, sample_log());
+    }
+
+    #[test]
+    //parsing stops at the 0xff filled end of the log area
+    fn event_log_padded_log_area() {
+        let mut log_area = sample_log().to_bytes();
+        log_area.resize(log_area.len() + 4096, 0xff);
+        assert_eq!(EventLog::from_bytes(&log_area).unwrap(), sample_log());
+    }
+
+    #[test]
+    //truncated events and undeclared digest algorithms are rejected
+    fn event_log_invalid() {
+        let log = sample_log().to_bytes();
+        assert!(matches!(
+            EventLog::from_bytes(&log[..log.len() - 5]),
+            Err(TdxError::InvalidEventLog(_))
+        ));
+
+        let mut undeclared = sample_log();
+        undeclared.events[0].digests[0].algorithm_id = TPM_ALG_SHA256;
+        assert!(EventLog::from_bytes(&undeclared.to_bytes()).is_err());
+
+        let mut no_spec_id = sample_log().to_bytes();
+        no_spec_id[4..8].copy_from_slice(&1u32.to_le_bytes());
+        assert!(EventLog::from_bytes(&no_spec_id).is_err());
+    }
+
+    #[test]
+    //the event log is read from the CCEL table data under the ACPI tables directory
+    fn ccel_read_event_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log_area = sample_log().to_bytes();
+        log_area.resize(log_area.len() + 256, 0xff);
+        fake_tables(dir.path(), &log_area);
+
+        let ccel = Ccel::new(dir.path());
+        assert!(ccel.is_available());
+        assert_eq!(ccel.read_event_log().unwrap(), sample_log());
+
+        let missing = Ccel::new(dir.path().join("missing"));
+        assert!(!missing.is_available());
+        assert_eq!(missing.read_event_log(), Err(TdxError::DeviceNotFound));
+    }
+}
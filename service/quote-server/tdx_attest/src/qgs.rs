@@ -0,0 +1,479 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use crate::report::array;
+use nix::sys::socket::{
+    connect, setsockopt, socket, sockopt, AddressFamily, SockFlag, SockType, VsockAddr,
+};
+use nix::sys::time::TimeVal;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::result::Result;
+use std::result::Result::Ok;
+use std::time::Duration;
+
+pub const QGS_MSG_MAJOR_VERSION: u16 = 1;
+pub const QGS_MSG_MINOR_VERSION: u16 = 0;
+pub const GET_QUOTE_REQ: u32 = 0;
+pub const GET_QUOTE_RESP: u32 = 1;
+
+//the QGS listens on the host, which is always vsock CID 2
+pub const QGS_VSOCK_CID: u32 = 2;
+pub const QGS_VSOCK_PORT: u32 = 4050;
+
+//a QGS that accepts the connection but never answers must not block the caller forever
+pub const DEFAULT_QGS_TIMEOUT: Duration = Duration::from_secs(30);
+
+//error codes reported by the QGS in the message header
+pub const QGS_MSG_ERROR_UNEXPECTED: u32 = 0x00012001;
+pub const QGS_MSG_ERROR_INVALID_PARAMETER: u32 = 0x00012003;
+
+//attestation key ID of the Intel SGX ECDSA quoting enclave
+pub const TDX_SGX_ECDSA_ATTESTATION_ID: AttKeyId = AttKeyId([
+    0xe8, 0x6c, 0x04, 0x6e, 0x8c, 0xc4, 0x4d, 0x95, 0x81, 0x73, 0xfc, 0x43, 0xc1, 0xfa, 0x4f, 0x3f,
+]);
+
+const ATT_KEY_ID_LEN: usize = 16;
+const QGS_MSG_HEADER_LEN: usize = 16;
+const QGS_GET_QUOTE_LEN: usize = QGS_MSG_HEADER_LEN + 8;
+//upper bound on a QGS message accepted from the wire
+const QGS_MSG_MAX_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QgsMsgHeader {
+    pub major_version: u16, // QGS message major version
+    pub minor_version: u16, // QGS message minor version
+    pub msg_type: u32,      // GET_QUOTE_REQ or GET_QUOTE_RESP
+    pub size: u32,          // size of the whole message, include this header, in byte
+    pub error_code: u32,    // used in response only
+}
+
+//UUID identifying an attestation key, and the quoting enclave owning it, on the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttKeyId(pub [u8; ATT_KEY_ID_LEN]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QgsQuoteResponse {
+    pub selected_id: Option<AttKeyId>, // Attestation key ID selected by the QGS, if reported
+    pub quote: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QgsAddress {
+    Vsock { cid: u32, port: u32 },
+    Unix(PathBuf),
+}
+
+impl Default for QgsAddress {
+    fn default() -> Self {
+        QgsAddress::Vsock {
+            cid: QGS_VSOCK_CID,
+            port: QGS_VSOCK_PORT,
+        }
+    }
+}
+
+impl QgsMsgHeader {
+    fn to_bytes(&self) -> [u8; QGS_MSG_HEADER_LEN] {
+        let mut bytes = [0u8; QGS_MSG_HEADER_LEN];
+        bytes[0..2].copy_from_slice(&self.major_version.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.minor_version.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.msg_type.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.error_code.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, TdxError> {
+        if bytes.len() < QGS_MSG_HEADER_LEN {
+            return Err(TdxError::InvalidQuote(format!(
+                "[QgsMsgHeader::from_bytes] Truncated QGS message header: {} bytes",
+                bytes.len()
+            )));
+        }
+        Ok(QgsMsgHeader {
+            major_version: u16::from_le_bytes(array(bytes, 0)),
+            minor_version: u16::from_le_bytes(array(bytes, 2)),
+            msg_type: u32::from_le_bytes(array(bytes, 4)),
+            size: u32::from_le_bytes(array(bytes, 8)),
+            error_code: u32::from_le_bytes(array(bytes, 12)),
+        })
+    }
+}
+
+//build a qgs_msg_get_quote_req carrying the TD report followed by the attestation key ID list
+pub fn encode_get_quote_req(report: &[u8], att_key_ids: &[AttKeyId]) -> Vec<u8> {
+    let id_list_size = att_key_ids.len() * ATT_KEY_ID_LEN;
+    let size = QGS_GET_QUOTE_LEN + report.len() + id_list_size;
+    let header = QgsMsgHeader {
+        major_version: QGS_MSG_MAJOR_VERSION,
+        minor_version: QGS_MSG_MINOR_VERSION,
+        msg_type: GET_QUOTE_REQ,
+        size: size as u32,
+        error_code: 0,
+    };
+
+    let mut msg = Vec::with_capacity(size);
+    msg.extend_from_slice(&header.to_bytes());
+    msg.extend_from_slice(&(report.len() as u32).to_le_bytes());
+    msg.extend_from_slice(&(id_list_size as u32).to_le_bytes());
+    msg.extend_from_slice(report);
+    for id in att_key_ids {
+        msg.extend_from_slice(&id.0);
+    }
+    msg
+}
+
+//parse a qgs_msg_get_quote_resp, bounds-checked against the message length
+pub fn decode_get_quote_resp(msg: &[u8]) -> Result<QgsQuoteResponse, TdxError> {
+    let header = QgsMsgHeader::from_bytes(msg)?;
+
+    if header.major_version != QGS_MSG_MAJOR_VERSION || header.msg_type != GET_QUOTE_RESP {
+        return Err(TdxError::InvalidQuote(format!(
+            "[decode_get_quote_resp] Unexpected QGS message: version {}.{}, type {}",
+            header.major_version, header.minor_version, header.msg_type
+        )));
+    }
+    if header.error_code != 0 {
+        return Err(TdxError::QgsError(header.error_code));
+    }
+    if header.size as usize != msg.len() || msg.len() < QGS_GET_QUOTE_LEN {
+        return Err(TdxError::InvalidQuote(format!(
+            "[decode_get_quote_resp] QGS message size {} does not match received {} bytes",
+            header.size,
+            msg.len()
+        )));
+    }
+
+    let selected_id_size = u32::from_le_bytes(array(msg, 16)) as usize;
+    let quote_size = u32::from_le_bytes(array(msg, 20)) as usize;
+    if QGS_GET_QUOTE_LEN + selected_id_size + quote_size != msg.len() {
+        return Err(TdxError::InvalidQuote(format!(
+            "[decode_get_quote_resp] Selected ID size {} and quote size {} do not fit a {} bytes message",
+            selected_id_size,
+            quote_size,
+            msg.len()
+        )));
+    }
+    if quote_size == 0 {
+        return Err(TdxError::InvalidQuote(
+            "[decode_get_quote_resp] Empty quote in QGS response".to_string(),
+        ));
+    }
+
+    let selected_id = match selected_id_size {
+        0 => None,
+        ATT_KEY_ID_LEN => Some(AttKeyId(array(msg, QGS_GET_QUOTE_LEN))),
+        _ => {
+            return Err(TdxError::InvalidQuote(format!(
+                "[decode_get_quote_resp] Malformed selected attestation key ID of {} bytes",
+                selected_id_size
+            )))
+        }
+    };
+
+    Ok(QgsQuoteResponse {
+        selected_id,
+        quote: msg[QGS_GET_QUOTE_LEN + selected_id_size..].to_vec(),
+    })
+}
+
+//client talking to the QGS directly instead of through the GetQuote ioctl
+pub struct QgsClient {
+    address: QgsAddress,
+    timeout: Option<Duration>,
+    att_key_ids: Vec<AttKeyId>,
+}
+
+impl QgsClient {
+    pub fn new(address: QgsAddress) -> Self {
+        QgsClient {
+            address,
+            timeout: Some(DEFAULT_QGS_TIMEOUT),
+            att_key_ids: Vec::new(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    //restrict the QGS to the given attestation keys, it picks any key when the list is empty
+    pub fn with_att_key_ids(mut self, att_key_ids: Vec<AttKeyId>) -> Self {
+        self.att_key_ids = att_key_ids;
+        self
+    }
+
+    pub fn get_quote(&self, report: &[u8]) -> Result<QgsQuoteResponse, TdxError> {
+        let request = encode_get_quote_req(report, &self.att_key_ids);
+        let response = match &self.address {
+            QgsAddress::Vsock { cid, port } => exchange(self.connect_vsock(*cid, *port)?, &request),
+            QgsAddress::Unix(path) => {
+                let stream = match UnixStream::connect(path) {
+                    Err(e) => {
+                        return Err(TdxError::QgsTransport(format!(
+                            "[QgsClient::get_quote] Fail to connect to QGS at {}: {:?}",
+                            path.display(),
+                            e
+                        )))
+                    }
+                    Ok(s) => s,
+                };
+                if let Err(e) = stream
+                    .set_read_timeout(self.timeout)
+                    .and_then(|_| stream.set_write_timeout(self.timeout))
+                {
+                    return Err(TdxError::QgsTransport(format!(
+                        "[QgsClient::get_quote] Fail to set socket timeout: {:?}",
+                        e
+                    )));
+                }
+                exchange(stream, &request)
+            }
+        };
+
+        match response {
+            Err(e) => Err(TdxError::QgsTransport(format!(
+                "[QgsClient::get_quote] Fail to talk to QGS: {:?}",
+                e
+            ))),
+            Ok(msg) => decode_get_quote_resp(&msg),
+        }
+    }
+
+    fn connect_vsock(&self, cid: u32, port: u32) -> Result<File, TdxError> {
+        let fd = socket(
+            AddressFamily::Vsock,
+            SockType::Stream,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .map_err(|e| {
+            TdxError::QgsTransport(format!(
+                "[QgsClient::connect_vsock] Fail to create socket: {:?}",
+                e
+            ))
+        })?;
+        //own the descriptor right away so that it is closed on every error path
+        let stream = unsafe { File::from_raw_fd(fd) };
+
+        if let Some(timeout) = self.timeout {
+            let timeval = TimeVal::new(timeout.as_secs() as _, timeout.subsec_micros() as _);
+            if let Err(e) = setsockopt(fd, sockopt::ReceiveTimeout, &timeval)
+                .and_then(|_| setsockopt(fd, sockopt::SendTimeout, &timeval))
+            {
+                return Err(TdxError::QgsTransport(format!(
+                    "[QgsClient::connect_vsock] Fail to set socket timeout: {:?}",
+                    e
+                )));
+            }
+        }
+
+        if let Err(e) = connect(fd, &VsockAddr::new(cid, port)) {
+            return Err(TdxError::QgsTransport(format!(
+                "[QgsClient::connect_vsock] Fail to connect to QGS at vsock {}:{}: {:?}",
+                cid, port, e
+            )));
+        }
+        Ok(stream)
+    }
+}
+
+//send one message with its 4-byte big-endian length prefix and read the reply
+fn exchange<S: Read + Write>(mut stream: S, msg: &[u8]) -> Result<Vec<u8>, io::Error> {
+    stream.write_all(&(msg.len() as u32).to_be_bytes())?;
+    stream.write_all(msg)?;
+    stream.flush()?;
+
+    let mut len_bytes = [0u8; 4];
+    stream.read_exact(&mut len_bytes)?;
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if len > QGS_MSG_MAX_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "QGS response of {} bytes exceeds the {} bytes limit",
+                len, QGS_MSG_MAX_LEN
+            ),
+        ));
+    }
+
+    let mut response = vec![0u8; len];
+    stream.read_exact(&mut response)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod qgs_tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    fn quote_resp(error_code: u32, selected_id: &[u8], quote: &[u8]) -> Vec<u8> {
+        let header = QgsMsgHeader {
+            major_version: QGS_MSG_MAJOR_VERSION,
+            minor_version: QGS_MSG_MINOR_VERSION,
+            msg_type: GET_QUOTE_RESP,
+            size: (QGS_GET_QUOTE_LEN + selected_id.len() + quote.len()) as u32,
+            error_code,
+        };
+        let mut msg = header.to_bytes().to_vec();
+        msg.extend_from_slice(&(selected_id.len() as u32).to_le_bytes());
+        msg.extend_from_slice(&(quote.len() as u32).to_le_bytes());
+        msg.extend_from_slice(selected_id);
+        msg.extend_from_slice(quote);
+        msg
+    }
+
+    //serve a single request on a Unix socket the way the QGS does
+    fn fake_qgs(path: PathBuf, response: Vec<u8>) -> thread::JoinHandle<Vec<u8>> {
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut len_bytes = [0u8; 4];
+            stream.read_exact(&mut len_bytes).unwrap();
+            let mut request = vec![0u8; u32::from_be_bytes(len_bytes) as usize];
+            stream.read_exact(&mut request).unwrap();
+
+            stream
+                .write_all(&(response.len() as u32).to_be_bytes())
+                .unwrap();
+            stream.write_all(&response).unwrap();
+            request
+        })
+    }
+
+    #[test]
+    //request message carries header, sizes and report
+    fn qgs_encode_get_quote_req() {
+        let msg = encode_get_quote_req(&[0xab; 1024], &[]);
+        let header = QgsMsgHeader::from_bytes(&msg).unwrap();
+
+        assert_eq!(msg.len(), 16 + 8 + 1024);
+        assert_eq!(header.major_version, 1);
+        assert_eq!(header.msg_type, GET_QUOTE_REQ);
+        assert_eq!(header.size as usize, msg.len());
+        assert_eq!(u32::from_le_bytes(msg[16..20].try_into().unwrap()), 1024);
+        assert_eq!(u32::from_le_bytes(msg[20..24].try_into().unwrap()), 0);
+        assert_eq!(&msg[24..], &[0xab; 1024][..]);
+    }
+
+    #[test]
+    //attestation key IDs follow the report and are counted in id_list_size
+    fn qgs_encode_get_quote_req_att_key_ids() {
+        let ids = [TDX_SGX_ECDSA_ATTESTATION_ID, AttKeyId([0x02; 16])];
+        let msg = encode_get_quote_req(&[0xab; 1024], &ids);
+        let header = QgsMsgHeader::from_bytes(&msg).unwrap();
+
+        assert_eq!(msg.len(), 16 + 8 + 1024 + 32);
+        assert_eq!(header.size as usize, msg.len());
+        assert_eq!(u32::from_le_bytes(msg[20..24].try_into().unwrap()), 32);
+        assert_eq!(&msg[1048..1064], &TDX_SGX_ECDSA_ATTESTATION_ID.0);
+        assert_eq!(&msg[1064..], &[0x02; 16]);
+    }
+
+    #[test]
+    //response message yields the selected ID and the quote
+    fn qgs_decode_get_quote_resp() {
+        let resp = decode_get_quote_resp(&quote_resp(0, &[0x01; 16], &[0x04, 0x00])).unwrap();
+        assert_eq!(resp.selected_id, Some(AttKeyId([0x01; 16])));
+        assert_eq!(resp.quote, vec![0x04, 0x00]);
+
+        let resp = decode_get_quote_resp(&quote_resp(0, &[], &[0x04, 0x00])).unwrap();
+        assert_eq!(resp.selected_id, None);
+    }
+
+    #[test]
+    //QGS error codes, wrong message types and inconsistent sizes are rejected
+    fn qgs_decode_get_quote_resp_invalid() {
+        assert!(decode_get_quote_resp(&quote_resp(0x12001, &[], &[0x04])).is_err());
+        assert!(decode_get_quote_resp(&quote_resp(0, &[], &[])).is_err());
+        assert!(decode_get_quote_resp(&encode_get_quote_req(&[0; 1024], &[])).is_err());
+        assert!(decode_get_quote_resp(&quote_resp(0, &[0x01; 8], &[0x04])).is_err());
+
+        let resp = quote_resp(0, &[], &[0x04, 0x00]);
+        assert!(decode_get_quote_resp(&resp[0..resp.len() - 1]).is_err());
+        assert!(decode_get_quote_resp(&resp[0..10]).is_err());
+
+        let mut resp = quote_resp(0, &[], &[0x04, 0x00]);
+        resp[20..24].copy_from_slice(&100u32.to_le_bytes());
+        assert!(decode_get_quote_resp(&resp).is_err());
+    }
+
+    #[test]
+    //QGS client exchanges length-prefixed messages over a Unix socket
+    fn qgs_client_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qgs.sock");
+        let server = fake_qgs(
+            path.clone(),
+            quote_resp(0, &TDX_SGX_ECDSA_ATTESTATION_ID.0, &[0x04, 0x00, 0x02]),
+        );
+
+        let client = QgsClient::new(QgsAddress::Unix(path))
+            .with_timeout(Duration::from_secs(5))
+            .with_att_key_ids(vec![TDX_SGX_ECDSA_ATTESTATION_ID]);
+        let resp = client.get_quote(&[0xcd; 1024]).unwrap();
+        assert_eq!(resp.quote, vec![0x04, 0x00, 0x02]);
+        assert_eq!(resp.selected_id, Some(TDX_SGX_ECDSA_ATTESTATION_ID));
+
+        let request = server.join().unwrap();
+        assert_eq!(
+            request,
+            encode_get_quote_req(&[0xcd; 1024], &[TDX_SGX_ECDSA_ATTESTATION_ID])
+        );
+    }
+
+    #[test]
+    //QGS client surfaces the QGS error code
+    fn qgs_client_unix_socket_error_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qgs.sock");
+        let server = fake_qgs(path.clone(), quote_resp(0x12001, &[], &[]));
+
+        let result = QgsClient::new(QgsAddress::Unix(path)).get_quote(&[0; 1024]);
+        server.join().unwrap();
+        assert_eq!(result, Err(TdxError::QgsError(0x12001)));
+    }
+
+    #[test]
+    //QGS client fails cleanly when nothing listens on the socket
+    fn qgs_client_unix_socket_not_listening() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = QgsClient::new(QgsAddress::Unix(dir.path().join("qgs.sock")));
+        assert!(client.get_quote(&[0; 1024]).is_err());
+    }
+
+    #[test]
+    //QGS client gives up on a QGS that accepts the connection but never answers
+    fn qgs_client_unix_socket_silent_qgs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qgs.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let _ = stream.read_to_end(&mut request);
+        });
+
+        assert_eq!(
+            QgsClient::new(QgsAddress::Unix(path.clone())).timeout,
+            Some(DEFAULT_QGS_TIMEOUT)
+        );
+        let client =
+            QgsClient::new(QgsAddress::Unix(path)).with_timeout(Duration::from_millis(200));
+        assert!(matches!(
+            client.get_quote(&[0; 1024]),
+            Err(TdxError::QgsTransport(_))
+        ));
+        drop(client);
+        server.join().unwrap();
+    }
+}
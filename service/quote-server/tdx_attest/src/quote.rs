@@ -0,0 +1,582 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use std::result::Result;
+use std::result::Result::Ok;
+
+use crate::report::{array, Rtmr, TdAttributes};
+
+pub const QUOTE_VERSION_4: u16 = 4;
+pub const QUOTE_VERSION_5: u16 = 5;
+pub const TEE_TYPE_TDX: u32 = 0x81;
+
+const QUOTE_HEADER_LEN: usize = 48;
+const TD_QUOTE_BODY_1_0_LEN: usize = 584;
+const TD_QUOTE_BODY_1_5_LEN: usize = 648;
+const ENCLAVE_REPORT_LEN: usize = 384;
+const ECDSA_P256_SIGNATURE_LEN: usize = 64;
+const ECDSA_P256_KEY_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttestationKeyType {
+    ECDSA_P256 = 2,
+    ECDSA_P384 = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuoteBodyType {
+    SGX_ENCLAVE_REPORT = 1,
+    TD_REPORT_1_0 = 2,
+    TD_REPORT_1_5 = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteHeader {
+    pub version: u16,                     // Quote version: 4, or 5 with a typed body
+    pub att_key_type: AttestationKeyType, // Type of the attestation key
+    pub tee_type: u32,                    // TEE for this attestation: 0x81 for TDX
+    pub reserved: [u8; 4],                // Reserved, QE/PCE SVN on older quotes
+    pub qe_vendor_id: [u8; 16],           // Unique vendor ID of the QE
+    pub user_data: [u8; 20],              // Custom user-defined data
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdQuoteBody {
+    pub body_type: QuoteBodyType, // Implicitly TD_REPORT_1_0 for v4 quotes
+    pub tee_tcb_svn: [u8; 16],    // TEE TCB SVN of the TDX module
+    #[serde(with = "BigArray")]
+    pub mrseam: [u8; 48], // Measurement of the TDX module
+    #[serde(with = "BigArray")]
+    pub mrsignerseam: [u8; 48], // Measurement of the TDX module signer
+    pub seam_attributes: [u8; 8], // Attributes of the TDX module
+    pub td_attributes: [u8; 8],   // TD ATTRIBUTES
+    pub xfam: [u8; 8],            // Extended features allowed mask
+    #[serde(with = "BigArray")]
+    pub mrtd: [u8; 48], // Measurement of the initial TD contents
+    #[serde(with = "BigArray")]
+    pub mrconfigid: [u8; 48], // Software-defined ID for non-owner-defined TD configuration
+    #[serde(with = "BigArray")]
+    pub mrowner: [u8; 48], // Software-defined ID for the TD owner
+    #[serde(with = "BigArray")]
+    pub mrownerconfig: [u8; 48], // Software-defined ID for owner-defined TD configuration
+    pub rtmrs: [Rtmr; 4],         // Runtime extendable measurement registers RTMR0-3
+    #[serde(with = "BigArray")]
+    pub report_data: [u8; 64], // REPORTDATA of the TD report
+    pub tee_tcb_svn2: [u8; 16],   // TEE TCB SVN of the TDX module after update, TDX 1.5 only
+    #[serde(with = "BigArray")]
+    pub mrservicetd: [u8; 48], // Measurement of bound service TDs, TDX 1.5 only
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnclaveReport {
+    pub cpusvn: [u8; 16],
+    pub miscselect: u32,
+    #[serde(with = "BigArray")]
+    pub reserved1: [u8; 28],
+    pub attributes: [u8; 16],
+    pub mrenclave: [u8; 32],
+    pub reserved2: [u8; 32],
+    pub mrsigner: [u8; 32],
+    #[serde(with = "BigArray")]
+    pub reserved3: [u8; 96],
+    pub isv_prod_id: u16,
+    pub isv_svn: u16,
+    #[serde(with = "BigArray")]
+    pub reserved4: [u8; 60],
+    #[serde(with = "BigArray")]
+    pub report_data: [u8; 64],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QeReportCertificationData {
+    pub qe_report: EnclaveReport, // SGX report of the QE
+    #[serde(with = "BigArray")]
+    pub qe_report_signature: [u8; 64], // ECDSA signature over the QE report by the PCK key
+    pub qe_auth_data: Vec<u8>,    // Authentication data bound into the QE report data
+    pub certification_data: CertificationData, // Certification data of the PCK key
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CertificationData {
+    PpidCleartext(Vec<u8>),
+    PpidRsa2048Encrypted(Vec<u8>),
+    PpidRsa3072Encrypted(Vec<u8>),
+    PckCert(Vec<u8>),
+    PckCertChain(Vec<u8>),
+    QeReportCertificationData(Box<QeReportCertificationData>),
+    PlatformManifest(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteSignatureData {
+    #[serde(with = "BigArray")]
+    pub signature: [u8; 64], // ECDSA signature over the quote header and body
+    #[serde(with = "BigArray")]
+    pub attestation_key: [u8; 64], // Raw ECDSA public key of the attestation key
+    pub certification_data: CertificationData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quote {
+    pub header: QuoteHeader,
+    pub body: TdQuoteBody,
+    pub signature_data: QuoteSignatureData,
+}
+
+impl Quote {
+    pub fn from_bytes(quote: &[u8]) -> Result<Self, TdxError> {
+        let mut reader = QuoteReader::new(quote);
+
+        let header = parse_quote_header(&mut reader)?;
+        let body = match header.version {
+            QUOTE_VERSION_4 => parse_td_quote_body(&mut reader, QuoteBodyType::TD_REPORT_1_0)?,
+            QUOTE_VERSION_5 => {
+                let body_type = match reader.read_u16()? {
+                    2 => QuoteBodyType::TD_REPORT_1_0,
+                    3 => QuoteBodyType::TD_REPORT_1_5,
+                    t => {
+                        return Err(TdxError::InvalidQuote(format!(
+                            "[Quote::from_bytes] Unsupported quote body type: {}",
+                            t
+                        )))
+                    }
+                };
+                let body_size = reader.read_u32()? as usize;
+                let expected_size = match body_type {
+                    QuoteBodyType::TD_REPORT_1_5 => TD_QUOTE_BODY_1_5_LEN,
+                    _ => TD_QUOTE_BODY_1_0_LEN,
+                };
+                if body_size != expected_size {
+                    return Err(TdxError::InvalidQuote(format!(
+                        "[Quote::from_bytes] Wrong quote body size {} for {:?}",
+                        body_size, body_type
+                    )));
+                }
+                parse_td_quote_body(&mut reader, body_type)?
+            }
+            v => {
+                return Err(TdxError::InvalidQuote(format!(
+                    "[Quote::from_bytes] Unsupported quote version: {}",
+                    v
+                )))
+            }
+        };
+
+        let signature_data_len = reader.read_u32()? as usize;
+        let mut signature_reader = QuoteReader::new(reader.read_bytes(signature_data_len)?);
+        let signature_data = parse_signature_data(&mut signature_reader, header.att_key_type)?;
+
+        Ok(Quote {
+            header,
+            body,
+            signature_data,
+        })
+    }
+
+    //length of the quote header and body covered by the attestation key signature
+    pub fn signed_data_len(&self) -> usize {
+        match (self.header.version, self.body.body_type) {
+            (QUOTE_VERSION_4, _) => QUOTE_HEADER_LEN + TD_QUOTE_BODY_1_0_LEN,
+            (_, QuoteBodyType::TD_REPORT_1_5) => QUOTE_HEADER_LEN + 6 + TD_QUOTE_BODY_1_5_LEN,
+            _ => QUOTE_HEADER_LEN + 6 + TD_QUOTE_BODY_1_0_LEN,
+        }
+    }
+
+    pub fn report_data(&self) -> &[u8; 64] {
+        &self.body.report_data
+    }
+
+    pub fn td_attributes(&self) -> TdAttributes {
+        TdAttributes::from_bytes(&self.body.td_attributes)
+    }
+
+    //the PCK certificate chain in PEM format, if present in the certification data
+    pub fn pck_cert_chain(&self) -> Option<&[u8]> {
+        match &self.signature_data.certification_data {
+            CertificationData::PckCertChain(chain) => Some(chain),
+            CertificationData::QeReportCertificationData(qe) => match &qe.certification_data {
+                CertificationData::PckCertChain(chain) => Some(chain),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn qe_report_certification_data(&self) -> Option<&QeReportCertificationData> {
+        match &self.signature_data.certification_data {
+            CertificationData::QeReportCertificationData(qe) => Some(qe),
+            _ => None,
+        }
+    }
+}
+
+impl EnclaveReport {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ENCLAVE_REPORT_LEN);
+        bytes.extend_from_slice(&self.cpusvn);
+        bytes.extend_from_slice(&self.miscselect.to_le_bytes());
+        bytes.extend_from_slice(&self.reserved1);
+        bytes.extend_from_slice(&self.attributes);
+        bytes.extend_from_slice(&self.mrenclave);
+        bytes.extend_from_slice(&self.reserved2);
+        bytes.extend_from_slice(&self.mrsigner);
+        bytes.extend_from_slice(&self.reserved3);
+        bytes.extend_from_slice(&self.isv_prod_id.to_le_bytes());
+        bytes.extend_from_slice(&self.isv_svn.to_le_bytes());
+        bytes.extend_from_slice(&self.reserved4);
+        bytes.extend_from_slice(&self.report_data);
+        bytes
+    }
+}
+
+//bounds-checked little-endian reader over quote bytes
+struct QuoteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> QuoteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        QuoteReader { bytes, offset: 0 }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], TdxError> {
+        let end = match self.offset.checked_add(len) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => {
+                return Err(TdxError::InvalidQuote(format!(
+                    "[QuoteReader::read_bytes] Truncated quote: need {} bytes at offset {}, {} available",
+                    len,
+                    self.offset,
+                    self.bytes.len() - self.offset
+                )))
+            }
+        };
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], TdxError> {
+        Ok(array(self.read_bytes(N)?, 0))
+    }
+
+    fn read_u16(&mut self) -> Result<u16, TdxError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, TdxError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+}
+
+fn parse_quote_header(reader: &mut QuoteReader) -> Result<QuoteHeader, TdxError> {
+    let version = reader.read_u16()?;
+    let att_key_type = match reader.read_u16()? {
+        2 => AttestationKeyType::ECDSA_P256,
+        3 => AttestationKeyType::ECDSA_P384,
+        t => {
+            return Err(TdxError::InvalidQuote(format!(
+                "[parse_quote_header] Unknown attestation key type: {}",
+                t
+            )))
+        }
+    };
+    let tee_type = reader.read_u32()?;
+    if tee_type != TEE_TYPE_TDX {
+        return Err(TdxError::InvalidQuote(format!(
+            "[parse_quote_header] Not a TDX quote: TEE type {:#x}",
+            tee_type
+        )));
+    }
+
+    Ok(QuoteHeader {
+        version,
+        att_key_type,
+        tee_type,
+        reserved: reader.read_array()?,
+        qe_vendor_id: reader.read_array()?,
+        user_data: reader.read_array()?,
+    })
+}
+
+fn parse_td_quote_body(
+    reader: &mut QuoteReader,
+    body_type: QuoteBodyType,
+) -> Result<TdQuoteBody, TdxError> {
+    let mut body = TdQuoteBody {
+        body_type,
+        tee_tcb_svn: reader.read_array()?,
+        mrseam: reader.read_array()?,
+        mrsignerseam: reader.read_array()?,
+        seam_attributes: reader.read_array()?,
+        td_attributes: reader.read_array()?,
+        xfam: reader.read_array()?,
+        mrtd: reader.read_array()?,
+        mrconfigid: reader.read_array()?,
+        mrowner: reader.read_array()?,
+        mrownerconfig: reader.read_array()?,
+        rtmrs: [
+            Rtmr(reader.read_array()?),
+            Rtmr(reader.read_array()?),
+            Rtmr(reader.read_array()?),
+            Rtmr(reader.read_array()?),
+        ],
+        report_data: reader.read_array()?,
+        tee_tcb_svn2: [0; 16],
+        mrservicetd: [0; 48],
+    };
+
+    if body_type == QuoteBodyType::TD_REPORT_1_5 {
+        body.tee_tcb_svn2 = reader.read_array()?;
+        body.mrservicetd = reader.read_array()?;
+    }
+
+    Ok(body)
+}
+
+fn parse_signature_data(
+    reader: &mut QuoteReader,
+    att_key_type: AttestationKeyType,
+) -> Result<QuoteSignatureData, TdxError> {
+    if att_key_type != AttestationKeyType::ECDSA_P256 {
+        return Err(TdxError::InvalidQuote(format!(
+            "[parse_signature_data] Unsupported attestation key type: {:?}",
+            att_key_type
+        )));
+    }
+
+    let signature = reader.read_array::<ECDSA_P256_SIGNATURE_LEN>()?;
+    let attestation_key = reader.read_array::<ECDSA_P256_KEY_LEN>()?;
+    let certification_data = parse_certification_data(reader)?;
+
+    Ok(QuoteSignatureData {
+        signature,
+        attestation_key,
+        certification_data,
+    })
+}
+
+fn parse_certification_data(reader: &mut QuoteReader) -> Result<CertificationData, TdxError> {
+    let cert_type = reader.read_u16()?;
+    let size = reader.read_u32()? as usize;
+    let data = reader.read_bytes(size)?;
+
+    let certification_data = match cert_type {
+        1 => CertificationData::PpidCleartext(data.to_vec()),
+        2 => CertificationData::PpidRsa2048Encrypted(data.to_vec()),
+        3 => CertificationData::PpidRsa3072Encrypted(data.to_vec()),
+        4 => CertificationData::PckCert(data.to_vec()),
+        5 => CertificationData::PckCertChain(data.to_vec()),
+        6 => CertificationData::QeReportCertificationData(Box::new(
+            parse_qe_report_certification_data(&mut QuoteReader::new(data))?,
+        )),
+        7 => CertificationData::PlatformManifest(data.to_vec()),
+        t => {
+            return Err(TdxError::InvalidQuote(format!(
+                "[parse_certification_data] Unknown certification data type: {}",
+                t
+            )))
+        }
+    };
+
+    Ok(certification_data)
+}
+
+fn parse_qe_report_certification_data(
+    reader: &mut QuoteReader,
+) -> Result<QeReportCertificationData, TdxError> {
+    let qe_report = parse_enclave_report(reader)?;
+    let qe_report_signature = reader.read_array()?;
+    let qe_auth_data_size = reader.read_u16()? as usize;
+    let qe_auth_data = reader.read_bytes(qe_auth_data_size)?.to_vec();
+    let certification_data = parse_certification_data(reader)?;
+
+    if reader.remaining() != 0 {
+        return Err(TdxError::InvalidQuote(format!(
+            "[parse_qe_report_certification_data] {} trailing bytes in QE report certification data",
+            reader.remaining()
+        )));
+    }
+
+    Ok(QeReportCertificationData {
+        qe_report,
+        qe_report_signature,
+        qe_auth_data,
+        certification_data,
+    })
+}
+
+fn parse_enclave_report(reader: &mut QuoteReader) -> Result<EnclaveReport, TdxError> {
+    Ok(EnclaveReport {
+        cpusvn: reader.read_array()?,
+        miscselect: reader.read_u32()?,
+        reserved1: reader.read_array()?,
+        attributes: reader.read_array()?,
+        mrenclave: reader.read_array()?,
+        reserved2: reader.read_array()?,
+        mrsigner: reader.read_array()?,
+        reserved3: reader.read_array()?,
+        isv_prod_id: reader.read_u16()?,
+        isv_svn: reader.read_u16()?,
+        reserved4: reader.read_array()?,
+        report_data: reader.read_array()?,
+    })
+}
+
+#[cfg(test)]
+mod quote_tests {
+    use super::*;
+
+    fn sample_header(version: u16) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&version.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&TEE_TYPE_TDX.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&[0x93; 16]);
+        header.extend_from_slice(&[0x01; 20]);
+        header
+    }
+
+    fn sample_body(len: usize) -> Vec<u8> {
+        let mut body = vec![0u8; len];
+        body[136..184].copy_from_slice(&[0x44; 48]);
+        body[520..584].copy_from_slice(&[0x22; 64]);
+        if len == TD_QUOTE_BODY_1_5_LEN {
+            body[584..600].copy_from_slice(&[0x33; 16]);
+            body[600..648].copy_from_slice(&[0x55; 48]);
+        }
+        body
+    }
+
+    fn sample_signature_data() -> Vec<u8> {
+        let pck_chain = b"-----BEGIN CERTIFICATE-----\n".to_vec();
+
+        let mut qe_cert_data = vec![0u8; ENCLAVE_REPORT_LEN];
+        qe_cert_data[320..384].copy_from_slice(&[0x66; 64]);
+        qe_cert_data.extend_from_slice(&[0x77; 64]);
+        qe_cert_data.extend_from_slice(&2u16.to_le_bytes());
+        qe_cert_data.extend_from_slice(&[0xaa, 0xbb]);
+        qe_cert_data.extend_from_slice(&5u16.to_le_bytes());
+        qe_cert_data.extend_from_slice(&(pck_chain.len() as u32).to_le_bytes());
+        qe_cert_data.extend_from_slice(&pck_chain);
+
+        let mut sig_data = Vec::new();
+        sig_data.extend_from_slice(&[0x88; 64]);
+        sig_data.extend_from_slice(&[0x99; 64]);
+        sig_data.extend_from_slice(&6u16.to_le_bytes());
+        sig_data.extend_from_slice(&(qe_cert_data.len() as u32).to_le_bytes());
+        sig_data.extend_from_slice(&qe_cert_data);
+        sig_data
+    }
+
+    fn sample_quote_v4() -> Vec<u8> {
+        let sig_data = sample_signature_data();
+        let mut quote = sample_header(QUOTE_VERSION_4);
+        quote.extend_from_slice(&sample_body(TD_QUOTE_BODY_1_0_LEN));
+        quote.extend_from_slice(&(sig_data.len() as u32).to_le_bytes());
+        quote.extend_from_slice(&sig_data);
+        quote
+    }
+
+    fn sample_quote_v5() -> Vec<u8> {
+        let sig_data = sample_signature_data();
+        let mut quote = sample_header(QUOTE_VERSION_5);
+        quote.extend_from_slice(&3u16.to_le_bytes());
+        quote.extend_from_slice(&(TD_QUOTE_BODY_1_5_LEN as u32).to_le_bytes());
+        quote.extend_from_slice(&sample_body(TD_QUOTE_BODY_1_5_LEN));
+        quote.extend_from_slice(&(sig_data.len() as u32).to_le_bytes());
+        quote.extend_from_slice(&sig_data);
+        quote
+    }
+
+    #[test]
+    //v4 quote parses header, TDX 1.0 body and signature data
+    fn quote_v4_parse() {
+        let raw = sample_quote_v4();
+        let quote = Quote::from_bytes(&raw).unwrap();
+
+        assert_eq!(quote.header.version, QUOTE_VERSION_4);
+        assert_eq!(quote.header.att_key_type, AttestationKeyType::ECDSA_P256);
+        assert_eq!(quote.header.qe_vendor_id, [0x93; 16]);
+        assert_eq!(quote.body.body_type, QuoteBodyType::TD_REPORT_1_0);
+        assert_eq!(quote.body.mrtd, [0x44; 48]);
+        assert_eq!(quote.report_data(), &[0x22; 64]);
+        assert_eq!(&raw[568..632], &quote.report_data()[..]);
+        assert_eq!(quote.signed_data_len(), 632);
+        assert_eq!(quote.signature_data.signature, [0x88; 64]);
+        assert_eq!(quote.signature_data.attestation_key, [0x99; 64]);
+
+        let qe = quote.qe_report_certification_data().unwrap();
+        assert_eq!(qe.qe_report.report_data, [0x66; 64]);
+        assert_eq!(qe.qe_report.to_bytes().len(), ENCLAVE_REPORT_LEN);
+        assert_eq!(qe.qe_report_signature, [0x77; 64]);
+        assert_eq!(qe.qe_auth_data, vec![0xaa, 0xbb]);
+        assert_eq!(
+            quote.pck_cert_chain().unwrap(),
+            b"-----BEGIN CERTIFICATE-----\n"
+        );
+    }
+
+    #[test]
+    //v5 quote parses the TDX 1.5 body including TEE_TCB_SVN2 and MRSERVICETD
+    fn quote_v5_parse() {
+        let quote = Quote::from_bytes(&sample_quote_v5()).unwrap();
+
+        assert_eq!(quote.header.version, QUOTE_VERSION_5);
+        assert_eq!(quote.body.body_type, QuoteBodyType::TD_REPORT_1_5);
+        assert_eq!(quote.body.mrtd, [0x44; 48]);
+        assert_eq!(quote.report_data(), &[0x22; 64]);
+        assert_eq!(quote.body.tee_tcb_svn2, [0x33; 16]);
+        assert_eq!(quote.body.mrservicetd, [0x55; 48]);
+        assert_eq!(quote.signed_data_len(), 48 + 6 + 648);
+    }
+
+    #[test]
+    //truncating a quote at any point is reported as an error
+    fn quote_truncated() {
+        let raw = sample_quote_v4();
+        for len in [0, 47, 100, 632, 700, raw.len() - 1] {
+            assert!(Quote::from_bytes(&raw[0..len]).is_err());
+        }
+    }
+
+    #[test]
+    //quote parser rejects unknown versions and non-TDX TEE types
+    fn quote_wrong_version_or_tee_type() {
+        let mut raw = sample_quote_v4();
+        raw[0] = 3;
+        assert!(Quote::from_bytes(&raw).is_err());
+
+        let mut raw = sample_quote_v4();
+        raw[4] = 0;
+        assert!(Quote::from_bytes(&raw).is_err());
+    }
+
+    #[test]
+    //v5 quote parser rejects a body size that does not match the body type
+    fn quote_v5_wrong_body_size() {
+        let mut raw = sample_quote_v5();
+        raw[50..54].copy_from_slice(&(TD_QUOTE_BODY_1_0_LEN as u32).to_le_bytes());
+        assert!(Quote::from_bytes(&raw).is_err());
+    }
+
+    #[test]
+    //quote round trips through serde
+    fn quote_serde_round_trip() {
+        let quote = Quote::from_bytes(&sample_quote_v5()).unwrap();
+        let json = serde_json::to_string(&quote).unwrap();
+        let decoded: Quote = serde_json::from_str(&json).unwrap();
+        assert_eq!(quote, decoded);
+    }
+}
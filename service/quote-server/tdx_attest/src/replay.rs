@@ -0,0 +1,251 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::device::TdxDevice;
+use crate::error::TdxError;
+use crate::eventlog::{EventLogEntry, EV_NO_ACTION, TPM_ALG_SHA384};
+use crate::report::TdReport;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+use std::result::Result;
+use std::result::Result::Ok;
+
+pub const RTMR_COUNT: usize = 4;
+pub const RTMR_LEN: usize = 48;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtmrReplay {
+    pub rtmr_index: usize,                    // RTMR0-3
+    pub replayed: Vec<u8>,                    // Value recomputed from the event log
+    pub reported: Vec<u8>,                    // Value in the TD report
+    pub event_count: usize,                   // Number of logged events extending this RTMR
+    pub first_divergent_event: Option<usize>, // Index into the event list, set on mismatch
+}
+
+impl RtmrReplay {
+    pub fn is_valid(&self) -> bool {
+        self.replayed == self.reported
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub rtmrs: Vec<RtmrReplay>, // Replay of RTMR0-3 in index order
+}
+
+impl ReplayResult {
+    pub fn is_valid(&self) -> bool {
+        self.rtmrs.iter().all(|r| r.is_valid())
+    }
+
+    //earliest event in the log from which the replay of any RTMR diverges
+    pub fn first_divergent_event(&self) -> Option<usize> {
+        self.rtmrs
+            .iter()
+            .filter_map(|r| r.first_divergent_event)
+            .min()
+    }
+}
+
+//RTMR[i] = SHA384(RTMR[i] || digest), starting from all zeros
+pub fn extend(rtmr: &[u8; RTMR_LEN], digest: &[u8; RTMR_LEN]) -> [u8; RTMR_LEN] {
+    let mut hasher = Sha384::new();
+    hasher.update(rtmr);
+    hasher.update(digest);
+    hasher.finalize().into()
+}
+
+//the SHA384 digest an event extends its RTMR with, None for events that extend no RTMR
+fn rtmr_extension(
+    position: usize,
+    event: &EventLogEntry,
+) -> Result<Option<(usize, [u8; RTMR_LEN])>, TdxError> {
+    let rtmr_index = match event.rtmr_index() {
+        Some(i) if event.event_type != EV_NO_ACTION => i,
+        _ => return Ok(None),
+    };
+    match event.digest(TPM_ALG_SHA384).map(<[u8; RTMR_LEN]>::try_from) {
+        Some(Ok(digest)) => Ok(Some((rtmr_index, digest))),
+        _ => Err(TdxError::InvalidEventLog(format!(
+            "[rtmr_extension] Event {} extends RTMR{} without a SHA384 digest",
+            position, rtmr_index
+        ))),
+    }
+}
+
+//recompute RTMR0-3 from the events in log order
+pub fn replay_rtmrs(events: &[EventLogEntry]) -> Result<[[u8; RTMR_LEN]; RTMR_COUNT], TdxError> {
+    let mut rtmrs = [[0u8; RTMR_LEN]; RTMR_COUNT];
+    for (position, event) in events.iter().enumerate() {
+        if let Some((i, digest)) = rtmr_extension(position, event)? {
+            rtmrs[i] = extend(&rtmrs[i], &digest);
+        }
+    }
+    Ok(rtmrs)
+}
+
+//replay the events and compare every RTMR with the TD report.
+//on mismatch the first divergent event is the first one after the longest prefix of the
+//log which replays to the reported value, the log has events the TD did not extend from there
+pub fn verify_rtmrs(events: &[EventLogEntry], report: &TdReport) -> Result<ReplayResult, TdxError> {
+    let mut rtmrs: Vec<RtmrReplay> = (0..RTMR_COUNT)
+        .map(|i| RtmrReplay {
+            rtmr_index: i,
+            replayed: vec![0; RTMR_LEN],
+            reported: report.td_info.rtmrs[i].0.to_vec(),
+            event_count: 0,
+            first_divergent_event: None,
+        })
+        .collect();
+    //whether the replay of the events so far equals the reported value
+    let mut matched: Vec<bool> = rtmrs.iter().map(|r| r.replayed == r.reported).collect();
+
+    let mut values = [[0u8; RTMR_LEN]; RTMR_COUNT];
+    for (position, event) in events.iter().enumerate() {
+        let (i, digest) = match rtmr_extension(position, event)? {
+            Some(e) => e,
+            None => continue,
+        };
+        let rtmr = &mut rtmrs[i];
+        if matched[i] || rtmr.event_count == 0 {
+            rtmr.first_divergent_event = Some(position);
+            matched[i] = false;
+        }
+        values[i] = extend(&values[i], &digest);
+        rtmr.event_count += 1;
+        if values[i][..] == rtmr.reported[..] {
+            matched[i] = true;
+        }
+    }
+
+    for (i, rtmr) in rtmrs.iter_mut().enumerate() {
+        rtmr.replayed = values[i].to_vec();
+        if rtmr.is_valid() {
+            rtmr.first_divergent_event = None;
+        }
+    }
+    Ok(ReplayResult { rtmrs })
+}
+
+//check the events against the RTMRs of a fresh TD report of the device
+pub fn verify_td_rtmrs(
+    device: &dyn TdxDevice,
+    events: &[EventLogEntry],
+) -> Result<ReplayResult, TdxError> {
+    let report = TdReport::from_bytes(&device.get_td_report(&[0; 64])?)?;
+    verify_rtmrs(events, &report)
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use super::*;
+    use crate::eventlog::EventDigest;
+    use crate::report::Rtmr;
+    use crate::simulator::TdxSimulator;
+
+    fn event(mr_index: u32, fill: u8) -> EventLogEntry {
+        EventLogEntry {
+            mr_index,
+            event_type: 0x8000_0001,
+            digests: vec![EventDigest {
+                algorithm_id: TPM_ALG_SHA384,
+                digest: vec![fill; RTMR_LEN],
+            }],
+            event: vec![],
+        }
+    }
+
+    fn sample_events() -> Vec<EventLogEntry> {
+        vec![
+            event(0, 0x01),
+            event(1, 0x02),
+            event(2, 0x03),
+            event(1, 0x04),
+            event(4, 0x05),
+        ]
+    }
+
+    //a simulated TD whose RTMRs were extended with the given events
+    fn simulator_for(events: &[EventLogEntry]) -> TdxSimulator {
+        let rtmrs = replay_rtmrs(events).unwrap();
+        let mut td_info = TdxSimulator::default().td_info();
+        td_info.rtmrs = rtmrs.map(Rtmr);
+        TdxSimulator::default().with_td_info(td_info)
+    }
+
+    #[test]
+    //RTMRs are extended with SHA384(RTMR || digest) in log order, MRTD events are skipped
+    fn replay_extends_in_order() {
+        let rtmrs = replay_rtmrs(&sample_events()).unwrap();
+        let rtmr0 = extend(&extend(&[0; 48], &[0x02; 48]), &[0x04; 48]);
+        assert_eq!(rtmrs[0], rtmr0);
+        assert_eq!(rtmrs[1], extend(&[0; 48], &[0x03; 48]));
+        assert_eq!(rtmrs[2], [0; 48]);
+        assert_eq!(rtmrs[3], extend(&[0; 48], &[0x05; 48]));
+
+        let mut no_action = sample_events();
+        no_action[1].event_type = EV_NO_ACTION;
+        assert_eq!(
+            replay_rtmrs(&no_action).unwrap()[0],
+            extend(&[0; 48], &[0x04; 48])
+        );
+    }
+
+    #[test]
+    //a log matching the TD report replays without divergence
+    fn verify_rtmrs_consistent() {
+        let events = sample_events();
+        let result = verify_td_rtmrs(&simulator_for(&events), &events).unwrap();
+        assert!(result.is_valid());
+        assert_eq!(result.first_divergent_event(), None);
+        assert_eq!(result.rtmrs[0].event_count, 2);
+    }
+
+    #[test]
+    //a tampered event is named as the first divergent event
+    fn verify_rtmrs_tampered_event() {
+        let events = sample_events();
+        let simulator = simulator_for(&events);
+
+        let mut tampered = events.clone();
+        tampered[1].digests[0].digest = vec![0xee; 48];
+        let result = verify_td_rtmrs(&simulator, &tampered).unwrap();
+        assert!(!result.is_valid());
+        assert!(!result.rtmrs[0].is_valid());
+        assert!(result.rtmrs[1].is_valid());
+        assert_eq!(result.first_divergent_event(), Some(1));
+    }
+
+    #[test]
+    //events logged beyond what the TD extended are named from the first extra one
+    fn verify_rtmrs_extra_events() {
+        let events = sample_events();
+        let simulator = simulator_for(&events);
+
+        let mut extra = events.clone();
+        extra.push(event(4, 0x06));
+        extra.push(event(4, 0x07));
+        let result = verify_td_rtmrs(&simulator, &extra).unwrap();
+        assert!(!result.rtmrs[3].is_valid());
+        assert_eq!(result.rtmrs[3].first_divergent_event, Some(5));
+        assert_eq!(result.first_divergent_event(), Some(5));
+
+        //events missing from the log leave nothing to name
+        let result = verify_td_rtmrs(&simulator, &events[..4]).unwrap();
+        assert!(!result.rtmrs[3].is_valid());
+        assert_eq!(result.rtmrs[3].first_divergent_event, None);
+    }
+
+    #[test]
+    //an event extending an RTMR without a SHA384 digest cannot be replayed
+    fn replay_missing_sha384_digest() {
+        let mut events = sample_events();
+        events[2].digests[0].digest = vec![0x03; 32];
+        assert!(matches!(
+            replay_rtmrs(&events),
+            Err(TdxError::InvalidEventLog(_))
+        ));
+    }
+}
@@ -0,0 +1,396 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use std::convert::TryInto;
+use std::result::Result;
+use std::result::Result::Ok;
+
+use crate::{TdxVersion, TDX_REPORT_LEN};
+
+//TDREPORT_STRUCT layout as defined by the TDX Module ABI specification
+const REPORT_MAC_STRUCT_OFFSET: usize = 0;
+const TEE_TCB_INFO_OFFSET: usize = 256;
+const TD_INFO_OFFSET: usize = 512;
+pub const REPORT_MAC_STRUCT_LEN: usize = 256;
+pub const TEE_TCB_INFO_LEN: usize = 256;
+pub const TD_INFO_LEN: usize = 512;
+
+//REPORTTYPE.type value identifying a TDX report
+const TEE_TYPE_TDX: u8 = 0x81;
+
+//TD ATTRIBUTES bits as defined by the TDX Module ABI specification
+const TD_ATTRIBUTES_DEBUG: u64 = 1 << 0;
+const TD_ATTRIBUTES_SEPT_VE_DISABLE: u64 = 1 << 28;
+const TD_ATTRIBUTES_MIGRATABLE: u64 = 1 << 29;
+const TD_ATTRIBUTES_PKS: u64 = 1 << 30;
+const TD_ATTRIBUTES_PERFMON: u64 = 1 << 63;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportType {
+    pub tee_type: u8, // Trusted execution environment type: 0x81 for TDX
+    pub subtype: u8,  // TYPE-specific subtype
+    pub version: u8,  // TYPE-specific version: 0 for TDX 1.0, 1 for TDX 1.5
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportMacStruct {
+    pub report_type: ReportType,
+    pub cpusvn: [u8; 16], // CPU SVN
+    #[serde(with = "BigArray")]
+    pub tee_tcb_info_hash: [u8; 48], // SHA384 of TEE_TCB_INFO
+    #[serde(with = "BigArray")]
+    pub tee_info_hash: [u8; 48], // SHA384 of TDINFO
+    #[serde(with = "BigArray")]
+    pub report_data: [u8; 64], // User-defined REPORTDATA
+    pub mac: [u8; 32],    // MAC over the REPORTMACSTRUCT
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeeTcbInfo {
+    pub valid: [u8; 8],        // Indicates which fields of TEE_TCB_INFO are valid
+    pub tee_tcb_svn: [u8; 16], // TEE TCB SVN of the TDX module
+    #[serde(with = "BigArray")]
+    pub mrseam: [u8; 48], // Measurement of the TDX module
+    #[serde(with = "BigArray")]
+    pub mrsignerseam: [u8; 48], // Measurement of the TDX module signer
+    pub attributes: [u8; 8],   // Attributes of the TDX module
+    pub tee_tcb_svn2: [u8; 16], // TEE TCB SVN of the TDX module after update, TDX 1.5 only
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdInfo {
+    pub attributes: [u8; 8], // TD ATTRIBUTES
+    pub xfam: [u8; 8],       // Extended features allowed mask
+    #[serde(with = "BigArray")]
+    pub mrtd: [u8; 48], // Measurement of the initial TD contents
+    #[serde(with = "BigArray")]
+    pub mrconfigid: [u8; 48], // Software-defined ID for non-owner-defined TD configuration
+    #[serde(with = "BigArray")]
+    pub mrowner: [u8; 48], // Software-defined ID for the TD owner
+    #[serde(with = "BigArray")]
+    pub mrownerconfig: [u8; 48], // Software-defined ID for owner-defined TD configuration
+    pub rtmrs: [Rtmr; 4],    // Runtime extendable measurement registers RTMR0-3
+    #[serde(with = "BigArray")]
+    pub servtd_hash: [u8; 48], // SHA384 of the TDINFO of bound service TDs, TDX 1.5 only
+}
+
+//decoded TD ATTRIBUTES, bits without a named flag are kept in raw
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdAttributes {
+    pub raw: u64,              // TD ATTRIBUTES as a little-endian 64-bit value
+    pub debug: bool,           // TD runs in debug mode, its state is visible to the host
+    pub sept_ve_disable: bool, // EPT violations on pending pages are not converted to #VE
+    pub migratable: bool,      // TD may be migrated
+    pub pks: bool,             // Supervisor protection keys are enabled
+    pub perfmon: bool,         // TD may use the performance monitoring counters
+}
+
+impl TdAttributes {
+    pub fn from_bytes(attributes: &[u8; 8]) -> Self {
+        let raw = u64::from_le_bytes(*attributes);
+        TdAttributes {
+            raw,
+            debug: raw & TD_ATTRIBUTES_DEBUG != 0,
+            sept_ve_disable: raw & TD_ATTRIBUTES_SEPT_VE_DISABLE != 0,
+            migratable: raw & TD_ATTRIBUTES_MIGRATABLE != 0,
+            pks: raw & TD_ATTRIBUTES_PKS != 0,
+            perfmon: raw & TD_ATTRIBUTES_PERFMON != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rtmr(#[serde(with = "BigArray")] pub [u8; 48]);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdReport {
+    pub report_mac_struct: ReportMacStruct,
+    pub tee_tcb_info: TeeTcbInfo,
+    pub td_info: TdInfo,
+}
+
+impl TdReport {
+    pub fn from_bytes(report: &[u8]) -> Result<Self, TdxError> {
+        if report.len() < TDX_REPORT_LEN as usize {
+            return Err(TdxError::InvalidReport(format!(
+                "[TdReport::from_bytes] Truncated TD report: {} bytes, expected {}",
+                report.len(),
+                TDX_REPORT_LEN
+            )));
+        }
+        if report.len() > TDX_REPORT_LEN as usize {
+            return Err(TdxError::InvalidReport(format!(
+                "[TdReport::from_bytes] Malformed TD report: {} bytes, expected {}",
+                report.len(),
+                TDX_REPORT_LEN
+            )));
+        }
+
+        let report_mac_struct = parse_report_mac_struct(&report[REPORT_MAC_STRUCT_OFFSET..])?;
+        if report_mac_struct.report_type.tee_type != TEE_TYPE_TDX {
+            return Err(TdxError::InvalidReport(format!(
+                "[TdReport::from_bytes] Malformed TD report: unexpected TEE type {:#x}",
+                report_mac_struct.report_type.tee_type
+            )));
+        }
+
+        Ok(TdReport {
+            report_mac_struct,
+            tee_tcb_info: parse_tee_tcb_info(&report[TEE_TCB_INFO_OFFSET..]),
+            td_info: parse_td_info(&report[TD_INFO_OFFSET..]),
+        })
+    }
+
+    pub fn report_data(&self) -> &[u8; 64] {
+        &self.report_mac_struct.report_data
+    }
+
+    pub fn rtmr(&self, index: usize) -> Option<&[u8; 48]> {
+        self.td_info.rtmrs.get(index).map(|r| &r.0)
+    }
+
+    //TDX module version the report was generated by, from REPORTTYPE.version
+    pub fn tdx_version(&self) -> Result<TdxVersion, TdxError> {
+        match self.report_mac_struct.report_type.version {
+            0 => Ok(TdxVersion::TDX_1_0),
+            1 => Ok(TdxVersion::TDX_1_5),
+            v => Err(TdxError::InvalidReport(format!(
+                "[TdReport::tdx_version] Unknown TD report version {}",
+                v
+            ))),
+        }
+    }
+
+    pub fn td_attributes(&self) -> TdAttributes {
+        TdAttributes::from_bytes(&self.td_info.attributes)
+    }
+
+    //a debug TD cannot protect its secrets from the host
+    pub fn is_debug(&self) -> bool {
+        self.td_attributes().debug
+    }
+
+    //serialize back into TDREPORT_STRUCT layout, reserved bytes are zero
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut report = vec![0u8; TDX_REPORT_LEN as usize];
+        report[REPORT_MAC_STRUCT_OFFSET..TEE_TCB_INFO_OFFSET]
+            .copy_from_slice(&self.report_mac_struct.to_bytes());
+        report[TEE_TCB_INFO_OFFSET..TD_INFO_OFFSET].copy_from_slice(&self.tee_tcb_info.to_bytes());
+        report[TD_INFO_OFFSET..].copy_from_slice(&self.td_info.to_bytes());
+        report
+    }
+}
+
+impl ReportMacStruct {
+    pub fn to_bytes(&self) -> [u8; REPORT_MAC_STRUCT_LEN] {
+        let mut bytes = [0u8; REPORT_MAC_STRUCT_LEN];
+        bytes[0] = self.report_type.tee_type;
+        bytes[1] = self.report_type.subtype;
+        bytes[2] = self.report_type.version;
+        bytes[16..32].copy_from_slice(&self.cpusvn);
+        bytes[32..80].copy_from_slice(&self.tee_tcb_info_hash);
+        bytes[80..128].copy_from_slice(&self.tee_info_hash);
+        bytes[128..192].copy_from_slice(&self.report_data);
+        bytes[224..256].copy_from_slice(&self.mac);
+        bytes
+    }
+}
+
+impl TeeTcbInfo {
+    pub fn to_bytes(&self) -> [u8; TEE_TCB_INFO_LEN] {
+        let mut bytes = [0u8; TEE_TCB_INFO_LEN];
+        bytes[0..8].copy_from_slice(&self.valid);
+        bytes[8..24].copy_from_slice(&self.tee_tcb_svn);
+        bytes[24..72].copy_from_slice(&self.mrseam);
+        bytes[72..120].copy_from_slice(&self.mrsignerseam);
+        bytes[120..128].copy_from_slice(&self.attributes);
+        bytes[128..144].copy_from_slice(&self.tee_tcb_svn2);
+        bytes
+    }
+}
+
+impl TdInfo {
+    pub fn to_bytes(&self) -> [u8; TD_INFO_LEN] {
+        let mut bytes = [0u8; TD_INFO_LEN];
+        bytes[0..8].copy_from_slice(&self.attributes);
+        bytes[8..16].copy_from_slice(&self.xfam);
+        bytes[16..64].copy_from_slice(&self.mrtd);
+        bytes[64..112].copy_from_slice(&self.mrconfigid);
+        bytes[112..160].copy_from_slice(&self.mrowner);
+        bytes[160..208].copy_from_slice(&self.mrownerconfig);
+        for (i, rtmr) in self.rtmrs.iter().enumerate() {
+            bytes[208 + i * 48..256 + i * 48].copy_from_slice(&rtmr.0);
+        }
+        bytes[400..448].copy_from_slice(&self.servtd_hash);
+        bytes
+    }
+}
+
+pub(crate) fn array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N]
+        .try_into()
+        .expect("slice length checked by caller")
+}
+
+fn parse_report_mac_struct(bytes: &[u8]) -> Result<ReportMacStruct, TdxError> {
+    if bytes[3] != 0 {
+        return Err(TdxError::InvalidReport(
+            "[parse_report_mac_struct] Malformed TD report: non-zero reserved REPORTTYPE byte"
+                .to_string(),
+        ));
+    }
+
+    Ok(ReportMacStruct {
+        report_type: ReportType {
+            tee_type: bytes[0],
+            subtype: bytes[1],
+            version: bytes[2],
+        },
+        cpusvn: array(bytes, 16),
+        tee_tcb_info_hash: array(bytes, 32),
+        tee_info_hash: array(bytes, 80),
+        report_data: array(bytes, 128),
+        mac: array(bytes, 224),
+    })
+}
+
+fn parse_tee_tcb_info(bytes: &[u8]) -> TeeTcbInfo {
+    TeeTcbInfo {
+        valid: array(bytes, 0),
+        tee_tcb_svn: array(bytes, 8),
+        mrseam: array(bytes, 24),
+        mrsignerseam: array(bytes, 72),
+        attributes: array(bytes, 120),
+        tee_tcb_svn2: array(bytes, 128),
+    }
+}
+
+fn parse_td_info(bytes: &[u8]) -> TdInfo {
+    TdInfo {
+        attributes: array(bytes, 0),
+        xfam: array(bytes, 8),
+        mrtd: array(bytes, 16),
+        mrconfigid: array(bytes, 64),
+        mrowner: array(bytes, 112),
+        mrownerconfig: array(bytes, 160),
+        rtmrs: [
+            Rtmr(array(bytes, 208)),
+            Rtmr(array(bytes, 256)),
+            Rtmr(array(bytes, 304)),
+            Rtmr(array(bytes, 352)),
+        ],
+        servtd_hash: array(bytes, 400),
+    }
+}
+
+#[cfg(test)]
+mod report_tests {
+    use super::*;
+
+    fn sample_report() -> Vec<u8> {
+        let mut report = vec![0u8; TDX_REPORT_LEN as usize];
+        report[0] = TEE_TYPE_TDX;
+        report[2] = 1;
+        report[16..32].copy_from_slice(&[0x11; 16]);
+        report[128..192].copy_from_slice(&[0x22; 64]);
+        report[256 + 8..256 + 24].copy_from_slice(&[0x33; 16]);
+        report[512..520].copy_from_slice(&[0x10, 0, 0, 0, 0, 0, 0, 0]);
+        report[512 + 8..512 + 16].copy_from_slice(&[0xe7, 0x02, 0x06, 0, 0, 0, 0, 0]);
+        report[512 + 16..512 + 64].copy_from_slice(&[0x44; 48]);
+        report[512 + 64..512 + 112].copy_from_slice(&[0x45; 48]);
+        report[512 + 112..512 + 160].copy_from_slice(&[0x46; 48]);
+        report[512 + 160..512 + 208].copy_from_slice(&[0x47; 48]);
+        for i in 0..4 {
+            let offset = 512 + 208 + i * 48;
+            report[offset..offset + 48].copy_from_slice(&[0x50 + i as u8; 48]);
+        }
+        report[512 + 400..512 + 448].copy_from_slice(&[0x60; 48]);
+        report
+    }
+
+    #[test]
+    //TdReport parses every named field from its offset
+    fn td_report_parse_fields() {
+        let report = TdReport::from_bytes(&sample_report()).unwrap();
+
+        assert_eq!(report.report_mac_struct.report_type.tee_type, TEE_TYPE_TDX);
+        assert_eq!(report.report_mac_struct.report_type.version, 1);
+        assert_eq!(report.tdx_version(), Ok(TdxVersion::TDX_1_5));
+        assert_eq!(report.report_mac_struct.cpusvn, [0x11; 16]);
+        assert_eq!(report.report_data(), &[0x22; 64]);
+        assert_eq!(report.tee_tcb_info.tee_tcb_svn, [0x33; 16]);
+        assert_eq!(report.td_info.attributes, [0x10, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(report.td_info.xfam, [0xe7, 0x02, 0x06, 0, 0, 0, 0, 0]);
+        assert_eq!(report.td_info.mrtd, [0x44; 48]);
+        assert_eq!(report.td_info.mrconfigid, [0x45; 48]);
+        assert_eq!(report.td_info.mrowner, [0x46; 48]);
+        assert_eq!(report.td_info.mrownerconfig, [0x47; 48]);
+        for i in 0..4 {
+            assert_eq!(report.rtmr(i).unwrap(), &[0x50 + i as u8; 48]);
+        }
+        assert!(report.rtmr(4).is_none());
+        assert_eq!(report.td_info.servtd_hash, [0x60; 48]);
+    }
+
+    #[test]
+    //TD ATTRIBUTES flags are decoded from their bit positions
+    fn td_attributes_decode() {
+        let attributes = TdAttributes::from_bytes(&[0x01, 0, 0, 0x70, 0, 0, 0, 0x80]);
+        assert_eq!(attributes.raw, 0x8000_0000_7000_0001);
+        assert!(attributes.debug);
+        assert!(attributes.sept_ve_disable);
+        assert!(attributes.migratable);
+        assert!(attributes.pks);
+        assert!(attributes.perfmon);
+
+        let report = TdReport::from_bytes(&sample_report()).unwrap();
+        assert_eq!(report.td_attributes().raw, 0x10);
+        assert!(!report.is_debug());
+        assert!(!report.td_attributes().sept_ve_disable);
+    }
+
+    #[test]
+    //TdReport rejects a buffer shorter than 1024 bytes
+    fn td_report_truncated() {
+        let report = sample_report();
+        assert!(TdReport::from_bytes(&report[0..1000]).is_err());
+        assert!(TdReport::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    //TdReport rejects a buffer longer than 1024 bytes
+    fn td_report_oversized() {
+        let mut report = sample_report();
+        report.push(0);
+        assert!(TdReport::from_bytes(&report).is_err());
+    }
+
+    #[test]
+    //TdReport rejects a report that is not of TDX type
+    fn td_report_wrong_tee_type() {
+        let mut report = sample_report();
+        report[0] = 0;
+        assert!(TdReport::from_bytes(&report).is_err());
+    }
+
+    #[test]
+    //TdReport serializes back into the bytes it was parsed from
+    fn td_report_to_bytes_round_trip() {
+        let report = sample_report();
+        assert_eq!(TdReport::from_bytes(&report).unwrap().to_bytes(), report);
+    }
+
+    #[test]
+    //TdReport round trips through serde
+    fn td_report_serde_round_trip() {
+        let report = TdReport::from_bytes(&sample_report()).unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        let decoded: TdReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(report, decoded);
+    }
+}
@@ -0,0 +1,179 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use std::cmp;
+use std::result::Result;
+use std::result::Result::Ok;
+use std::thread;
+use std::time::{Duration, Instant};
+
+//how often and how long transient quote failures are retried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32, // Retries after the first attempt, 0 disables retrying
+    pub initial_backoff: Duration, // Delay before the first retry, doubled for every further retry
+    pub max_backoff: Duration, // Upper bound for the delay between two attempts
+    pub timeout: Duration, // Deadline for all attempts together, including a running attempt
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(2),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    //a single attempt, failures are returned as they are
+    pub fn no_retry() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    //run the operation until it succeeds, fails permanently, or retries or time run out.
+    //every attempt gets the overall deadline, an attempt that may block in the device
+    //has to stop waiting by then, e.g. by running on a DeviceThread
+    pub fn run<T, F>(&self, mut operation: F) -> Result<T, TdxError>
+    where
+        F: FnMut(Instant) -> Result<T, TdxError>,
+    {
+        let deadline = Instant::now() + self.timeout;
+        let mut retries = 0;
+
+        loop {
+            let error = match operation(deadline) {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+            thread::sleep(self.retry_delay(error, retries, deadline)?);
+            retries += 1;
+        }
+    }
+
+    //delay before the next retry, or the error to give up with
+    pub fn retry_delay(
+        &self,
+        error: TdxError,
+        retries: u32,
+        deadline: Instant,
+    ) -> Result<Duration, TdxError> {
+        if !error.is_transient() || retries >= self.max_retries {
+            return Err(error);
+        }
+        let backoff = self.backoff(retries);
+        if Instant::now() + backoff >= deadline {
+            return Err(TdxError::Timeout(self.timeout));
+        }
+        Ok(backoff)
+    }
+
+    //initial backoff doubled for every earlier retry, capped at max_backoff
+    fn backoff(&self, retries: u32) -> Duration {
+        let factor = 1u32.checked_shl(retries).unwrap_or(u32::MAX);
+        cmp::min(
+            self.initial_backoff.saturating_mul(factor),
+            self.max_backoff,
+        )
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    //transient failures are retried until the operation succeeds
+    fn retry_transient_then_success() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let result =
+            fast_policy(5).run(move |_| match counter.fetch_add(1, Ordering::SeqCst) + 1 {
+                1 => Err(TdxError::QuoteInFlight),
+                2 => Err(TdxError::QuoteServiceUnavailable),
+                n => Ok(n),
+            });
+        assert_eq!(result, Ok(3));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    //permanent failures are returned right away
+    fn retry_permanent_failure() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let result: Result<(), TdxError> = fast_policy(5).run(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(TdxError::QuoteStatus(0x8000000000000000))
+        });
+        assert_eq!(result, Err(TdxError::QuoteStatus(0x8000000000000000)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    //the last transient failure is returned once the retries are used up
+    fn retry_exhausted() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let result: Result<(), TdxError> = fast_policy(2).run(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(TdxError::QuoteInFlight)
+        });
+        assert_eq!(result, Err(TdxError::QuoteInFlight));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let result: Result<(), TdxError> = RetryPolicy::no_retry().run(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(TdxError::QuoteInFlight)
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    //no retry is started that would end past the overall deadline
+    fn retry_timeout() {
+        let policy = RetryPolicy {
+            max_retries: 100,
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(20),
+            timeout: Duration::from_millis(50),
+        };
+        let started = Instant::now();
+        let result: Result<(), TdxError> = policy.run(|_| Err(TdxError::QuoteInFlight));
+        assert_eq!(result, Err(TdxError::Timeout(Duration::from_millis(50))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    //backoff doubles per retry up to max_backoff
+    fn retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(1), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(1600));
+        assert_eq!(policy.backoff(4), Duration::from_secs(2));
+        assert_eq!(policy.backoff(40), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,153 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::error::TdxError;
+use crate::eventlog::EventLogEntry;
+use crate::replay::{RTMR_COUNT, RTMR_LEN};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::result::Result;
+use std::result::Result::Ok;
+use std::sync::{Mutex, OnceLock};
+
+//measurement registers of the TDX guest driver on kernels with TSM measurement support
+pub const TDX_MEASUREMENTS_PATH: &str = "/sys/class/misc/tdx_guest/measurements";
+
+pub fn check_rtmr_index(index: usize) -> Result<(), TdxError> {
+    if index >= RTMR_COUNT {
+        return Err(TdxError::InvalidRtmrIndex(index));
+    }
+    Ok(())
+}
+
+//RTMR extension through the sysfs measurement registers, writing a digest extends the register
+pub struct SysfsRtmrs {
+    root: PathBuf,
+}
+
+impl Default for SysfsRtmrs {
+    fn default() -> Self {
+        SysfsRtmrs::new(TDX_MEASUREMENTS_PATH)
+    }
+}
+
+impl SysfsRtmrs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        SysfsRtmrs { root: root.into() }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.root.is_dir()
+    }
+
+    pub fn extend(&self, index: usize, digest: &[u8; RTMR_LEN]) -> Result<(), TdxError> {
+        check_rtmr_index(index)?;
+        let path = self.root.join(format!("rtmr{}:sha384", index));
+        //the register file exists already, it is never created
+        let result = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(digest));
+        match result {
+            Err(e) => Err(TdxError::from_io(&path.display().to_string(), e)),
+            Ok(()) => Ok(()),
+        }
+    }
+}
+
+//events of the RTMR extensions made at runtime, in the order the registers were extended
+#[derive(Debug, Default)]
+pub struct RuntimeEventLog {
+    events: Mutex<Vec<EventLogEntry>>,
+}
+
+impl RuntimeEventLog {
+    //run the extension and record its event once it succeeded. the lock is held across both,
+    //so concurrent extensions of the same RTMR are logged in the order they were applied
+    pub fn record<F>(&self, event: EventLogEntry, extend: F) -> Result<(), TdxError>
+    where
+        F: FnOnce() -> Result<(), TdxError>,
+    {
+        let mut events = match self.events.lock() {
+            Err(e) => e.into_inner(),
+            Ok(e) => e,
+        };
+        extend()?;
+        events.push(event);
+        Ok(())
+    }
+
+    pub fn events(&self) -> Vec<EventLogEntry> {
+        match self.events.lock() {
+            Err(e) => e.into_inner().clone(),
+            Ok(e) => e.clone(),
+        }
+    }
+}
+
+//the RTMRs of the guest are shared by the whole TD, so is the log of their extensions
+pub(crate) fn guest_runtime_event_log() -> &'static RuntimeEventLog {
+    static GUEST_RUNTIME_EVENT_LOG: OnceLock<RuntimeEventLog> = OnceLock::new();
+    GUEST_RUNTIME_EVENT_LOG.get_or_init(RuntimeEventLog::default)
+}
+
+#[cfg(test)]
+mod rtmr_tests {
+    use super::*;
+    use nix::errno::Errno;
+    use std::fs;
+
+    fn event(mr_index: u32) -> EventLogEntry {
+        EventLogEntry {
+            mr_index,
+            event_type: 0xd,
+            digests: vec![],
+            event: vec![],
+        }
+    }
+
+    #[test]
+    //the digest is written to the register file of the RTMR
+    fn sysfs_rtmrs_extend() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..4 {
+            fs::write(dir.path().join(format!("rtmr{}:sha384", i)), []).unwrap();
+        }
+
+        let registers = SysfsRtmrs::new(dir.path());
+        assert!(registers.is_available());
+        registers.extend(3, &[0x33; 48]).unwrap();
+        assert_eq!(
+            fs::read(dir.path().join("rtmr3:sha384")).unwrap(),
+            vec![0x33; 48]
+        );
+        assert!(fs::read(dir.path().join("rtmr2:sha384"))
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            registers.extend(4, &[0x33; 48]),
+            Err(TdxError::InvalidRtmrIndex(4))
+        );
+        assert_eq!(
+            SysfsRtmrs::new(dir.path().join("missing")).extend(3, &[0; 48]),
+            Err(TdxError::DeviceNotFound)
+        );
+    }
+
+    #[test]
+    //only successful extensions are recorded
+    fn runtime_event_log_record() {
+        let log = RuntimeEventLog::default();
+        log.record(event(3), || Ok(())).unwrap();
+        assert_eq!(
+            log.record(event(4), || Err(TdxError::IoctlFailed(Errno::EIO))),
+            Err(TdxError::IoctlFailed(Errno::EIO))
+        );
+        log.record(event(4), || Ok(())).unwrap();
+        assert_eq!(log.events(), vec![event(3), event(4)]);
+    }
+}
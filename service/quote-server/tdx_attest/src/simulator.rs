@@ -0,0 +1,351 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::device::{TdxDevice, TdxInterface};
+use crate::error::TdxError;
+use crate::qgs::{
+    AttKeyId, QgsQuoteResponse, QGS_MSG_ERROR_INVALID_PARAMETER, TDX_SGX_ECDSA_ATTESTATION_ID,
+};
+use crate::replay;
+use crate::report::{ReportMacStruct, ReportType, Rtmr, TdInfo, TdReport, TeeTcbInfo};
+use crate::rtmr::{self, RuntimeEventLog};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use sha2::{Digest, Sha256, Sha384};
+use std::result::Result;
+use std::result::Result::Ok;
+use std::sync::Mutex;
+
+//test keys and certificates, never trusted by a production verifier
+pub const SIMULATOR_ROOT_CA: &str = include_str!("../testdata/root_ca.pem");
+const SIMULATOR_PCK_CERT_CHAIN: &str = include_str!("../testdata/pck_cert_chain.pem");
+const SIMULATOR_PCK_KEY: &str = include_str!("../testdata/pck.key");
+const SIMULATOR_ATTESTATION_KEY: &str = include_str!("../testdata/attestation_key.key");
+
+//Intel QE vendor ID carried in the quote header
+const INTEL_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9c, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+
+const QUOTE_VERSION_4: u16 = 4;
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+const TEE_TYPE_TDX: u32 = 0x81;
+const CERT_DATA_TYPE_PCK_CERT_CHAIN: u16 = 5;
+const CERT_DATA_TYPE_QE_REPORT: u16 = 6;
+const QE_REPORT_LEN: usize = 384;
+const QE_REPORT_DATA_OFFSET: usize = 320;
+const QE_AUTH_DATA: [u8; 32] = [0x5a; 32];
+
+//software TDX device producing well-formed TD reports and v4 quotes without TDX hardware
+pub struct TdxSimulator {
+    tee_tcb_info: TeeTcbInfo,
+    td_info: Mutex<TdInfo>, // RTMRs are extended at runtime
+    attestation_key: SigningKey,
+    pck_key: SigningKey,
+    runtime_event_log: RuntimeEventLog,
+}
+
+impl Default for TdxSimulator {
+    fn default() -> Self {
+        TdxSimulator {
+            tee_tcb_info: TeeTcbInfo {
+                valid: [0xff, 0x01, 0, 0, 0, 0, 0, 0],
+                tee_tcb_svn: [0x03, 0, 0x05, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                mrseam: [0x2f; 48],
+                mrsignerseam: [0; 48],
+                attributes: [0; 8],
+                tee_tcb_svn2: [0; 16],
+            },
+            td_info: Mutex::new(TdInfo {
+                attributes: [0; 8],
+                xfam: [0xe7, 0x02, 0x06, 0, 0, 0, 0, 0],
+                mrtd: [0x4d; 48],
+                mrconfigid: [0; 48],
+                mrowner: [0; 48],
+                mrownerconfig: [0; 48],
+                rtmrs: [Rtmr([0; 48]); 4],
+                servtd_hash: [0; 48],
+            }),
+            attestation_key: SigningKey::from_pkcs8_pem(SIMULATOR_ATTESTATION_KEY)
+                .expect("[TdxSimulator] Malformed simulator attestation key"),
+            pck_key: SigningKey::from_pkcs8_pem(SIMULATOR_PCK_KEY)
+                .expect("[TdxSimulator] Malformed simulator PCK key"),
+            runtime_event_log: RuntimeEventLog::default(),
+        }
+    }
+}
+
+impl TdxSimulator {
+    //measurements of the simulated TD: MRTD, RTMRs, MRCONFIGID, MROWNER, ATTRIBUTES, ...
+    pub fn with_td_info(mut self, td_info: TdInfo) -> Self {
+        self.td_info = Mutex::new(td_info);
+        self
+    }
+
+    //measurements of the simulated TDX module: MRSEAM, TEE_TCB_SVN, ...
+    pub fn with_tee_tcb_info(mut self, tee_tcb_info: TeeTcbInfo) -> Self {
+        self.tee_tcb_info = tee_tcb_info;
+        self
+    }
+
+    //current measurements, including the RTMR extensions made so far
+    pub fn td_info(&self) -> TdInfo {
+        match self.td_info.lock() {
+            Err(e) => e.into_inner().clone(),
+            Ok(t) => t.clone(),
+        }
+    }
+
+    pub fn tee_tcb_info(&self) -> &TeeTcbInfo {
+        &self.tee_tcb_info
+    }
+
+    fn td_report(&self, report_data: &[u8; 64]) -> TdReport {
+        let td_info = self.td_info();
+        TdReport {
+            report_mac_struct: ReportMacStruct {
+                report_type: ReportType {
+                    tee_type: TEE_TYPE_TDX as u8,
+                    subtype: 0,
+                    version: 0,
+                },
+                cpusvn: [0; 16],
+                tee_tcb_info_hash: Sha384::digest(self.tee_tcb_info.to_bytes()).into(),
+                tee_info_hash: Sha384::digest(td_info.to_bytes()).into(),
+                report_data: *report_data,
+                //there is no MAC key in software, only the TDX module can produce the MAC
+                mac: [0; 32],
+            },
+            tee_tcb_info: self.tee_tcb_info.clone(),
+            td_info,
+        }
+    }
+
+    fn quote_body(&self, report_data: &[u8; 64]) -> Vec<u8> {
+        let tcb = &self.tee_tcb_info;
+        let td = &self.td_info();
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&tcb.tee_tcb_svn);
+        body.extend_from_slice(&tcb.mrseam);
+        body.extend_from_slice(&tcb.mrsignerseam);
+        body.extend_from_slice(&tcb.attributes);
+        body.extend_from_slice(&td.attributes);
+        body.extend_from_slice(&td.xfam);
+        body.extend_from_slice(&td.mrtd);
+        body.extend_from_slice(&td.mrconfigid);
+        body.extend_from_slice(&td.mrowner);
+        body.extend_from_slice(&td.mrownerconfig);
+        for rtmr in td.rtmrs.iter() {
+            body.extend_from_slice(&rtmr.0);
+        }
+        body.extend_from_slice(report_data);
+        body
+    }
+
+    //QE report certification data binding the attestation key to the PCK certificate chain
+    fn qe_certification_data(&self, attestation_pub: &[u8]) -> Vec<u8> {
+        let mut qe_report = vec![0u8; QE_REPORT_LEN];
+        let mut hasher = Sha256::new();
+        hasher.update(attestation_pub);
+        hasher.update(QE_AUTH_DATA);
+        qe_report[QE_REPORT_DATA_OFFSET..QE_REPORT_DATA_OFFSET + 32]
+            .copy_from_slice(&hasher.finalize());
+        let qe_report_signature: Signature = self.pck_key.sign(&qe_report);
+
+        let mut data = qe_report;
+        data.extend_from_slice(&qe_report_signature.to_bytes());
+        data.extend_from_slice(&(QE_AUTH_DATA.len() as u16).to_le_bytes());
+        data.extend_from_slice(&QE_AUTH_DATA);
+        data.extend_from_slice(&CERT_DATA_TYPE_PCK_CERT_CHAIN.to_le_bytes());
+        data.extend_from_slice(&(SIMULATOR_PCK_CERT_CHAIN.len() as u32).to_le_bytes());
+        data.extend_from_slice(SIMULATOR_PCK_CERT_CHAIN.as_bytes());
+        data
+    }
+}
+
+impl TdxDevice for TdxSimulator {
+    fn interface(&self) -> Result<TdxInterface, TdxError> {
+        Ok(TdxInterface::Simulator)
+    }
+
+    fn get_td_report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>, TdxError> {
+        Ok(self.td_report(report_data).to_bytes())
+    }
+
+    fn extend_rtmr_register(&self, index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+        rtmr::check_rtmr_index(index)?;
+        let mut td_info = match self.td_info.lock() {
+            Err(e) => e.into_inner(),
+            Ok(t) => t,
+        };
+        td_info.rtmrs[index] = Rtmr(replay::extend(&td_info.rtmrs[index].0, digest));
+        Ok(())
+    }
+
+    fn runtime_event_log(&self) -> &RuntimeEventLog {
+        &self.runtime_event_log
+    }
+
+    fn get_quote_with_key_ids(
+        &self,
+        report_data: &[u8; 64],
+        att_key_ids: &[AttKeyId],
+    ) -> Result<QgsQuoteResponse, TdxError> {
+        //the simulator only has the ECDSA attestation key, rejected like the QGS does
+        if !att_key_ids.is_empty() && !att_key_ids.contains(&TDX_SGX_ECDSA_ATTESTATION_ID) {
+            return Err(TdxError::QgsError(QGS_MSG_ERROR_INVALID_PARAMETER));
+        }
+
+        let mut quote = Vec::new();
+        quote.extend_from_slice(&QUOTE_VERSION_4.to_le_bytes());
+        quote.extend_from_slice(&ATTESTATION_KEY_TYPE_ECDSA_P256.to_le_bytes());
+        quote.extend_from_slice(&TEE_TYPE_TDX.to_le_bytes());
+        quote.extend_from_slice(&[0; 4]);
+        quote.extend_from_slice(&INTEL_QE_VENDOR_ID);
+        quote.extend_from_slice(&[0; 20]);
+        quote.extend_from_slice(&self.quote_body(report_data));
+        let signature: Signature = self.attestation_key.sign(&quote);
+
+        let attestation_pub = self.attestation_key.verifying_key().to_encoded_point(false);
+        let attestation_pub = &attestation_pub.as_bytes()[1..];
+        let qe_certification_data = self.qe_certification_data(attestation_pub);
+
+        let mut signature_data = Vec::new();
+        signature_data.extend_from_slice(&signature.to_bytes());
+        signature_data.extend_from_slice(attestation_pub);
+        signature_data.extend_from_slice(&CERT_DATA_TYPE_QE_REPORT.to_le_bytes());
+        signature_data.extend_from_slice(&(qe_certification_data.len() as u32).to_le_bytes());
+        signature_data.extend_from_slice(&qe_certification_data);
+
+        quote.extend_from_slice(&(signature_data.len() as u32).to_le_bytes());
+        quote.extend_from_slice(&signature_data);
+        Ok(QgsQuoteResponse {
+            selected_id: Some(TDX_SGX_ECDSA_ATTESTATION_ID),
+            quote,
+        })
+    }
+}
+
+#[cfg(test)]
+mod simulator_tests {
+    use super::*;
+    use crate::quote::Quote;
+    use crate::verify::verify_quote;
+    use crate::TdxVersion;
+
+    fn custom_td_info() -> TdInfo {
+        TdInfo {
+            attributes: [0x01, 0, 0, 0, 0, 0, 0, 0],
+            xfam: [0xe7, 0x1a, 0x06, 0, 0, 0, 0, 0],
+            mrtd: [0x11; 48],
+            mrconfigid: [0x12; 48],
+            mrowner: [0x13; 48],
+            mrownerconfig: [0x14; 48],
+            rtmrs: [
+                Rtmr([0x20; 48]),
+                Rtmr([0x21; 48]),
+                Rtmr([0x22; 48]),
+                Rtmr([0x23; 48]),
+            ],
+            servtd_hash: [0; 48],
+        }
+    }
+
+    #[test]
+    //simulated TD reports parse and carry the report data and configured measurements
+    fn simulator_td_report() {
+        let simulator = TdxSimulator::default().with_td_info(custom_td_info());
+        let report = simulator.get_td_report(&[0x77; 64]).unwrap();
+
+        let td_report = TdReport::from_bytes(&report).unwrap();
+        assert_eq!(td_report.report_data(), &[0x77; 64]);
+        assert_eq!(td_report.td_info, custom_td_info());
+        assert_eq!(td_report.rtmr(3).unwrap(), &[0x23; 48]);
+        assert_eq!(
+            td_report.report_mac_struct.tee_info_hash,
+            <[u8; 48]>::from(Sha384::digest(&report[512..]))
+        );
+    }
+
+    #[test]
+    //simulated quotes carry the configured measurements and verify against the simulator root CA
+    fn simulator_quote_verifies() {
+        let simulator = TdxSimulator::default().with_td_info(custom_td_info());
+        let quote = simulator.get_quote(&[0x77; 64]).unwrap();
+
+        let parsed = Quote::from_bytes(&quote).unwrap();
+        assert_eq!(parsed.report_data(), &[0x77; 64]);
+        assert_eq!(parsed.body.mrtd, [0x11; 48]);
+        assert_eq!(parsed.body.mrowner, [0x13; 48]);
+        assert_eq!(parsed.body.rtmrs[1].0, [0x21; 48]);
+        assert_eq!(parsed.header.qe_vendor_id, INTEL_QE_VENDOR_ID);
+
+        let result = verify_quote(&quote, SIMULATOR_ROOT_CA.as_bytes()).unwrap();
+        assert!(result.is_valid());
+    }
+
+    #[test]
+    //the simulator selects its ECDSA attestation key and rejects lists without it
+    fn simulator_att_key_ids() {
+        let simulator = TdxSimulator::default();
+        let resp = simulator
+            .get_quote_with_key_ids(
+                &[0; 64],
+                &[AttKeyId([0x01; 16]), TDX_SGX_ECDSA_ATTESTATION_ID],
+            )
+            .unwrap();
+        assert_eq!(resp.selected_id, Some(TDX_SGX_ECDSA_ATTESTATION_ID));
+        assert!(Quote::from_bytes(&resp.quote).is_ok());
+
+        assert_eq!(
+            simulator.get_quote_with_key_ids(&[0; 64], &[AttKeyId([0x01; 16])]),
+            Err(TdxError::QgsError(QGS_MSG_ERROR_INVALID_PARAMETER))
+        );
+    }
+
+    #[test]
+    //platform info reports the simulated module version, interface and TD attributes
+    fn simulator_platform_info() {
+        let info = TdxSimulator::default().platform_info().unwrap();
+        assert_eq!(info.tdx_version, TdxVersion::TDX_1_0);
+        assert_eq!(info.interface, TdxInterface::Simulator);
+        assert!(!info.td_attributes.debug);
+        assert!(!TdxSimulator::default().is_debug_td().unwrap());
+
+        let simulator = TdxSimulator::default().with_td_info(custom_td_info());
+        assert!(simulator.is_debug_td().unwrap());
+        let quote = Quote::from_bytes(&simulator.get_quote(&[0; 64]).unwrap()).unwrap();
+        assert!(quote.td_attributes().debug);
+    }
+
+    #[test]
+    //runtime extensions change the reported RTMR and replay from the runtime event log
+    fn simulator_extend_rtmr() {
+        let simulator = TdxSimulator::default();
+        simulator.extend_rtmr(3, &[0x31; 48]).unwrap();
+        simulator
+            .extend_rtmr_event(3, &[0x32; 48], 0x8000_0001, b"config")
+            .unwrap();
+        assert_eq!(
+            simulator.extend_rtmr(4, &[0x33; 48]),
+            Err(TdxError::InvalidRtmrIndex(4))
+        );
+
+        let expected = replay::extend(&replay::extend(&[0; 48], &[0x31; 48]), &[0x32; 48]);
+        assert_eq!(simulator.td_info().rtmrs[3].0, expected);
+        assert_eq!(simulator.td_info().rtmrs[2].0, [0; 48]);
+        let quote = Quote::from_bytes(&simulator.get_quote(&[0; 64]).unwrap()).unwrap();
+        assert_eq!(quote.body.rtmrs[3].0, expected);
+
+        let events = simulator.runtime_event_log().events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].rtmr_index(), Some(3));
+        assert_eq!(events[1].event, b"config".to_vec());
+        let result = replay::verify_td_rtmrs(&simulator, &events).unwrap();
+        assert!(result.is_valid());
+    }
+}
@@ -5,8 +5,35 @@
 
 #![allow(non_camel_case_types)]
 
-use anyhow::*;
+pub mod configfs;
+pub mod device;
+mod device_thread;
+pub mod error;
+pub mod eventlog;
+pub mod qgs;
+pub mod quote;
+pub mod replay;
+pub mod report;
+pub mod retry;
+pub mod rtmr;
+#[cfg(any(test, feature = "simulator"))]
+pub mod simulator;
+pub mod verify;
+
+pub use device::{TdxDevice, TdxInterface, TdxPlatformInfo};
+pub use error::TdxError;
+pub use eventlog::{Ccel, EventLog};
+pub use quote::Quote;
+pub use replay::ReplayResult;
+pub use report::{TdAttributes, TdReport};
+pub use retry::RetryPolicy;
+pub use rtmr::RuntimeEventLog;
+#[cfg(any(test, feature = "simulator"))]
+pub use simulator::TdxSimulator;
+
 use nix::*;
+use qgs::{AttKeyId, QgsQuoteResponse};
+use std::cmp;
 use std::convert::TryInto;
 use std::fs::File;
 use std::mem;
@@ -31,31 +58,19 @@ pub struct tdx_1_5_report_req {
     tdreport: [u8; TDX_REPORT_LEN as usize], // User buffer to store TDREPORT output from TDCALL[TDG.MR.REPORT]
 }
 
+//header of the GetQuote shared buffer, followed by the 4-byte big-endian length and the QGS message
 #[repr(C)]
-pub struct qgs_msg_header {
-    major_version: u16, // TDX major version
-    minor_version: u16, // TDX minor version
-    msg_type: u32,      // GET_QUOTE_REQ or GET_QUOTE_RESP
-    size: u32,          // size of the whole message, include this header, in byte
-    error_code: u32,    // used in response only
-}
-
-#[repr(C)]
-pub struct qgs_msg_get_quote_req {
-    header: qgs_msg_header,                        // header.type = GET_QUOTE_REQ
-    report_size: u32,                              // cannot be 0
-    id_list_size: u32,                             // length of id_list, in byte, can be 0
-    report_id_list: [u8; TDX_REPORT_LEN as usize], // report followed by id list
+pub struct tdx_quote_hdr {
+    version: u64, // Quote version, filled by TD
+    status: u64,  // Status code of Quote request, filled by VMM
+    in_len: u32,  // Length of TDREPORT, filled by TD
+    out_len: u32, // Length of Quote, filled by VMM
 }
 
 #[repr(C)]
-pub struct tdx_quote_hdr {
-    version: u64,                       // Quote version, filled by TD
-    status: u64,                        // Status code of Quote request, filled by VMM
-    in_len: u32,                        // Length of TDREPORT, filled by TD
-    out_len: u32,                       // Length of Quote, filled by VMM
-    data_len_be_bytes: [u8; 4],         // big-endian 4 bytes indicate the size of data following
-    data: [u8; TDX_QUOTE_LEN as usize], // Actual Quote data or TDREPORT on input
+pub struct tdx_extend_rtmr_req {
+    data: [u8; 48], // Digest to extend the RTMR with
+    index: u8,      // Index of the RTMR to extend
 }
 
 #[repr(C)]
@@ -64,29 +79,44 @@ pub struct tdx_quote_req {
     len: u64, // Length of the Quote buffer
 }
 
-#[repr(C)]
-pub struct qgs_msg_get_quote_resp {
-    header: qgs_msg_header,        // header.type = GET_QUOTE_RESP
-    selected_id_size: u32,         // can be 0 in case only one id is sent in request
-    quote_size: u32,               // length of quote_data, in byte
-    id_quote: [u8; TDX_QUOTE_LEN], // selected id followed by quote
-}
-
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TdxVersion {
     TDX_1_0,
     TDX_1_5,
 }
 
+impl TdxVersion {
+    //device node providing the ioctl ABI of this version
+    pub fn device_path(&self) -> &'static str {
+        match self {
+            TdxVersion::TDX_1_0 => "/dev/tdx-guest",
+            TdxVersion::TDX_1_5 => "/dev/tdx_guest",
+        }
+    }
+}
+
 pub enum TdxOperation {
     TDX_GET_TD_REPORT = 1,
     TDX_1_0_GET_QUOTE = 2,
+    TDX_EXTEND_RTMR = 3,
     TDX_1_5_GET_QUOTE = 4,
 }
 
-const REPORT_DATA_LEN: u32 = 64;
-const TDX_REPORT_LEN: u32 = 1024;
-const TDX_QUOTE_LEN: usize = 4 * 4096;
-
+pub const REPORT_DATA_LEN: u32 = 64;
+pub(crate) const TDX_REPORT_LEN: u32 = 1024;
+const TDX_QUOTE_HDR_LEN: usize = mem::size_of::<tdx_quote_hdr>();
+//size of the GetQuote shared buffer the first request is sent with, including the header
+pub const DEFAULT_QUOTE_BUFFER_LEN: usize = 4 * 4096;
+//the buffer is grown up to this size when the quote does not fit
+pub const MAX_QUOTE_BUFFER_LEN: usize = 1024 * 1024;
+
+//GetQuote status codes filled in tdx_quote_hdr.status by the VMM, as defined by the GHCI specification
+const GET_QUOTE_SUCCESS: u64 = 0;
+const GET_QUOTE_IN_FLIGHT: u64 = 0xffff_ffff_ffff_ffff;
+const GET_QUOTE_SERVICE_UNAVAILABLE: u64 = 0x8000_0000_0000_0001;
+
+//TDX guest device node, opened once and shared by all requests on a device
+#[derive(Debug)]
 pub struct TdxInfo {
     tdx_version: TdxVersion,
     device_node: File,
@@ -101,83 +131,85 @@ impl TdxInfo {
     }
 }
 
-fn get_tdx_version() -> TdxVersion {
+fn get_tdx_version() -> Result<TdxVersion, TdxError> {
     if Path::new("/dev/tdx-guest").exists() {
-        TdxVersion::TDX_1_0
+        Ok(TdxVersion::TDX_1_0)
     } else if Path::new("/dev/tdx_guest").exists() {
-        TdxVersion::TDX_1_5
+        Ok(TdxVersion::TDX_1_5)
     } else if Path::new("/dev/tdx-attest").exists() {
-        panic!("get_tdx_version: Deprecated device node /dev/tdx-attest, please upgrade to use /dev/tdx-guest or /dev/tdx_guest");
+        Err(TdxError::DeprecatedDevice("/dev/tdx-attest".to_string()))
     } else {
-        panic!("get_tdx_version: no TDX device found!");
+        Err(TdxError::DeviceNotFound)
     }
 }
 
-pub fn get_td_report(report_data: String) -> Result<Vec<u8>, anyhow::Error> {
-    //detect TDX version
-    let tdx_info = match get_tdx_version() {
-        TdxVersion::TDX_1_0 => {
-            let device_node = match File::options()
-                .read(true)
-                .write(true)
-                .open("/dev/tdx-guest")
-            {
-                Err(e) => {
-                    return Err(anyhow!(
-                        "[get_td_report] Fail to open {}: {:?}",
-                        "/dev/tdx-guest",
-                        e
-                    ))
-                }
-                Ok(fd) => fd,
-            };
-            TdxInfo::new(TdxVersion::TDX_1_0, device_node)
-        }
-        TdxVersion::TDX_1_5 => {
-            let device_node = match File::options()
-                .read(true)
-                .write(true)
-                .open("/dev/tdx_guest")
-            {
-                Err(e) => {
-                    return Err(anyhow!(
-                        "[get_td_report] Fail to open {}: {:?}",
-                        "/dev/tdx_guest",
-                        e
-                    ))
-                }
-                Ok(fd) => fd,
-            };
-            TdxInfo::new(TdxVersion::TDX_1_5, device_node)
-        }
-    };
+pub(crate) fn open_tdx_device() -> Result<TdxInfo, TdxError> {
+    let tdx_version = get_tdx_version()?;
+    let device_path = tdx_version.device_path();
 
-    match tdx_info.tdx_version {
-        TdxVersion::TDX_1_0 => match get_tdx_1_0_report(tdx_info.device_node, report_data) {
-            Err(e) => return Err(anyhow!("[get_td_report] Fail to get TDX report: {:?}", e)),
-            Ok(report) => Ok(report),
-        },
-        TdxVersion::TDX_1_5 => match get_tdx_1_5_report(tdx_info.device_node, report_data) {
-            Err(e) => return Err(anyhow!("[get_td_report] Fail to get TDX report: {:?}", e)),
-            Ok(report) => Ok(report),
-        },
+    match File::options().read(true).write(true).open(device_path) {
+        Err(e) => Err(TdxError::from_io(device_path, e)),
+        Ok(device_node) => Ok(TdxInfo::new(tdx_version, device_node)),
     }
 }
 
-fn get_tdx_1_0_report(device_node: File, report_data: String) -> Result<Vec<u8>, anyhow::Error> {
-    let report_data_bytes = match base64::decode(report_data) {
-        Ok(v) => v,
-        Err(e) => return Err(anyhow!("report data is not base64 encoded: {:?}", e)),
-    };
+fn decode_report_data(report_data: String) -> Result<Vec<u8>, TdxError> {
+    match base64::decode(report_data) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(TdxError::BadReportData(format!(
+            "report data is not base64 encoded: {:?}",
+            e
+        ))),
+    }
+}
+
+fn report_data_array(report_data: &[u8]) -> Result<&[u8; REPORT_DATA_LEN as usize], TdxError> {
+    match report_data.try_into() {
+        Ok(r) => Ok(r),
+        Err(_) => Err(TdxError::BadReportData(format!(
+            "report data is {} bytes, expected {}",
+            report_data.len(),
+            REPORT_DATA_LEN
+        ))),
+    }
+}
+
+//report data as base64 encoded string of exactly 64 bytes
+pub fn get_td_report(report_data: String) -> Result<Vec<u8>, TdxError> {
+    get_td_report_checked(&decode_report_data(report_data)?)
+}
+
+pub fn get_td_report_bytes(
+    report_data: &[u8; REPORT_DATA_LEN as usize],
+) -> Result<Vec<u8>, TdxError> {
+    device::default_device().get_td_report(report_data)
+}
+
+//report data of any length, rejected unless it is exactly 64 bytes
+pub fn get_td_report_checked(report_data: &[u8]) -> Result<Vec<u8>, TdxError> {
+    get_td_report_bytes(report_data_array(report_data)?)
+}
+
+pub(crate) fn get_td_report_ioctl(
+    tdx_info: &TdxInfo,
+    report_data: &[u8; REPORT_DATA_LEN as usize],
+) -> Result<Vec<u8>, TdxError> {
+    match tdx_info.tdx_version {
+        TdxVersion::TDX_1_0 => get_tdx_1_0_report(&tdx_info.device_node, *report_data),
+        TdxVersion::TDX_1_5 => get_tdx_1_5_report(&tdx_info.device_node, *report_data),
+    }
+}
 
+fn get_tdx_1_0_report(
+    device_node: &File,
+    report_data_array: [u8; REPORT_DATA_LEN as usize],
+) -> Result<Vec<u8>, TdxError> {
     //prepare get TDX report request data
-    let mut report_data_array: [u8; REPORT_DATA_LEN as usize] = [0; REPORT_DATA_LEN as usize];
-    report_data_array.copy_from_slice(&report_data_bytes[0..]);
     let td_report: [u8; TDX_REPORT_LEN as usize] = [0; TDX_REPORT_LEN as usize];
 
     //build the request
     let request = tdx_1_0_report_req {
-        subtype: 0 as u8,
+        subtype: 0,
         reportdata: ptr::addr_of!(report_data_array) as u64,
         rpd_len: REPORT_DATA_LEN,
         tdreport: ptr::addr_of!(td_report) as u64,
@@ -193,33 +225,24 @@ fn get_tdx_1_0_report(device_node: File, report_data: String) -> Result<Vec<u8>,
     );
 
     //apply the ioctl command
-    match unsafe {
-        get_report_1_0_ioctl(device_node.as_raw_fd(), ptr::addr_of!(request) as *mut u64)
-    } {
-        Err(e) => {
-            return Err(anyhow!(
-                "[get_tdx_1_0_report] Fail to get TDX report: {:?}",
-                e
-            ))
-        }
-        Ok(_) => (),
+    if let Err(e) =
+        unsafe { get_report_1_0_ioctl(device_node.as_raw_fd(), ptr::addr_of!(request) as *mut u64) }
+    {
+        return Err(TdxError::IoctlFailed(e));
     };
 
     Ok(td_report.to_vec())
 }
 
-fn get_tdx_1_5_report(device_node: File, report_data: String) -> Result<Vec<u8>, anyhow::Error> {
-    let report_data_bytes = match base64::decode(report_data) {
-        Ok(v) => v,
-        Err(e) => return Err(anyhow!("report data is not base64 encoded: {:?}", e)),
-    };
-
+fn get_tdx_1_5_report(
+    device_node: &File,
+    report_data_array: [u8; REPORT_DATA_LEN as usize],
+) -> Result<Vec<u8>, TdxError> {
     //prepare get TDX report request data
-    let mut request = tdx_1_5_report_req {
-        reportdata: [0; REPORT_DATA_LEN as usize],
+    let request = tdx_1_5_report_req {
+        reportdata: report_data_array,
         tdreport: [0; TDX_REPORT_LEN as usize],
     };
-    request.reportdata.copy_from_slice(&report_data_bytes[0..]);
 
     //build the operator code
     ioctl_readwrite!(
@@ -230,118 +253,86 @@ fn get_tdx_1_5_report(device_node: File, report_data: String) -> Result<Vec<u8>,
     );
 
     //apply the ioctl command
-    match unsafe {
+    if let Err(e) = unsafe {
         get_report_1_5_ioctl(
             device_node.as_raw_fd(),
             ptr::addr_of!(request) as *mut tdx_1_5_report_req,
         )
     } {
-        Err(e) => {
-            return Err(anyhow!(
-                "[get_tdx_1_5_report] Fail to get TDX report: {:?}",
-                e
-            ))
-        }
-        Ok(_) => (),
+        return Err(TdxError::IoctlFailed(e));
     };
 
     Ok(request.tdreport.to_vec())
 }
 
-fn generate_qgs_quote_msg(report: [u8; TDX_REPORT_LEN as usize]) -> qgs_msg_get_quote_req {
-    //build quote service message header to be used by QGS
-    let qgs_header = qgs_msg_header {
-        major_version: 1,
-        minor_version: 0,
-        msg_type: 0,
-        size: 16 + 8 + TDX_REPORT_LEN, // header + report_size and id_list_size + TDX_REPORT_LEN
-        error_code: 0,
-    };
+//report data as base64 encoded string of exactly 64 bytes
+pub fn get_tdx_quote(report_data: String) -> Result<Vec<u8>, TdxError> {
+    get_tdx_quote_checked(&decode_report_data(report_data)?)
+}
 
-    //build quote service message body to be used by QGS
-    let mut qgs_request = qgs_msg_get_quote_req {
-        header: qgs_header,
-        report_size: TDX_REPORT_LEN,
-        id_list_size: 0,
-        report_id_list: [0; TDX_REPORT_LEN as usize],
-    };
+pub fn get_tdx_quote_bytes(
+    report_data: &[u8; REPORT_DATA_LEN as usize],
+) -> Result<Vec<u8>, TdxError> {
+    device::default_device().get_quote(report_data)
+}
 
-    qgs_request.report_id_list.copy_from_slice(&report[0..]);
+//report data of any length, rejected unless it is exactly 64 bytes
+pub fn get_tdx_quote_checked(report_data: &[u8]) -> Result<Vec<u8>, TdxError> {
+    get_tdx_quote_bytes(report_data_array(report_data)?)
+}
+
+//quote signed by one of the given attestation keys, reported back as the selected ID
+pub fn get_tdx_quote_with_key_ids(
+    report_data: &[u8; REPORT_DATA_LEN as usize],
+    att_key_ids: &[AttKeyId],
+) -> Result<QgsQuoteResponse, TdxError> {
+    device::default_device().get_quote_with_key_ids(report_data, att_key_ids)
+}
+
+//TDX module version, quote interface and decoded TD attributes of the running TD
+pub fn get_tdx_platform_info() -> Result<TdxPlatformInfo, TdxError> {
+    device::default_device().platform_info()
+}
 
-    qgs_request
+//a debug TD exposes its memory and registers to the host, secrets must not be released to it
+pub fn is_debug_td() -> Result<bool, TdxError> {
+    device::default_device().is_debug_td()
 }
 
-pub fn get_tdx_quote(report_data: String) -> Result<Vec<u8>, anyhow::Error> {
+pub(crate) fn get_tdx_quote_ioctl(
+    tdx_info: &TdxInfo,
+    report_data: &[u8; REPORT_DATA_LEN as usize],
+    att_key_ids: &[AttKeyId],
+    quote_buffer_len: usize,
+) -> Result<QgsQuoteResponse, TdxError> {
     //retrieve TDX report
-    let report_data_vec = match get_td_report(report_data) {
-        Err(e) => return Err(anyhow!("[get_tdx_quote] Fail to get TDX report: {:?}", e)),
-        Ok(report) => report,
-    };
-    let report_data_array: [u8; TDX_REPORT_LEN as usize] = match report_data_vec.try_into() {
-        Ok(r) => r,
-        Err(e) => return Err(anyhow!("[get_tdx_quote] Wrong TDX report format: {:?}", e)),
-    };
+    let report = get_td_report_ioctl(tdx_info, report_data)?;
 
     //build QGS request message
-    let qgs_msg = generate_qgs_quote_msg(report_data_array);
-
-    let tdx_info = match get_tdx_version() {
-        TdxVersion::TDX_1_0 => {
-            let device_node = match File::options()
-                .read(true)
-                .write(true)
-                .open("/dev/tdx-guest")
-            {
-                Err(e) => {
-                    return Err(anyhow!(
-                        "[get_tdx_quote] Fail to open {}: {:?}",
-                        "/dev/tdx-guest",
-                        e
-                    ))
+    let qgs_msg = qgs::encode_get_quote_req(&report, att_key_ids);
+
+    //grow the quote buffer to what the VMM asks for when the quote does not fit
+    let mut buffer_len = quote_buffer_len;
+    loop {
+        let mut buffer = build_quote_buffer(&qgs_msg, buffer_len)?;
+        match get_tdx_quote_buffer(tdx_info, &mut buffer).and_then(|_| parse_quote_buffer(&buffer))
+        {
+            Err(TdxError::QuoteTooLarge(required)) => {
+                match next_quote_buffer_len(buffer_len, required) {
+                    Some(len) => buffer_len = len,
+                    None => return Err(TdxError::QuoteTooLarge(required)),
                 }
-                Ok(fd) => fd,
-            };
-            TdxInfo::new(TdxVersion::TDX_1_0, device_node)
+            }
+            result => return result,
         }
-        TdxVersion::TDX_1_5 => {
-            let device_node = match File::options()
-                .read(true)
-                .write(true)
-                .open("/dev/tdx_guest")
-            {
-                Err(e) => {
-                    return Err(anyhow!(
-                        "[get_tdx_quote] Fail to open {}: {:?}",
-                        "/dev/tdx_guest",
-                        e
-                    ))
-                }
-                Ok(fd) => fd,
-            };
-            TdxInfo::new(TdxVersion::TDX_1_5, device_node)
-        }
-    };
-
-    //build quote generation request header
-    let mut quote_header = tdx_quote_hdr {
-        version: 1,
-        status: 0,
-        in_len: (mem::size_of_val(&qgs_msg) + 4) as u32,
-        out_len: 0,
-        data_len_be_bytes: (1048 as u32).to_be_bytes(),
-        data: [0; TDX_QUOTE_LEN as usize],
-    };
-
-    let qgs_msg_bytes = unsafe {
-        let ptr = &qgs_msg as *const qgs_msg_get_quote_req as *const u8;
-        std::slice::from_raw_parts(ptr, mem::size_of::<qgs_msg_get_quote_req>())
-    };
-    quote_header.data[0..(16 + 8 + TDX_REPORT_LEN) as usize]
-        .copy_from_slice(&qgs_msg_bytes[0..((16 + 8 + TDX_REPORT_LEN) as usize)]);
+    }
+}
 
+//apply the GetQuote ioctl on the shared buffer, the VMM writes the response into it
+fn get_tdx_quote_buffer(tdx_info: &TdxInfo, buffer: &mut [u8]) -> Result<(), TdxError> {
     let request = tdx_quote_req {
-        buf: ptr::addr_of!(quote_header) as u64,
-        len: TDX_QUOTE_LEN as u64,
+        buf: buffer.as_mut_ptr() as u64,
+        len: buffer.len() as u64,
     };
 
     //build the operator code and apply the ioctl command
@@ -353,14 +344,13 @@ pub fn get_tdx_quote(report_data: String) -> Result<Vec<u8>, anyhow::Error> {
                 TdxOperation::TDX_1_0_GET_QUOTE,
                 u64
             );
-            match unsafe {
+            if let Err(e) = unsafe {
                 get_quote_1_0_ioctl(
                     tdx_info.device_node.as_raw_fd(),
                     ptr::addr_of!(request) as *mut u64,
                 )
             } {
-                Err(e) => return Err(anyhow!("[get_tdx_quote] Fail to get TDX quote: {:?}", e)),
-                Ok(_r) => _r,
+                return Err(TdxError::IoctlFailed(e));
             };
         }
         TdxVersion::TDX_1_5 => {
@@ -370,56 +360,318 @@ pub fn get_tdx_quote(report_data: String) -> Result<Vec<u8>, anyhow::Error> {
                 TdxOperation::TDX_1_5_GET_QUOTE,
                 tdx_quote_req
             );
-            match unsafe {
+            if let Err(e) = unsafe {
                 get_quote_1_5_ioctl(
                     tdx_info.device_node.as_raw_fd(),
                     ptr::addr_of!(request) as *mut tdx_quote_req,
                 )
             } {
-                Err(e) => return Err(anyhow!("[get_tdx_quote] Fail to get TDX quote: {:?}", e)),
-                Ok(_r) => _r,
+                return Err(TdxError::IoctlFailed(e));
             };
         }
     };
 
-    //inspect the response and retrive quote data
-    let out_len = quote_header.out_len;
-    let qgs_msg_resp_size =
-        unsafe { std::mem::transmute::<[u8; 4], u32>(quote_header.data_len_be_bytes) }.to_be();
+    Ok(())
+}
 
-    let qgs_msg_resp = unsafe {
-        let raw_ptr = ptr::addr_of!(quote_header.data) as *mut qgs_msg_get_quote_resp;
-        raw_ptr.as_mut().unwrap() as &mut qgs_msg_get_quote_resp
-    };
+//lay out tdx_quote_hdr and the length-prefixed QGS message in a buffer of the given size
+fn build_quote_buffer(qgs_msg: &[u8], buffer_len: usize) -> Result<Vec<u8>, TdxError> {
+    let data_offset = TDX_QUOTE_HDR_LEN + 4;
+    if data_offset + qgs_msg.len() > buffer_len {
+        return Err(TdxError::InvalidQuote(format!(
+            "[build_quote_buffer] QGS request of {} bytes exceeds the {} bytes quote buffer",
+            qgs_msg.len(),
+            buffer_len
+        )));
+    }
 
-    if out_len - qgs_msg_resp_size != 4 {
-        return Err(anyhow!(
-            "[get_tdx_quote] Fail to get TDX quote: wrong TDX quote size!"
-        ));
+    let mut buffer = vec![0u8; buffer_len];
+    buffer[0..8].copy_from_slice(&1u64.to_le_bytes());
+    buffer[16..20].copy_from_slice(&((qgs_msg.len() + 4) as u32).to_le_bytes());
+    buffer[TDX_QUOTE_HDR_LEN..data_offset].copy_from_slice(&(qgs_msg.len() as u32).to_be_bytes());
+    buffer[data_offset..data_offset + qgs_msg.len()].copy_from_slice(qgs_msg);
+    Ok(buffer)
+}
+
+//decode the response in the shared buffer, every length is checked against out_len and the buffer
+fn parse_quote_buffer(buffer: &[u8]) -> Result<QgsQuoteResponse, TdxError> {
+    let status = u64::from_le_bytes(report::array(buffer, 8));
+    let out_len = u32::from_le_bytes(report::array(buffer, 20)) as usize;
+    let capacity = buffer.len() - TDX_QUOTE_HDR_LEN;
+
+    if status == GET_QUOTE_IN_FLIGHT {
+        return Err(TdxError::QuoteInFlight);
+    }
+    //a response larger than the buffer is reported through out_len, whatever the status
+    if out_len > capacity {
+        return Err(TdxError::QuoteTooLarge(TDX_QUOTE_HDR_LEN + out_len));
     }
+    check_quote_status(status)?;
 
-    if qgs_msg_resp.header.major_version != 1
-        || qgs_msg_resp.header.minor_version != 0
-        || qgs_msg_resp.header.msg_type != 1
-        || qgs_msg_resp.header.error_code != 0
-    {
-        return Err(anyhow!(
-            "[get_tdx_quote] Fail to get TDX quote: QGS response error!"
-        ));
+    if out_len < 4 {
+        return Err(TdxError::InvalidQuote(format!(
+            "[parse_quote_buffer] Truncated response: out_len {}",
+            out_len
+        )));
+    }
+    let data = &buffer[TDX_QUOTE_HDR_LEN..TDX_QUOTE_HDR_LEN + out_len];
+    let qgs_msg_resp_size = u32::from_be_bytes(report::array(data, 0)) as usize;
+    if qgs_msg_resp_size != out_len - 4 {
+        return Err(TdxError::InvalidQuote(format!(
+            "[parse_quote_buffer] Wrong TDX quote size: out_len {}, QGS message size {}",
+            out_len, qgs_msg_resp_size
+        )));
+    }
+
+    qgs::decode_get_quote_resp(&data[4..])
+}
+
+//size of the next quote buffer, None once the required size is out of bounds
+fn next_quote_buffer_len(current: usize, required: usize) -> Option<usize> {
+    if required <= current || required > MAX_QUOTE_BUFFER_LEN {
+        return None;
+    }
+    //round up to whole pages, the VMM shares the buffer page by page
+    Some(cmp::min(
+        required.next_multiple_of(4096),
+        MAX_QUOTE_BUFFER_LEN,
+    ))
+}
+
+fn check_quote_status(status: u64) -> Result<(), TdxError> {
+    match status {
+        GET_QUOTE_SUCCESS => Ok(()),
+        GET_QUOTE_IN_FLIGHT => Err(TdxError::QuoteInFlight),
+        GET_QUOTE_SERVICE_UNAVAILABLE => Err(TdxError::QuoteServiceUnavailable),
+        //GET_QUOTE_ERROR and unknown codes alike, the status is kept for the caller
+        _ => Err(TdxError::QuoteStatus(status)),
     }
+}
+
+//extend RTMR[index] with the digest and record it in the runtime event log
+pub fn extend_rtmr(index: usize, digest: &[u8; 48]) -> Result<(), TdxError> {
+    device::default_device().extend_rtmr(index, digest)
+}
+
+//events of the RTMR extensions made by this process
+pub fn get_runtime_event_log() -> Vec<eventlog::EventLogEntry> {
+    device::default_device().runtime_event_log().events()
+}
+
+pub(crate) fn extend_rtmr_ioctl(
+    tdx_info: &TdxInfo,
+    index: usize,
+    digest: &[u8; 48],
+) -> Result<(), TdxError> {
+    let request = tdx_extend_rtmr_req {
+        data: *digest,
+        index: index as u8,
+    };
+
+    //build the operator code and apply the ioctl command
+    match tdx_info.tdx_version {
+        TdxVersion::TDX_1_0 => {
+            ioctl_read!(
+                extend_rtmr_1_0_ioctl,
+                b'T',
+                TdxOperation::TDX_EXTEND_RTMR,
+                u64
+            );
+            if let Err(e) = unsafe {
+                extend_rtmr_1_0_ioctl(
+                    tdx_info.device_node.as_raw_fd(),
+                    ptr::addr_of!(request) as *mut u64,
+                )
+            } {
+                return Err(TdxError::IoctlFailed(e));
+            };
+        }
+        TdxVersion::TDX_1_5 => {
+            ioctl_write_ptr!(
+                extend_rtmr_1_5_ioctl,
+                b'T',
+                TdxOperation::TDX_EXTEND_RTMR,
+                tdx_extend_rtmr_req
+            );
+            if let Err(e) = unsafe {
+                extend_rtmr_1_5_ioctl(tdx_info.device_node.as_raw_fd(), ptr::addr_of!(request))
+            } {
+                return Err(TdxError::IoctlFailed(e));
+            };
+        }
+    };
+
+    Ok(())
+}
 
-    Ok(qgs_msg_resp.id_quote[0..(qgs_msg_resp.quote_size as usize)].to_vec())
+//send the quote request straight to the QGS, for kernels without the GetQuote ioctl,
+//the client gives up after qgs::DEFAULT_QGS_TIMEOUT
+pub fn get_tdx_quote_qgs(
+    report_data: String,
+    address: qgs::QgsAddress,
+) -> Result<Vec<u8>, TdxError> {
+    let report = get_td_report(report_data)?;
+    Ok(qgs::QgsClient::new(address).get_quote(&report)?.quote)
 }
 
 #[cfg(test)]
 mod tdx_attest_tests {
     use super::*;
 
+    //generic GHCI failure, reported as a plain QuoteStatus
+    const GET_QUOTE_ERROR: u64 = 0x8000_0000_0000_0000;
+
+    #[test]
+    //malformed report data is rejected before touching the TDX device
+    fn get_td_report_bad_report_data() {
+        assert!(matches!(
+            get_td_report("not base64!".to_string()),
+            Err(TdxError::BadReportData(_))
+        ));
+        assert!(matches!(
+            get_td_report(base64::encode([0u8; 32])),
+            Err(TdxError::BadReportData(_))
+        ));
+    }
+
+    #[test]
+    //the checked byte API errors instead of panicking on report data that is not 64 bytes
+    fn get_tdx_quote_checked_wrong_length() {
+        for len in [0, 32, 63, 65, 128] {
+            assert_eq!(
+                get_tdx_quote_checked(&vec![0u8; len]),
+                Err(TdxError::BadReportData(format!(
+                    "report data is {} bytes, expected 64",
+                    len
+                )))
+            );
+            assert!(matches!(
+                get_td_report_checked(&vec![0u8; len]),
+                Err(TdxError::BadReportData(_))
+            ));
+        }
+        assert!(report_data_array(&[0u8; 64]).is_ok());
+    }
+
+    //fill the shared buffer the way the VMM does after the QGS answered
+    fn vmm_response(buffer: &mut [u8], status: u64, qgs_msg: &[u8]) {
+        buffer[8..16].copy_from_slice(&status.to_le_bytes());
+        buffer[20..24].copy_from_slice(&((qgs_msg.len() + 4) as u32).to_le_bytes());
+        if TDX_QUOTE_HDR_LEN + 4 + qgs_msg.len() <= buffer.len() {
+            buffer[24..28].copy_from_slice(&(qgs_msg.len() as u32).to_be_bytes());
+            buffer[28..28 + qgs_msg.len()].copy_from_slice(qgs_msg);
+        }
+    }
+
+    fn qgs_resp(quote: &[u8]) -> Vec<u8> {
+        let mut msg = vec![1, 0, 0, 0, 1, 0, 0, 0];
+        msg.extend_from_slice(&((24 + quote.len()) as u32).to_le_bytes());
+        msg.extend_from_slice(&[0; 8]);
+        msg.extend_from_slice(&(quote.len() as u32).to_le_bytes());
+        msg.extend_from_slice(quote);
+        msg
+    }
+
+    #[test]
+    //the request is laid out behind tdx_quote_hdr with its big-endian length
+    fn build_quote_buffer_layout() {
+        let buffer = build_quote_buffer(&[0xab; 100], 4096).unwrap();
+        assert_eq!(buffer.len(), 4096);
+        assert_eq!(u64::from_le_bytes(buffer[0..8].try_into().unwrap()), 1);
+        assert_eq!(u32::from_le_bytes(buffer[16..20].try_into().unwrap()), 104);
+        assert_eq!(u32::from_be_bytes(buffer[24..28].try_into().unwrap()), 100);
+        assert_eq!(&buffer[28..128], &[0xab; 100][..]);
+
+        assert!(build_quote_buffer(&[0xab; 100], 64).is_err());
+    }
+
+    #[test]
+    //the quote is taken from the response bounded by out_len
+    fn parse_quote_buffer_response() {
+        let mut buffer = build_quote_buffer(&[0; 1048], DEFAULT_QUOTE_BUFFER_LEN).unwrap();
+        vmm_response(&mut buffer, GET_QUOTE_SUCCESS, &qgs_resp(&[0x04; 5000]));
+        assert_eq!(parse_quote_buffer(&buffer).unwrap().quote, vec![0x04; 5000]);
+    }
+
+    #[test]
+    //a response that does not fit reports the buffer size it needs
+    fn parse_quote_buffer_too_small() {
+        let mut buffer = build_quote_buffer(&[0; 1048], DEFAULT_QUOTE_BUFFER_LEN).unwrap();
+        vmm_response(&mut buffer, GET_QUOTE_ERROR, &qgs_resp(&[0x04; 20000]));
+        let required = TDX_QUOTE_HDR_LEN + 4 + 24 + 20000;
+        assert_eq!(
+            parse_quote_buffer(&buffer),
+            Err(TdxError::QuoteTooLarge(required))
+        );
+
+        let next = next_quote_buffer_len(DEFAULT_QUOTE_BUFFER_LEN, required).unwrap();
+        assert_eq!(next, 20480);
+        let mut buffer = build_quote_buffer(&[0; 1048], next).unwrap();
+        vmm_response(&mut buffer, GET_QUOTE_SUCCESS, &qgs_resp(&[0x04; 20000]));
+        assert_eq!(parse_quote_buffer(&buffer).unwrap().quote.len(), 20000);
+    }
+
+    #[test]
+    //inconsistent lengths and failed statuses are rejected
+    fn parse_quote_buffer_invalid() {
+        let mut buffer = build_quote_buffer(&[0; 1048], DEFAULT_QUOTE_BUFFER_LEN).unwrap();
+        vmm_response(&mut buffer, GET_QUOTE_SUCCESS, &qgs_resp(&[0x04; 100]));
+        buffer[24..28].copy_from_slice(&99u32.to_be_bytes());
+        assert!(matches!(
+            parse_quote_buffer(&buffer),
+            Err(TdxError::InvalidQuote(_))
+        ));
+
+        buffer[20..24].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(
+            parse_quote_buffer(&buffer),
+            Err(TdxError::InvalidQuote(_))
+        ));
+
+        let mut buffer = build_quote_buffer(&[0; 1048], DEFAULT_QUOTE_BUFFER_LEN).unwrap();
+        vmm_response(&mut buffer, GET_QUOTE_IN_FLIGHT, &[]);
+        assert_eq!(parse_quote_buffer(&buffer), Err(TdxError::QuoteInFlight));
+    }
+
+    #[test]
+    //the quote buffer grows in whole pages and never beyond the maximum
+    fn next_quote_buffer_len_bounds() {
+        assert_eq!(next_quote_buffer_len(16384, 16385), Some(20480));
+        assert_eq!(next_quote_buffer_len(16384, 16384), None);
+        assert_eq!(
+            next_quote_buffer_len(16384, MAX_QUOTE_BUFFER_LEN),
+            Some(MAX_QUOTE_BUFFER_LEN)
+        );
+        assert_eq!(next_quote_buffer_len(16384, MAX_QUOTE_BUFFER_LEN + 1), None);
+    }
+
+    #[test]
+    //GHCI GetQuote status codes are decoded into distinct errors
+    fn check_quote_status_codes() {
+        assert_eq!(check_quote_status(GET_QUOTE_SUCCESS), Ok(()));
+        assert_eq!(
+            check_quote_status(GET_QUOTE_IN_FLIGHT),
+            Err(TdxError::QuoteInFlight)
+        );
+        assert_eq!(
+            check_quote_status(GET_QUOTE_SERVICE_UNAVAILABLE),
+            Err(TdxError::QuoteServiceUnavailable)
+        );
+        assert_eq!(
+            check_quote_status(GET_QUOTE_ERROR),
+            Err(TdxError::QuoteStatus(GET_QUOTE_ERROR))
+        );
+        assert_eq!(check_quote_status(0x42), Err(TdxError::QuoteStatus(0x42)));
+    }
+
     #[test]
-    //TDX ENV required: call get_td_report and verify report data embedded in quote
+    //call get_td_report on the simulator and verify report data embedded in report
     fn get_td_report_verify_report_data() {
         let report_data = "XUccU3O9poJXiX53jNGj1w2v4WVAw8TKDyWm8Y0xgJ2khEMyCSCiWfO/sYMEn5xoC8ES2VzXwmKRv9NVu3YnUA==";
-        let report = get_td_report(report_data.to_string()).unwrap();
+        let report_data = decode_report_data(report_data.to_string()).unwrap();
+        let report = TdxSimulator::default()
+            .get_td_report(report_data_array(&report_data).unwrap())
+            .unwrap();
 
         let expected_report_data = [
             93, 71, 28, 83, 115, 189, 166, 130, 87, 137, 126, 119, 140, 209, 163, 215, 13, 175,
@@ -428,16 +680,18 @@ mod tdx_attest_tests {
             145, 191, 211, 85, 187, 118, 39, 80,
         ];
 
-        let mut report_data_in_report: [u8; 64 as usize] = [0; 64 as usize];
-        report_data_in_report.copy_from_slice(&report[128..192]);
-        assert_eq!(report_data_in_report, expected_report_data);
+        let td_report = TdReport::from_bytes(&report).unwrap();
+        assert_eq!(td_report.report_data(), &expected_report_data);
     }
 
     #[test]
-    //TDX ENV required: call tdx_get_quote and verify report data embedded in quote
+    //call get_quote on the simulator and verify report data embedded in quote
     fn get_tdx_quote_verify_report_data() {
         let report_data = "XUccU3O9poJXiX53jNGj1w2v4WVAw8TKDyWm8Y0xgJ2khEMyCSCiWfO/sYMEn5xoC8ES2VzXwmKRv9NVu3YnUA==";
-        let quote = get_tdx_quote(report_data.to_string()).unwrap();
+        let report_data = decode_report_data(report_data.to_string()).unwrap();
+        let quote = TdxSimulator::default()
+            .get_quote(report_data_array(&report_data).unwrap())
+            .unwrap();
 
         let expected_report_data = [
             93, 71, 28, 83, 115, 189, 166, 130, 87, 137, 126, 119, 140, 209, 163, 215, 13, 175,
@@ -446,8 +700,7 @@ mod tdx_attest_tests {
             145, 191, 211, 85, 187, 118, 39, 80,
         ];
 
-        let mut report_data_in_quote: [u8; 64 as usize] = [0; 64 as usize];
-        report_data_in_quote.copy_from_slice(&quote[568..632]);
-        assert_eq!(report_data_in_quote, expected_report_data);
+        let parsed_quote = Quote::from_bytes(&quote).unwrap();
+        assert_eq!(parsed_quote.report_data(), &expected_report_data);
     }
 }
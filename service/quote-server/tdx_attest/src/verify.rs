@@ -0,0 +1,427 @@
+/*
+* Copyright (c) 2023, Intel Corporation. All rights reserved.<BR>
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use anyhow::*;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::result::Result;
+use std::result::Result::Ok;
+use std::time::SystemTime;
+use x509_cert::der::oid::ObjectIdentifier;
+use x509_cert::der::{DecodePem, Encode};
+use x509_cert::ext::pkix::{BasicConstraints, KeyUsage};
+use x509_cert::Certificate;
+
+use crate::error::TdxError;
+use crate::quote::{QeReportCertificationData, Quote};
+
+//ecdsa-with-SHA256, the only signature algorithm used in the Intel SGX PCK certificate chain
+const ECDSA_WITH_SHA_256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerificationStatus {
+    Valid,
+    Invalid(String),
+}
+
+impl VerificationStatus {
+    pub fn is_valid(&self) -> bool {
+        *self == VerificationStatus::Valid
+    }
+
+    fn from_result(result: Result<(), anyhow::Error>) -> Self {
+        match result {
+            Ok(()) => VerificationStatus::Valid,
+            Err(e) => VerificationStatus::Invalid(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteVerificationResult {
+    pub attestation_key_signature: VerificationStatus, // Attestation key signature over header and body
+    pub qe_report_signature: VerificationStatus,       // PCK key signature over the QE report
+    pub qe_report_data_binding: VerificationStatus,    // QE report data binds the attestation key
+    pub pck_cert_chain: VerificationStatus,            // PCK certificate chain up to the root CA
+}
+
+impl QuoteVerificationResult {
+    pub fn is_valid(&self) -> bool {
+        self.attestation_key_signature.is_valid()
+            && self.qe_report_signature.is_valid()
+            && self.qe_report_data_binding.is_valid()
+            && self.pck_cert_chain.is_valid()
+    }
+}
+
+pub fn verify_quote(quote: &[u8], root_ca_pem: &[u8]) -> Result<QuoteVerificationResult, TdxError> {
+    verify_quote_at(quote, root_ca_pem, SystemTime::now())
+}
+
+//verify the quote with certificate validity checked against the given time
+pub fn verify_quote_at(
+    quote: &[u8],
+    root_ca_pem: &[u8],
+    time: SystemTime,
+) -> Result<QuoteVerificationResult, TdxError> {
+    let parsed_quote = Quote::from_bytes(quote)?;
+    let root_ca = match Certificate::from_pem(root_ca_pem) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(TdxError::InvalidCertificate(format!(
+                "[verify_quote] Fail to parse root CA: {:?}",
+                e
+            )))
+        }
+    };
+
+    let attestation_key_signature =
+        VerificationStatus::from_result(verify_attestation_key_signature(&parsed_quote, quote));
+
+    let qe = match parsed_quote.qe_report_certification_data() {
+        Some(qe) => qe,
+        None => {
+            let missing = VerificationStatus::Invalid(
+                "quote carries no QE report certification data".to_string(),
+            );
+            return Ok(QuoteVerificationResult {
+                attestation_key_signature,
+                qe_report_signature: missing.clone(),
+                qe_report_data_binding: missing.clone(),
+                pck_cert_chain: missing,
+            });
+        }
+    };
+
+    let qe_report_data_binding = VerificationStatus::from_result(verify_qe_report_data_binding(
+        qe,
+        &parsed_quote.signature_data.attestation_key,
+    ));
+
+    let pck_chain = match parsed_quote.pck_cert_chain() {
+        Some(pem) => Certificate::load_pem_chain(pem).map_err(|e| {
+            anyhow!(
+                "[verify_quote] Fail to parse PCK certificate chain: {:?}",
+                e
+            )
+        }),
+        None => Err(anyhow!(
+            "[verify_quote] quote carries no PCK certificate chain"
+        )),
+    };
+
+    let (pck_cert_chain, qe_report_signature) = match pck_chain {
+        Ok(chain) => (
+            VerificationStatus::from_result(verify_cert_chain(&chain, &root_ca, time)),
+            VerificationStatus::from_result(verify_qe_report_signature(qe, &chain)),
+        ),
+        Err(e) => (
+            VerificationStatus::Invalid(e.to_string()),
+            VerificationStatus::Invalid(e.to_string()),
+        ),
+    };
+
+    Ok(QuoteVerificationResult {
+        attestation_key_signature,
+        qe_report_signature,
+        qe_report_data_binding,
+        pck_cert_chain,
+    })
+}
+
+fn verify_attestation_key_signature(
+    parsed_quote: &Quote,
+    quote: &[u8],
+) -> Result<(), anyhow::Error> {
+    let attestation_key = raw_verifying_key(&parsed_quote.signature_data.attestation_key)?;
+    let signature = Signature::from_slice(&parsed_quote.signature_data.signature).map_err(|e| {
+        anyhow!(
+            "[verify_attestation_key_signature] Malformed signature: {:?}",
+            e
+        )
+    })?;
+
+    attestation_key
+        .verify(&quote[0..parsed_quote.signed_data_len()], &signature)
+        .map_err(|_| {
+            anyhow!(
+                "[verify_attestation_key_signature] Quote signature does not match attestation key"
+            )
+        })
+}
+
+fn verify_qe_report_data_binding(
+    qe: &QeReportCertificationData,
+    attestation_key: &[u8; 64],
+) -> Result<(), anyhow::Error> {
+    //QE report data is SHA256(attestation key || QE authentication data) padded with zeros
+    let mut hasher = Sha256::new();
+    hasher.update(attestation_key);
+    hasher.update(&qe.qe_auth_data);
+    let expected = hasher.finalize();
+
+    let report_data = &qe.qe_report.report_data;
+    if report_data[0..32] != expected[..] || report_data[32..].iter().any(|b| *b != 0) {
+        return Err(anyhow!(
+            "[verify_qe_report_data_binding] QE report data does not bind the attestation key"
+        ));
+    }
+    Ok(())
+}
+
+fn verify_qe_report_signature(
+    qe: &QeReportCertificationData,
+    chain: &[Certificate],
+) -> Result<(), anyhow::Error> {
+    let pck_cert = match chain.first() {
+        Some(c) => c,
+        None => {
+            return Err(anyhow!(
+                "[verify_qe_report_signature] Empty PCK certificate chain"
+            ))
+        }
+    };
+    let pck_key = cert_verifying_key(pck_cert)?;
+    let signature = Signature::from_slice(&qe.qe_report_signature)
+        .map_err(|e| anyhow!("[verify_qe_report_signature] Malformed signature: {:?}", e))?;
+
+    pck_key
+        .verify(&qe.qe_report.to_bytes(), &signature)
+        .map_err(|_| {
+            anyhow!("[verify_qe_report_signature] QE report signature does not match PCK key")
+        })
+}
+
+fn verify_cert_chain(
+    chain: &[Certificate],
+    root_ca: &Certificate,
+    time: SystemTime,
+) -> Result<(), anyhow::Error> {
+    if chain.is_empty() {
+        return Err(anyhow!("[verify_cert_chain] Empty PCK certificate chain"));
+    }
+
+    for (i, cert) in chain.iter().enumerate() {
+        check_validity(cert, time)?;
+        //the top of the supplied chain is checked against the trusted root below
+        if let Some(issuer) = chain.get(i + 1) {
+            verify_cert_signature(cert, issuer)?;
+        }
+    }
+
+    check_validity(root_ca, time)?;
+    let top = &chain[chain.len() - 1];
+    let root_der = root_ca.to_der()?;
+    if top.to_der()? == root_der {
+        verify_cert_signature(root_ca, root_ca)
+    } else {
+        verify_cert_signature(top, root_ca)
+    }
+}
+
+fn verify_cert_signature(cert: &Certificate, issuer: &Certificate) -> Result<(), anyhow::Error> {
+    check_ca(issuer)?;
+    let subject = cert.tbs_certificate.subject.to_string();
+    if cert.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return Err(anyhow!(
+            "[verify_cert_signature] Certificate '{}' is not issued by '{}'",
+            subject,
+            issuer.tbs_certificate.subject
+        ));
+    }
+    if cert.signature_algorithm.oid != ECDSA_WITH_SHA_256 {
+        return Err(anyhow!(
+            "[verify_cert_signature] Unsupported signature algorithm {} on '{}'",
+            cert.signature_algorithm.oid,
+            subject
+        ));
+    }
+
+    let issuer_key = cert_verifying_key(issuer)?;
+    let signature = Signature::from_der(cert.signature.raw_bytes()).map_err(|e| {
+        anyhow!(
+            "[verify_cert_signature] Malformed signature on '{}': {:?}",
+            subject,
+            e
+        )
+    })?;
+    let tbs = cert.tbs_certificate.to_der()?;
+
+    issuer_key.verify(&tbs, &signature).map_err(|_| {
+        anyhow!(
+            "[verify_cert_signature] Signature on '{}' does not match issuer key",
+            subject
+        )
+    })
+}
+
+//only CA certificates allowed to sign certificates may issue the next one in the chain
+fn check_ca(cert: &Certificate) -> Result<(), anyhow::Error> {
+    let subject = &cert.tbs_certificate.subject;
+    let is_ca = match cert.tbs_certificate.get::<BasicConstraints>() {
+        Err(e) => {
+            return Err(anyhow!(
+                "[check_ca] Malformed basic constraints on '{}': {:?}",
+                subject,
+                e
+            ))
+        }
+        Ok(constraints) => matches!(constraints, Some((_, c)) if c.ca),
+    };
+    if !is_ca {
+        return Err(anyhow!(
+            "[check_ca] Issuer '{}' is not a CA certificate",
+            subject
+        ));
+    }
+    let can_sign = match cert.tbs_certificate.get::<KeyUsage>() {
+        Err(e) => {
+            return Err(anyhow!(
+                "[check_ca] Malformed key usage on '{}': {:?}",
+                subject,
+                e
+            ))
+        }
+        Ok(usage) => matches!(usage, Some((_, u)) if u.key_cert_sign()),
+    };
+    if !can_sign {
+        return Err(anyhow!(
+            "[check_ca] Issuer '{}' is not allowed to sign certificates",
+            subject
+        ));
+    }
+    Ok(())
+}
+
+fn check_validity(cert: &Certificate, time: SystemTime) -> Result<(), anyhow::Error> {
+    let validity = &cert.tbs_certificate.validity;
+    if time < validity.not_before.to_system_time() || time > validity.not_after.to_system_time() {
+        return Err(anyhow!(
+            "[check_validity] Certificate '{}' is not valid at the verification time",
+            cert.tbs_certificate.subject
+        ));
+    }
+    Ok(())
+}
+
+fn cert_verifying_key(cert: &Certificate) -> Result<VerifyingKey, anyhow::Error> {
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    VerifyingKey::from_sec1_bytes(spki.subject_public_key.raw_bytes()).map_err(|e| {
+        anyhow!(
+            "[cert_verifying_key] Certificate '{}' has no P-256 public key: {:?}",
+            cert.tbs_certificate.subject,
+            e
+        )
+    })
+}
+
+//quote attestation keys are raw x || y coordinates without the SEC1 tag
+fn raw_verifying_key(key: &[u8; 64]) -> Result<VerifyingKey, anyhow::Error> {
+    let mut sec1 = [0u8; 65];
+    sec1[0] = 0x04;
+    sec1[1..].copy_from_slice(key);
+    VerifyingKey::from_sec1_bytes(&sec1)
+        .map_err(|e| anyhow!("[raw_verifying_key] Malformed attestation key: {:?}", e))
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use super::*;
+    use crate::device::TdxDevice;
+    use crate::simulator::TdxSimulator;
+
+    const ROOT_CA: &str = include_str!("../testdata/root_ca.pem");
+    const UNTRUSTED_ROOT_CA: &str = include_str!("../testdata/untrusted_root_ca.pem");
+    //a certificate signed by the PCK leaf key, followed by the test PCK chain
+    const LEAF_ISSUER_CERT_CHAIN: &str = include_str!("../testdata/leaf_issuer_cert_chain.pem");
+
+    //a v4 quote signed with the test attestation key and test PCK key
+    fn signed_quote() -> Vec<u8> {
+        TdxSimulator::default().get_quote(&[0x22; 64]).unwrap()
+    }
+
+    #[test]
+    //a quote signed by the test keys verifies against the test root CA
+    fn verify_quote_valid() {
+        let result = verify_quote(&signed_quote(), ROOT_CA.as_bytes()).unwrap();
+        assert_eq!(result.attestation_key_signature, VerificationStatus::Valid);
+        assert_eq!(result.qe_report_signature, VerificationStatus::Valid);
+        assert_eq!(result.qe_report_data_binding, VerificationStatus::Valid);
+        assert_eq!(result.pck_cert_chain, VerificationStatus::Valid);
+        assert!(result.is_valid());
+    }
+
+    #[test]
+    //tampering with the quote body breaks only the attestation key signature
+    fn verify_quote_tampered_body() {
+        let mut quote = signed_quote();
+        quote[568] ^= 0xff;
+        let result = verify_quote(&quote, ROOT_CA.as_bytes()).unwrap();
+        assert!(!result.attestation_key_signature.is_valid());
+        assert!(result.qe_report_signature.is_valid());
+        assert!(result.pck_cert_chain.is_valid());
+        assert!(!result.is_valid());
+    }
+
+    #[test]
+    //tampering with the QE authentication data breaks the attestation key binding
+    fn verify_quote_tampered_qe_auth_data() {
+        let mut quote = signed_quote();
+        //QE authentication data follows the QE report, its signature and its size
+        let offset = 636 + 128 + 6 + 384 + 64 + 2;
+        quote[offset] ^= 0xff;
+        let result = verify_quote(&quote, ROOT_CA.as_bytes()).unwrap();
+        assert!(result.attestation_key_signature.is_valid());
+        assert!(!result.qe_report_data_binding.is_valid());
+    }
+
+    #[test]
+    //tampering with the QE report breaks the PCK signature over it
+    fn verify_quote_tampered_qe_report() {
+        let mut quote = signed_quote();
+        quote[636 + 128 + 6] ^= 0xff;
+        let result = verify_quote(&quote, ROOT_CA.as_bytes()).unwrap();
+        assert!(!result.qe_report_signature.is_valid());
+        assert!(result.qe_report_data_binding.is_valid());
+    }
+
+    #[test]
+    //a PCK chain that does not lead to the supplied root CA is rejected
+    fn verify_quote_untrusted_root() {
+        let result = verify_quote(&signed_quote(), UNTRUSTED_ROOT_CA.as_bytes()).unwrap();
+        assert!(!result.pck_cert_chain.is_valid());
+        assert!(result.attestation_key_signature.is_valid());
+        assert!(!result.is_valid());
+    }
+
+    #[test]
+    //certificates are checked against the verification time
+    fn verify_quote_expired_chain() {
+        let result =
+            verify_quote_at(&signed_quote(), ROOT_CA.as_bytes(), SystemTime::UNIX_EPOCH).unwrap();
+        assert!(!result.pck_cert_chain.is_valid());
+    }
+
+    #[test]
+    //malformed quotes and root CAs are reported as errors
+    fn verify_quote_malformed_input() {
+        let quote = signed_quote();
+        assert!(verify_quote(&quote[0..600], ROOT_CA.as_bytes()).is_err());
+        assert!(verify_quote(&quote, b"not a certificate").is_err());
+    }
+
+    #[test]
+    //a leaf certificate cannot issue certificates even when its signature matches
+    fn verify_cert_chain_leaf_issuer() {
+        let root_ca = Certificate::from_pem(ROOT_CA).unwrap();
+        let chain = Certificate::load_pem_chain(LEAF_ISSUER_CERT_CHAIN.as_bytes()).unwrap();
+        assert!(verify_cert_chain(&chain[1..], &root_ca, SystemTime::now()).is_ok());
+
+        let e = verify_cert_chain(&chain, &root_ca, SystemTime::now()).unwrap_err();
+        assert!(e.to_string().contains("is not a CA certificate"));
+    }
+}